@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS Clench_Shields (
+    uid bigint REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint REFERENCES Chats(id) ON DELETE CASCADE,
+    attempted_at timestamptz NOT NULL DEFAULT current_timestamp,
+    shield_expires_at timestamptz,
+
+    PRIMARY KEY (uid, chat_id)
+);
+
+COMMENT ON COLUMN Clench_Shields.attempted_at      IS 'The time of the last /clench attempt, successful or not; used to enforce the cooldown';
+COMMENT ON COLUMN Clench_Shields.shield_expires_at IS 'NULL if there is no active shield; reset to NULL when the shield is consumed by a battle';
@@ -0,0 +1,45 @@
+DO $$ BEGIN
+    CREATE TYPE change_source AS ENUM (
+        'treatment',
+        'battle',
+        'hemoroid_of_day',
+        'promo',
+        'loan',
+        'perk'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Treatment_History (
+    id bigserial PRIMARY KEY,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    delta int NOT NULL,
+    source change_source NOT NULL,
+    reference_id varchar,
+    created_at timestamptz NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_treatment_history_uid_chat_id ON Treatment_History(uid, chat_id, created_at DESC);
+
+COMMENT ON TABLE  Treatment_History              IS 'An append-only ledger of every change of Hemoroids.protrusion_level';
+COMMENT ON COLUMN Treatment_History.reference_id IS 'Source specific: the opponent for battles, the code for promo codes, the loan id for loans, the perk name for perks';
+
+CREATE OR REPLACE FUNCTION forbid_treatment_history_updates()
+    RETURNS TRIGGER
+    LANGUAGE PLPGSQL
+AS $$
+BEGIN
+    -- only the chat may be changed, when chats are merged
+    IF (NEW.id, NEW.uid, NEW.delta, NEW.source, NEW.reference_id, NEW.created_at)
+            IS DISTINCT FROM (OLD.id, OLD.uid, OLD.delta, OLD.source, OLD.reference_id, OLD.created_at) THEN
+        RAISE EXCEPTION 'Updates of the Treatment_History table is forbidden!'
+            USING ERRCODE = 'GD1E2';
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+CREATE OR REPLACE TRIGGER trg_forbid_treatment_history_updates BEFORE UPDATE ON Treatment_History
+    FOR EACH ROW EXECUTE FUNCTION forbid_treatment_history_updates();
@@ -0,0 +1,22 @@
+-- Protrusion levels and all the values derived from them are stored as fixed-point tenths of a centimetre.
+-- The timestamp trigger is disabled to keep the treatment dates and to not hit the "already treated today" check.
+ALTER TABLE Hemoroids DISABLE TRIGGER trg_check_and_update_hemoroids_timestamp;
+UPDATE Hemoroids SET protrusion_level = protrusion_level * 10;
+ALTER TABLE Hemoroids ENABLE TRIGGER trg_check_and_update_hemoroids_timestamp;
+COMMENT ON COLUMN Hemoroids.protrusion_level IS 'in tenths of a centimetre';
+
+UPDATE Battle_Stats SET acquired_length = acquired_length * 10, lost_length = lost_length * 10;
+COMMENT ON COLUMN Battle_Stats.acquired_length IS 'in tenths of a centimetre';
+COMMENT ON COLUMN Battle_Stats.lost_length IS 'in tenths of a centimetre';
+
+-- repaid loans are skipped, otherwise the trigger would overwrite their repayment dates
+UPDATE Loans SET debt = debt * 10 WHERE repaid_at IS NULL;
+COMMENT ON COLUMN Loans.debt IS 'in tenths of a centimetre';
+
+UPDATE Promo_Codes SET bonus_length = bonus_length * 10;
+COMMENT ON COLUMN Promo_Codes.bonus_length IS 'in tenths of a centimetre';
+
+ALTER TABLE Treatment_History DISABLE TRIGGER trg_forbid_treatment_history_updates;
+UPDATE Treatment_History SET delta = delta * 10;
+ALTER TABLE Treatment_History ENABLE TRIGGER trg_forbid_treatment_history_updates;
+COMMENT ON COLUMN Treatment_History.delta IS 'in tenths of a centimetre';
@@ -0,0 +1,50 @@
+-- The daily reset happens at the local midnight of a chat
+ALTER TABLE Chats ADD COLUMN IF NOT EXISTS timezone varchar(64) NOT NULL DEFAULT 'UTC';
+COMMENT ON COLUMN Chats.timezone IS 'A name from pg_timezone_names, set by the administrators of the chat';
+
+CREATE OR REPLACE FUNCTION chat_local_date(chat_id_internal bigint, ts timestamptz)
+    RETURNS date
+    LANGUAGE SQL
+    STABLE
+AS $$
+    SELECT (ts AT TIME ZONE coalesce((SELECT timezone FROM Chats WHERE id = chat_id_internal), 'UTC'))::date
+$$;
+
+CREATE OR REPLACE FUNCTION check_and_update_hemoroids_timestamp()
+    RETURNS TRIGGER
+    LANGUAGE PLPGSQL
+AS $$
+BEGIN
+    IF chat_local_date(NEW.chat_id, current_timestamp) = chat_local_date(OLD.chat_id, OLD.updated_at) AND NEW.bonus_attempts = 0 THEN
+        RAISE EXCEPTION 'You have already applied treatment to your hemorrhoid today!'
+            USING ERRCODE = 'GD0E1';
+    END IF;
+
+    IF NEW.bonus_attempts > 0 THEN
+        NEW.bonus_attempts := NEW.bonus_attempts - 1;
+    END IF;
+
+    RETURN NEW;
+END
+$$;
+
+CREATE OR REPLACE FUNCTION check_dod_timestamp()
+    RETURNS TRIGGER
+    LANGUAGE PLPGSQL
+AS $$
+DECLARE
+    hod_name varchar;
+    local_date date := chat_local_date(NEW.chat_id, current_timestamp);
+BEGIN
+    SELECT name INTO hod_name FROM Hemoroid_of_Day hod
+        JOIN Users u ON hod.lowest_uid = u.uid
+        WHERE hod.created_at = local_date AND hod.chat_id = NEW.chat_id;
+    IF hod_name IS NOT NULL THEN
+        RAISE EXCEPTION '%', hod_name
+            USING ERRCODE = 'GD0E2';
+    END IF;
+
+    NEW.created_at := local_date;
+    RETURN NEW;
+END
+$$;
@@ -0,0 +1,48 @@
+-- Streaks of consecutive days of treatment, counted in the local time of a chat
+ALTER TABLE Hemoroids ADD COLUMN IF NOT EXISTS streak_current integer NOT NULL DEFAULT 0;
+ALTER TABLE Hemoroids ADD COLUMN IF NOT EXISTS streak_max integer NOT NULL DEFAULT 0;
+
+-- everyone has been treated at least once
+ALTER TABLE Hemoroids DISABLE TRIGGER trg_check_and_update_hemoroids_timestamp;
+UPDATE Hemoroids SET streak_current = 1, streak_max = 1;
+ALTER TABLE Hemoroids ENABLE TRIGGER trg_check_and_update_hemoroids_timestamp;
+
+CREATE OR REPLACE FUNCTION check_and_update_hemoroids_timestamp()
+    RETURNS TRIGGER
+    LANGUAGE PLPGSQL
+AS $$
+DECLARE
+    today date := chat_local_date(NEW.chat_id, current_timestamp);
+BEGIN
+    IF today = chat_local_date(OLD.chat_id, OLD.updated_at) AND NEW.bonus_attempts = 0 THEN
+        RAISE EXCEPTION 'You have already applied treatment to your hemorrhoid today!'
+            USING ERRCODE = 'GD0E1';
+    END IF;
+
+    IF NEW.bonus_attempts > 0 THEN
+        NEW.bonus_attempts := NEW.bonus_attempts - 1;
+    ELSE
+        -- a regular daily treatment either continues the streak or starts a new one
+        IF chat_local_date(OLD.chat_id, OLD.updated_at) = today - 1 THEN
+            NEW.streak_current := OLD.streak_current + 1;
+        ELSE
+            NEW.streak_current := 1;
+        END IF;
+        NEW.streak_max := greatest(NEW.streak_current, OLD.streak_max, 0);
+    END IF;
+
+    RETURN NEW;
+END
+$$;
+
+-- The stored streak_current isn't reset until the next treatment, so it must be checked for staleness on reading
+CREATE OR REPLACE FUNCTION actual_streak(chat_id_internal bigint, treated_at timestamptz, streak integer)
+    RETURNS integer
+    LANGUAGE SQL
+    STABLE
+AS $$
+    SELECT CASE
+        WHEN chat_local_date(chat_id_internal, treated_at) >= chat_local_date(chat_id_internal, current_timestamp) - 1 THEN streak
+        ELSE 0
+    END
+$$;
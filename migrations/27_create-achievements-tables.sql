@@ -0,0 +1,40 @@
+DO $$ BEGIN
+    CREATE TYPE achievement_rule AS ENUM (
+        'battle_win_streak',
+        'hod_wins',
+        'level_at_most',
+        'treatment_streak',
+        'loans_repaid',
+        'promo_codes'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Achievements (
+    code varchar(32) PRIMARY KEY,
+    rule achievement_rule NOT NULL,
+    threshold integer NOT NULL
+);
+
+COMMENT ON TABLE  Achievements           IS 'Definitions of achievements; the names and descriptions are in the locale files under the achievements.<code> keys';
+COMMENT ON COLUMN Achievements.threshold IS 'The value the metric of the rule must reach (or not exceed in case of level_at_most, which is measured in tenths of a centimetre)';
+
+CREATE TABLE IF NOT EXISTS Unlocked_Achievements (
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    code varchar(32) NOT NULL REFERENCES Achievements(code) ON DELETE CASCADE,
+    unlocked_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (uid, chat_id, code)
+);
+
+INSERT INTO Achievements (code, rule, threshold) VALUES
+    ('win_streak_10', 'battle_win_streak', 10),
+    ('hod_3', 'hod_wins', 3),
+    ('zero_level', 'level_at_most', 0),
+    ('treatment_streak_7', 'treatment_streak', 7),
+    ('treatment_streak_30', 'treatment_streak', 30),
+    ('loan_repaid', 'loans_repaid', 1),
+    ('promo_code', 'promo_codes', 1)
+ON CONFLICT (code) DO NOTHING;
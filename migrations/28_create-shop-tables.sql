@@ -0,0 +1,26 @@
+ALTER TYPE change_source ADD VALUE IF NOT EXISTS 'shop';
+
+CREATE TABLE IF NOT EXISTS Wallets (
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    coins integer NOT NULL DEFAULT 0 CHECK (coins >= 0),
+
+    PRIMARY KEY (uid, chat_id)
+);
+
+COMMENT ON TABLE Wallets IS 'Coins earned in battles, which can be spent in the medical supply shop';
+
+CREATE TABLE IF NOT EXISTS Purchases (
+    id bigserial PRIMARY KEY,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    item varchar(32) NOT NULL,
+    price_coins integer NOT NULL DEFAULT 0,
+    price_level integer NOT NULL DEFAULT 0,
+    purchased_at timestamptz NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_purchases_uid_chat_id ON Purchases(uid, chat_id);
+
+COMMENT ON COLUMN Purchases.item        IS 'The code of an item from the SHOP_ITEMS configuration';
+COMMENT ON COLUMN Purchases.price_level IS 'The increase of the protrusion level paid for the item, in tenths of a centimetre';
@@ -0,0 +1,37 @@
+ALTER TYPE change_source ADD VALUE IF NOT EXISTS 'item';
+
+CREATE TABLE IF NOT EXISTS Inventory (
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    item varchar(32) NOT NULL,
+    quantity integer NOT NULL CHECK (quantity >= 0),
+
+    PRIMARY KEY (uid, chat_id, item)
+);
+
+COMMENT ON COLUMN Inventory.item IS 'The code of an item from the SHOP_ITEMS configuration';
+
+DO $$ BEGIN
+    CREATE TYPE effect_target AS ENUM (
+        'treatment',
+        'battle'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Active_Effects (
+    id bigserial PRIMARY KEY,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    item varchar(32) NOT NULL,
+    target effect_target NOT NULL,
+    value integer NOT NULL,
+    charges_left integer NOT NULL CHECK (charges_left >= 0),
+    expires_at timestamptz NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_active_effects_uid_chat_id ON Active_Effects(uid, chat_id);
+
+COMMENT ON TABLE  Active_Effects       IS 'Effects of the used consumable items; every treatment or battle takes one charge of the matching effects';
+COMMENT ON COLUMN Active_Effects.value IS 'In tenths of a centimetre';
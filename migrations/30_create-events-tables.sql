@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS Events (
+    id serial PRIMARY KEY,
+    code varchar(32) NOT NULL UNIQUE,
+    starts_at timestamptz NOT NULL,
+    ends_at timestamptz NOT NULL CHECK (ends_at > starts_at),
+    shrink_multiplier real NOT NULL DEFAULT 1 CHECK (shrink_multiplier >= 0),
+    battle_damage_multiplier real NOT NULL DEFAULT 1 CHECK (battle_damage_multiplier >= 0),
+    hod_bonus_multiplier real NOT NULL DEFAULT 1 CHECK (hod_bonus_multiplier >= 0)
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_period ON Events(starts_at, ends_at);
+
+COMMENT ON TABLE  Events                          IS 'Global timed events which modify the gameplay in all chats';
+COMMENT ON COLUMN Events.shrink_multiplier        IS 'Applied to the base improvement of a daily treatment';
+COMMENT ON COLUMN Events.battle_damage_multiplier IS 'Applied to the swelling of the participants of a battle';
+COMMENT ON COLUMN Events.hod_bonus_multiplier     IS 'Applied to the base bonus of the Hemorrhoid of the Day';
+
+CREATE TABLE IF NOT EXISTS Event_Titles (
+    event_id integer NOT NULL REFERENCES Events(id) ON DELETE CASCADE,
+    language varchar(2) NOT NULL,
+    title text NOT NULL,
+
+    PRIMARY KEY (event_id, language)
+);
+
+COMMENT ON COLUMN Event_Titles.language IS 'A two-letter language code; the English title is used as a fallback';
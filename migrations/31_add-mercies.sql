@@ -0,0 +1,18 @@
+ALTER TYPE change_source ADD VALUE IF NOT EXISTS 'mercy';
+
+ALTER TABLE Battle_Stats ADD COLUMN IF NOT EXISTS mercies_shown int NOT NULL DEFAULT 0 CHECK ( mercies_shown >= 0 );
+
+CREATE TABLE IF NOT EXISTS Mercies (
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    winner_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    loser_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    battle_timestamp bigint NOT NULL,
+    amount integer NOT NULL CHECK ( amount > 0 ),
+    shown_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (chat_id, winner_uid, loser_uid, battle_timestamp)
+);
+
+COMMENT ON TABLE  Mercies                  IS 'Advantages returned by the winners of battles to the losers';
+COMMENT ON COLUMN Mercies.battle_timestamp IS 'The timestamp from the callback data of the battle, used to show mercy only once per battle';
+COMMENT ON COLUMN Mercies.amount           IS 'In tenths of a centimetre';
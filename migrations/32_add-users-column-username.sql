@@ -0,0 +1,5 @@
+ALTER TABLE Users ADD COLUMN IF NOT EXISTS username varchar(32);
+
+CREATE INDEX IF NOT EXISTS idx_users_username ON Users(lower(username));
+
+COMMENT ON COLUMN Users.username IS 'The public @username without the at sign, used to resolve mentions';
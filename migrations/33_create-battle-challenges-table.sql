@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS Battle_Challenges (
+    id serial PRIMARY KEY,
+    chat_id bigint,
+    message_id integer,
+    inline_message_id varchar(255) UNIQUE,
+    language varchar(16) NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    UNIQUE (chat_id, message_id),
+    CHECK ((chat_id IS NOT NULL AND message_id IS NOT NULL) <> (inline_message_id IS NOT NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_battle_challenges_created_at ON Battle_Challenges(created_at);
+
+COMMENT ON TABLE  Battle_Challenges          IS 'Messages with open challenges which must be marked as expired after a while';
+COMMENT ON COLUMN Battle_Challenges.chat_id  IS 'A Telegram identifier of the chat, not a reference to the Chats table';
+COMMENT ON COLUMN Battle_Challenges.language IS 'The language of the initiator, used to write the expiration notice';
@@ -0,0 +1,51 @@
+ALTER TYPE change_source ADD VALUE IF NOT EXISTS 'tournament';
+
+DO $$ BEGIN
+    CREATE TYPE tournament_status AS ENUM ('registration', 'running', 'finished', 'cancelled');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Tournaments (
+    id serial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    tg_chat_id bigint NOT NULL,
+    message_id integer,
+    language varchar(16) NOT NULL,
+    bet smallint NOT NULL CHECK ( bet > 0 ),
+    prize integer NOT NULL CHECK ( prize >= 0 ),
+    status tournament_status NOT NULL DEFAULT 'registration',
+    round smallint NOT NULL DEFAULT 0,
+    registration_ends_at timestamptz NOT NULL,
+    winner_uid bigint REFERENCES Users(uid) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT current_timestamp
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tournaments_active ON Tournaments(chat_id) WHERE status IN ('registration', 'running');
+
+COMMENT ON TABLE  Tournaments            IS 'Single-elimination tournaments opened by the administrators of chats';
+COMMENT ON COLUMN Tournaments.tg_chat_id IS 'A Telegram identifier of the chat, used along with message_id to edit the bracket';
+COMMENT ON COLUMN Tournaments.bet        IS 'In whole centimetres, like the bets of usual battles';
+COMMENT ON COLUMN Tournaments.prize      IS 'In tenths of a centimetre, subtracted from the protrusion level of the winner';
+COMMENT ON COLUMN Tournaments.round      IS 'The current round, starting from 1; 0 while the registration is open';
+
+CREATE TABLE IF NOT EXISTS Tournament_Players (
+    tournament_id integer NOT NULL REFERENCES Tournaments(id) ON DELETE CASCADE,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    joined_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (tournament_id, uid)
+);
+
+CREATE TABLE IF NOT EXISTS Tournament_Matches (
+    tournament_id integer NOT NULL REFERENCES Tournaments(id) ON DELETE CASCADE,
+    round smallint NOT NULL,
+    position smallint NOT NULL,
+    player1_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    player2_uid bigint REFERENCES Users(uid) ON DELETE CASCADE,
+    winner_uid bigint REFERENCES Users(uid) ON DELETE CASCADE,
+
+    PRIMARY KEY (tournament_id, round, position)
+);
+
+COMMENT ON COLUMN Tournament_Matches.player2_uid IS 'NULL means a bye: the first player advances without a battle';
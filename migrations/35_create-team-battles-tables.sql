@@ -0,0 +1,31 @@
+ALTER TABLE Battle_Stats ADD COLUMN IF NOT EXISTS team_battles_total int NOT NULL DEFAULT 0 CHECK ( team_battles_total >= 0 );
+ALTER TABLE Battle_Stats ADD COLUMN IF NOT EXISTS team_battles_won int NOT NULL DEFAULT 0 CHECK ( team_battles_won >= 0 );
+
+DO $$ BEGIN
+    CREATE TYPE battle_team AS ENUM ('a', 'b');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Team_Battles (
+    id serial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    initiator_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    team_size smallint NOT NULL CHECK ( team_size > 1 ),
+    bet smallint NOT NULL CHECK ( bet > 0 ),
+    created_at timestamptz NOT NULL DEFAULT current_timestamp,
+    started_at timestamptz
+);
+
+COMMENT ON TABLE  Team_Battles            IS 'Lobbies of team battles; the battle is fought as soon as both teams are full';
+COMMENT ON COLUMN Team_Battles.bet        IS 'In whole centimetres, like the bets of usual battles';
+COMMENT ON COLUMN Team_Battles.started_at IS 'Set when the last member joins, so the battle is fought only once';
+
+CREATE TABLE IF NOT EXISTS Team_Battle_Members (
+    battle_id integer NOT NULL REFERENCES Team_Battles(id) ON DELETE CASCADE,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    team battle_team NOT NULL,
+    joined_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (battle_id, uid)
+);
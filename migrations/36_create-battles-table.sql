@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS Battles (
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    initiator_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    top_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    bottom_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    top_damage integer NOT NULL,
+    bottom_damage integer NOT NULL,
+    bet smallint NOT NULL CHECK ( bet > 0 ),
+    winner_uid bigint NOT NULL,
+    fought_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    CHECK ( top_uid <> bottom_uid ),
+    CHECK ( winner_uid IN (top_uid, bottom_uid) ),
+    CHECK ( initiator_uid IN (top_uid, bottom_uid) )
+);
+
+CREATE INDEX IF NOT EXISTS idx_battles_top ON Battles(chat_id, top_uid, fought_at);
+CREATE INDEX IF NOT EXISTS idx_battles_bottom ON Battles(chat_id, bottom_uid, fought_at);
+
+COMMENT ON TABLE  Battles               IS 'The log of every battle fought, used to investigate disputes';
+COMMENT ON COLUMN Battles.top_damage    IS 'In tenths of a centimetre, after all shields and cushions were applied';
+COMMENT ON COLUMN Battles.bottom_damage IS 'In tenths of a centimetre, after all shields and cushions were applied';
+COMMENT ON COLUMN Battles.bet           IS 'In whole centimetres';
@@ -0,0 +1,5 @@
+ALTER TABLE Battle_Stats ADD COLUMN IF NOT EXISTS rating int NOT NULL DEFAULT 1000;
+
+CREATE INDEX IF NOT EXISTS idx_battle_stats_rating ON Battle_Stats(chat_id, rating DESC);
+
+COMMENT ON COLUMN Battle_Stats.rating IS 'Elo rating of the user in the chat, updated after every solo battle';
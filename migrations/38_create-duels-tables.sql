@@ -0,0 +1,56 @@
+DO $$ BEGIN
+    CREATE TYPE duel_status AS ENUM ('open', 'running', 'finished', 'cancelled');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+DO $$ BEGIN
+    CREATE TYPE duel_action AS ENUM ('push', 'clench', 'dodge');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Duels (
+    id serial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    tg_chat_id bigint NOT NULL,
+    message_id integer,
+    language varchar(16) NOT NULL,
+    initiator_uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    acceptor_uid bigint REFERENCES Users(uid) ON DELETE CASCADE,
+    best_of smallint NOT NULL CHECK ( best_of IN (3, 5) ),
+    bet smallint NOT NULL CHECK ( bet > 0 ),
+    status duel_status NOT NULL DEFAULT 'open',
+    round smallint NOT NULL DEFAULT 0,
+    initiator_action duel_action,
+    acceptor_action duel_action,
+    initiator_wins smallint NOT NULL DEFAULT 0,
+    acceptor_wins smallint NOT NULL DEFAULT 0,
+    initiator_damage integer NOT NULL DEFAULT 0,
+    acceptor_damage integer NOT NULL DEFAULT 0,
+    round_started_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    CHECK ( acceptor_uid <> initiator_uid )
+);
+
+CREATE INDEX IF NOT EXISTS idx_duels_running ON Duels(round_started_at) WHERE status = 'running';
+
+COMMENT ON TABLE  Duels                  IS 'Multi-round battles where both players pick an action every round';
+COMMENT ON COLUMN Duels.tg_chat_id       IS 'A Telegram identifier of the chat, used along with message_id to edit the duel after timeouts';
+COMMENT ON COLUMN Duels.round            IS 'The current round, starting from 1; 0 while the duel is not accepted';
+COMMENT ON COLUMN Duels.initiator_action IS 'The action picked in the current round, NULL until the player responds';
+COMMENT ON COLUMN Duels.initiator_damage IS 'In tenths of a centimetre, accumulated over the rounds and applied when the duel is over';
+
+CREATE TABLE IF NOT EXISTS Duel_Rounds (
+    duel_id integer NOT NULL REFERENCES Duels(id) ON DELETE CASCADE,
+    round smallint NOT NULL,
+    initiator_action duel_action,
+    acceptor_action duel_action,
+    winner_uid bigint REFERENCES Users(uid) ON DELETE CASCADE,
+
+    PRIMARY KEY (duel_id, round)
+);
+
+COMMENT ON COLUMN Duel_Rounds.initiator_action IS 'NULL means the player did not respond in time and forfeited the round';
+COMMENT ON COLUMN Duel_Rounds.winner_uid       IS 'NULL means a draw';
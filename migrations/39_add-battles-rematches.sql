@@ -0,0 +1,9 @@
+ALTER TABLE Battles ADD COLUMN IF NOT EXISTS rematch_of bigint REFERENCES Battles(id) ON DELETE SET NULL;
+ALTER TABLE Battles ADD COLUMN IF NOT EXISTS series_id bigint REFERENCES Battles(id) ON DELETE SET NULL;
+ALTER TABLE Battles ADD COLUMN IF NOT EXISTS rematch smallint NOT NULL DEFAULT 0 CHECK ( rematch >= 0 );
+
+CREATE INDEX IF NOT EXISTS idx_battles_series ON Battles(series_id) WHERE series_id IS NOT NULL;
+
+COMMENT ON COLUMN Battles.rematch_of IS 'The previous battle of the same pair, if this one was started with a rematch button';
+COMMENT ON COLUMN Battles.series_id  IS 'The first battle of the chain of rematches; NULL for the first battle itself';
+COMMENT ON COLUMN Battles.rematch    IS 'The number of the rematch in the chain, 0 for the first battle';
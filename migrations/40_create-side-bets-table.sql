@@ -0,0 +1,24 @@
+ALTER TYPE change_source ADD VALUE IF NOT EXISTS 'side_bet';
+
+DO $$ BEGIN
+    CREATE TYPE side_bet_side AS ENUM ('initiator', 'acceptor');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS Side_Bets (
+    chat_id bigint NOT NULL REFERENCES Chats(id) ON DELETE CASCADE,
+    challenge varchar(64) NOT NULL,
+    uid bigint NOT NULL REFERENCES Users(uid) ON DELETE CASCADE,
+    side side_bet_side NOT NULL,
+    amount integer NOT NULL CHECK ( amount > 0 ),
+    payout integer,
+    placed_at timestamptz NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (chat_id, challenge, uid)
+);
+
+COMMENT ON TABLE  Side_Bets           IS 'Bets of the spectators on the participants of open battle challenges';
+COMMENT ON COLUMN Side_Bets.challenge IS 'The initiator and the timestamp of the challenge, separated by a colon';
+COMMENT ON COLUMN Side_Bets.amount    IS 'In tenths of a centimetre';
+COMMENT ON COLUMN Side_Bets.payout    IS 'In tenths of a centimetre, subtracted from the protrusion level of the spectator; NULL until the battle is fought';
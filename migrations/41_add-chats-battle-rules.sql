@@ -0,0 +1,8 @@
+DO $$ BEGIN
+    CREATE TYPE battle_rules AS ENUM ('top_bottom', 'classic');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+ALTER TABLE Chats ADD COLUMN IF NOT EXISTS battle_rules battle_rules NOT NULL DEFAULT 'top_bottom';
+COMMENT ON COLUMN Chats.battle_rules IS 'The rule set of the battles in the chat, chosen by the administrators';
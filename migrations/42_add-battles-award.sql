@@ -0,0 +1,7 @@
+ALTER TABLE Battles ADD COLUMN IF NOT EXISTS award integer NOT NULL DEFAULT 0 CHECK ( award >= 0 );
+ALTER TABLE Mercies ADD COLUMN IF NOT EXISTS battle_id bigint REFERENCES Battles(id) ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_mercies_battle ON Mercies(battle_id) WHERE battle_id IS NOT NULL;
+
+COMMENT ON COLUMN Battles.award    IS 'The part of the bet actually moved to the loser, in tenths of a centimetre';
+COMMENT ON COLUMN Mercies.battle_id IS 'The battle the mercy was shown for; NULL for the mercies shown before the battles had their awards recorded';
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS Callback_Locks (
+    key text PRIMARY KEY,
+    token bigint NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+
+COMMENT ON TABLE  Callback_Locks            IS 'Challenges being accepted right now, shared by all replicas of the bot';
+COMMENT ON COLUMN Callback_Locks.token      IS 'Identifies the holder, so an expired lock taken by someone else is not released by mistake';
+COMMENT ON COLUMN Callback_Locks.expires_at IS 'The lock can be taken over after this moment, e.g. if its holder has died';
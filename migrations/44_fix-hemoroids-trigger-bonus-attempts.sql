@@ -0,0 +1,31 @@
+-- The bonus attempts given by the items used to be consumed by the first treatment of a day instead of the daily attempt,
+-- which didn't continue the streak. Now they are consumed only once the daily attempt has been spent.
+CREATE OR REPLACE FUNCTION check_and_update_hemoroids_timestamp()
+    RETURNS TRIGGER
+    LANGUAGE PLPGSQL
+AS $$
+DECLARE
+    today date := chat_local_date(NEW.chat_id, current_timestamp);
+BEGIN
+    IF NEW.bonus_attempts > OLD.bonus_attempts THEN
+        -- not a treatment: battles, loans, promo codes, items, etc. add an attempt to pass through, which is taken back here
+        NEW.bonus_attempts := NEW.bonus_attempts - 1;
+    ELSIF today = chat_local_date(OLD.chat_id, OLD.updated_at) THEN
+        IF NEW.bonus_attempts = 0 THEN
+            RAISE EXCEPTION 'You have already applied treatment to your hemorrhoid today!'
+                USING ERRCODE = 'GD0E1';
+        END IF;
+        NEW.bonus_attempts := NEW.bonus_attempts - 1;
+    ELSE
+        -- a regular daily treatment either continues the streak or starts a new one
+        IF chat_local_date(OLD.chat_id, OLD.updated_at) = today - 1 THEN
+            NEW.streak_current := OLD.streak_current + 1;
+        ELSE
+            NEW.streak_current := 1;
+        END IF;
+        NEW.streak_max := greatest(NEW.streak_current, OLD.streak_max, 0);
+    END IF;
+
+    RETURN NEW;
+END
+$$;
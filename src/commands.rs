@@ -6,8 +6,18 @@ use teloxide::types::{BotCommand, BotCommandScope};
 use teloxide::utils::command::BotCommands;
 use crate::config::CachedEnvToggles;
 use crate::handlers::{HemoroidCommands, HemoroidOfDayCommands, HelpCommands, ImportCommands, LoanCommands, PrivacyCommands, PromoCommands};
-use crate::handlers::pvp::BattleCommands;
+use crate::handlers::buttfight::BattleCommands;
 use crate::handlers::stats::StatsCommands;
+use crate::handlers::history::HistoryCommands;
+use crate::handlers::battles::BattlesCommands;
+use crate::handlers::timezone::TimezoneCommands;
+use crate::handlers::rules::RulesCommands;
+use crate::handlers::achievements::AchievementsCommands;
+use crate::handlers::shop::ShopCommands;
+use crate::handlers::inventory::InventoryCommands;
+use crate::handlers::tournament::TournamentCommands;
+use crate::handlers::teambattle::TeamBattleCommands;
+use crate::handlers::duel::DuelCommands;
 
 pub async fn set_my_commands(bot: &Bot, lang_code: &str, toggles: &CachedEnvToggles) -> Result<(), RequestError> {
     let personal_commands = vec![
@@ -21,11 +31,21 @@ pub async fn set_my_commands(bot: &Bot, lang_code: &str, toggles: &CachedEnvTogg
         HemoroidCommands::bot_commands(),
         HemoroidOfDayCommands::bot_commands(),
         BattleCommands::bot_commands(),
+        TeamBattleCommands::bot_commands(),
+        DuelCommands::bot_commands(),
         LoanCommands::bot_commands(),
         StatsCommands::bot_commands(),
+        HistoryCommands::bot_commands(),
+        BattlesCommands::bot_commands(),
+        AchievementsCommands::bot_commands(),
+        ShopCommands::bot_commands(),
+        InventoryCommands::bot_commands(),
     ];
     let admin_commands = [group_commands.clone(), vec![
         ImportCommands::bot_commands(),
+        TimezoneCommands::bot_commands(),
+        RulesCommands::bot_commands(),
+        TournamentCommands::bot_commands(),
     ]].concat();
 
     let requests = vec![
@@ -1,8 +1,11 @@
 use reqwest::Url;
+use anyhow::Context;
 use crate::config::env::*;
 use crate::config::toggles::*;
 use crate::config::announcements::*;
-use crate::domain::Ratio;
+use crate::config::shop::*;
+use crate::config::damage::*;
+use crate::domain::{Ratio, Tenths};
 use crate::domain::SupportedLanguage::{EN, RU};
 
 #[derive(Clone)]
@@ -13,8 +16,50 @@ pub struct AppConfig {
     pub loan_payout_ratio: f32,
     pub dod_rich_exclusion_ratio: Option<Ratio>,
     pub pvp_default_bet: u16,
+    pub pvp_challenge_ttl_minutes: u32,
+    /// In whole centimetres, 0 disables the side bets of the spectators.
+    pub pvp_side_bet: u16,
+    pub duel_round_timeout_seconds: u32,
     pub announcements: AnnouncementsConfig,
     pub command_toggles: CachedEnvToggles,
+    pub clench: ClenchConfig,
+    pub shop: ShopConfig,
+    pub tournament: TournamentConfig,
+    pub damage: DamageConfig,
+    pub battle_limits: BattleLimitsConfig,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Default))]
+pub struct ClenchConfig {
+    pub success_chance: f64,
+    pub damage_reduction: f32,
+    pub shield_hours: u16,
+    pub cooldown_hours: u16,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Default))]
+pub struct TournamentConfig {
+    pub registration_minutes: u32,
+    /// In tenths of a centimetre.
+    pub prize: i32,
+}
+
+/// Limits the battles fought in a chat per local day to prevent farming of the rewards. Zero limits are disabled.
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Default))]
+pub struct BattleLimitsConfig {
+    pub pair_daily: u16,
+    pub user_daily: u16,
+    /// The reward for every previous battle of the same pair today is multiplied by it.
+    pub pair_reward_decay: f32,
+}
+
+impl BattleLimitsConfig {
+    pub fn reward_ratio(&self, previous_pair_battles: u32) -> f32 {
+        self.pair_reward_decay.clamp(0.0, 1.0).powi(previous_pair_battles.try_into().unwrap_or(i32::MAX))
+    }
 }
 
 #[derive(Clone)]
@@ -24,7 +69,7 @@ pub struct DatabaseConfig {
 }
 
 impl AppConfig {
-    pub fn from_env() -> Self {
+    pub fn from_env() -> anyhow::Result<Self> {
         let top_limit = get_env_value_or_default("TOP_LIMIT", 10);
         let loan_payout_ratio = get_env_value_or_default("LOAN_PAYOUT_COEF", 0.0);
         let dod_selection_mode = get_optional_env_value("DOD_SELECTION_MODE");
@@ -33,14 +78,29 @@ impl AppConfig {
         let top_unlimited = get_env_value_or_default("TOP_UNLIMITED_ENABLED", false);
         let multiple_loans = get_env_value_or_default("MULTIPLE_LOANS_ENABLED", false);
         let pvp_default_bet = get_env_value_or_default("PVP_DEFAULT_BET", 1);
+        let pvp_challenge_ttl_minutes = get_env_value_or_default("PVP_CHALLENGE_TTL_MINUTES", 60);
+        let pvp_side_bet = get_env_value_or_default("PVP_SIDE_BET", 1);
+        let duel_round_timeout_seconds = get_env_value_or_default("DUEL_ROUND_TIMEOUT_SECONDS", 60);
         let check_acceptor_length = get_env_value_or_default("PVP_CHECK_ACCEPTOR_LENGTH", false);
         let callback_locks = get_env_value_or_default("PVP_CALLBACK_LOCKS_ENABLED", true);
+        let callback_locks_backend = get_env_value_or_default("PVP_CALLBACK_LOCKS_BACKEND", CallbackLocksBackend::Memory);
         let show_stats = get_env_value_or_default("PVP_STATS_SHOW", true);
         let show_stats_notice = get_env_value_or_default("PVP_STATS_SHOW_NOTICE", true);
         let announcement_max_shows = get_optional_env_value("ANNOUNCEMENT_MAX_SHOWS");
         let announcement_en = get_optional_env_value("ANNOUNCEMENT_EN");
         let announcement_ru = get_optional_env_value("ANNOUNCEMENT_RU");
-        Self {
+        let clench_success_chance = get_env_value_or_default("CLENCH_SUCCESS_CHANCE", 0.3);
+        let clench_damage_reduction = get_env_value_or_default("CLENCH_DAMAGE_REDUCTION", 0.5);
+        let clench_shield_hours = get_env_value_or_default("CLENCH_SHIELD_HOURS", 24);
+        let clench_cooldown_hours = get_env_value_or_default("CLENCH_COOLDOWN_HOURS", 24);
+        let shop_items = get_env_value_or_default("SHOP_ITEMS", DEFAULT_SHOP_ITEMS.to_owned());
+        let shop_coins_per_win = get_env_value_or_default("SHOP_COINS_PER_WIN", 1);
+        let tournament_registration_minutes = get_env_value_or_default("TOURNAMENT_REGISTRATION_MINUTES", 10);
+        let tournament_prize = get_length_env_value_or_default("TOURNAMENT_PRIZE", Tenths::from_cm(5))?;
+        let pvp_pair_daily_limit = get_env_value_or_default("PVP_PAIR_DAILY_LIMIT", 0);
+        let pvp_user_daily_limit = get_env_value_or_default("PVP_USER_DAILY_LIMIT", 0);
+        let pvp_pair_reward_decay = get_env_value_or_default("PVP_PAIR_REWARD_DECAY", 1.0);
+        Ok(Self {
             features: FeatureToggles {
                 chats_merging,
                 top_unlimited,
@@ -49,6 +109,7 @@ impl AppConfig {
                 pvp: BattlesFeatureToggles {
                     check_acceptor_length,
                     callback_locks,
+                    callback_locks_backend,
                     show_stats,
                     show_stats_notice,
                 }
@@ -57,6 +118,9 @@ impl AppConfig {
             loan_payout_ratio,
             dod_rich_exclusion_ratio,
             pvp_default_bet,
+            pvp_challenge_ttl_minutes,
+            pvp_side_bet,
+            duel_round_timeout_seconds,
             announcements: AnnouncementsConfig {
                 max_shows: announcement_max_shows,
                 announcements: [
@@ -68,7 +132,27 @@ impl AppConfig {
                     .collect()
             },
             command_toggles: Default::default(),
-        }
+            clench: ClenchConfig {
+                success_chance: clench_success_chance,
+                damage_reduction: clench_damage_reduction,
+                shield_hours: clench_shield_hours,
+                cooldown_hours: clench_cooldown_hours,
+            },
+            shop: ShopConfig {
+                items: parse_shop_items(&shop_items).context("invalid SHOP_ITEMS")?,
+                coins_per_win: shop_coins_per_win,
+            },
+            tournament: TournamentConfig {
+                registration_minutes: tournament_registration_minutes,
+                prize: tournament_prize,
+            },
+            damage: DamageConfig::from_env()?,
+            battle_limits: BattleLimitsConfig {
+                pair_daily: pvp_pair_daily_limit,
+                user_daily: pvp_user_daily_limit,
+                pair_reward_decay: pvp_pair_reward_decay,
+            },
+        })
     }
 }
 
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use anyhow::{anyhow, bail, ensure, Context};
+use rand::Rng;
+use teloxide::types::ChatId;
+use crate::config::env::get_env_value_or_default;
+use crate::domain::Tenths;
+use crate::repo::ChatIdKind;
+
+/// Format: `base:critical_chance:critical:lucky_chance:lucky`, where `base` and `critical` are inclusive ranges like `0.1..0.4`.
+/// The values are set in centimetres, and the lucky chance is taken among the critical events.
+pub const DEFAULT_TOP_DAMAGE: &str = "0.1..0.4:0.1:0.5..0.9:0.4:-0.5";
+pub const DEFAULT_BOTTOM_DAMAGE: &str = "0.4..1.8:0.15:1.8..2.4:0.3:-1";
+
+/// The damage of one of the battle roles in tenths of a centimetre. Positive values are swelling, negative ones are shrinkage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleDamage {
+    pub base: RangeInclusive<i32>,
+    pub critical_chance: f64,
+    /// Complications, i.e. critical events without luck.
+    pub critical: RangeInclusive<i32>,
+    pub lucky_chance: f64,
+    pub lucky: i32,
+}
+
+impl RoleDamage {
+    pub fn roll(&self, rng: &mut impl Rng) -> i32 {
+        if !rng.gen_bool(self.critical_chance) {
+            rng.gen_range(self.base.clone())
+        } else if rng.gen_bool(self.lucky_chance) {
+            self.lucky
+        } else {
+            rng.gen_range(self.critical.clone())
+        }
+    }
+
+    /// The overall chance of luck in percents.
+    pub fn lucky_percentage(&self) -> f64 {
+        self.critical_chance * self.lucky_chance * 100.0
+    }
+
+    /// The overall chance of complications in percents.
+    pub fn complications_percentage(&self) -> f64 {
+        self.critical_chance * (1.0 - self.lucky_chance) * 100.0
+    }
+}
+
+impl FromStr for RoleDamage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let [base, critical_chance, critical, lucky_chance, lucky] = parts[..] else {
+            bail!("the damage of a role must consist of 5 parts: {s}")
+        };
+        let damage = Self {
+            base: parse_range(base).context(format!("invalid base damage: {s}"))?,
+            critical_chance: parse_chance(critical_chance).context(format!("invalid critical chance: {s}"))?,
+            critical: parse_range(critical).context(format!("invalid critical damage: {s}"))?,
+            lucky_chance: parse_chance(lucky_chance).context(format!("invalid lucky chance: {s}"))?,
+            lucky: lucky.parse::<Tenths>().context(format!("invalid lucky damage: {s}"))?.value(),
+        };
+        Ok(damage)
+    }
+}
+
+fn parse_range(s: &str) -> anyhow::Result<RangeInclusive<i32>> {
+    let (min, max) = s.split_once("..")
+        .ok_or(anyhow!("a range must look like `min..max`"))?;
+    let (min, max) = (min.parse::<Tenths>()?.value(), max.parse::<Tenths>()?.value());
+    ensure!(min <= max, "the minimum is greater than the maximum");
+    Ok(min..=max)
+}
+
+fn parse_chance(s: &str) -> anyhow::Result<f64> {
+    let chance: f64 = s.parse()?;
+    ensure!((0.0..=1.0).contains(&chance), "a chance must be from 0 to 1");
+    Ok(chance)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DamageProfile {
+    pub top: RoleDamage,
+    pub bottom: RoleDamage,
+}
+
+impl Default for DamageProfile {
+    fn default() -> Self {
+        Self {
+            top: DEFAULT_TOP_DAMAGE.parse().expect("the default damage of the top must be valid"),
+            bottom: DEFAULT_BOTTOM_DAMAGE.parse().expect("the default damage of the bottom must be valid"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DamageConfig {
+    pub default: DamageProfile,
+    pub chats: HashMap<ChatId, DamageProfile>,
+}
+
+impl DamageConfig {
+    pub(super) fn from_env() -> anyhow::Result<Self> {
+        let top = get_env_value_or_default("BATTLE_DAMAGE_TOP", DEFAULT_TOP_DAMAGE.to_owned());
+        let bottom = get_env_value_or_default("BATTLE_DAMAGE_BOTTOM", DEFAULT_BOTTOM_DAMAGE.to_owned());
+        let overrides = get_env_value_or_default("BATTLE_DAMAGE_CHATS", String::new());
+        Self::parse(&top, &bottom, &overrides)
+    }
+
+    /// Overrides are separated by commas and look like `chat_id:role=damage`, where the role is either `top` or `bottom`.
+    /// The other role of the chat keeps the default damage.
+    fn parse(top: &str, bottom: &str, overrides: &str) -> anyhow::Result<Self> {
+        let default = DamageProfile {
+            top: top.parse().context("invalid BATTLE_DAMAGE_TOP")?,
+            bottom: bottom.parse().context("invalid BATTLE_DAMAGE_BOTTOM")?,
+        };
+        let mut chats: HashMap<ChatId, DamageProfile> = HashMap::new();
+        for item in overrides.split(',').filter(|item| !item.trim().is_empty()) {
+            let (key, damage) = item.split_once('=')
+                .ok_or(anyhow!("no '=' in the damage override: {item}"))?;
+            let (chat_id, role) = key.trim().split_once(':')
+                .ok_or(anyhow!("the key of the damage override must look like `chat_id:role`: {item}"))?;
+            let chat_id = ChatId(chat_id.parse().context(format!("invalid chat id of the damage override: {item}"))?);
+            let damage = damage.parse().context(format!("invalid damage override: {item}"))?;
+            let profile = chats.entry(chat_id).or_insert_with(|| default.clone());
+            match role {
+                "top" => profile.top = damage,
+                "bottom" => profile.bottom = damage,
+                _ => bail!("unknown role of the damage override: {item}")
+            }
+        }
+        Ok(Self { default, chats })
+    }
+
+    pub fn profile(&self, chat_id: &ChatIdKind) -> &DamageProfile {
+        let profile = match chat_id {
+            ChatIdKind::ID(chat_id) => self.chats.get(chat_id),
+            ChatIdKind::Instance(_) => None,
+        };
+        profile.unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::mock::StepRng;
+    use teloxide::types::ChatId;
+    use crate::repo::ChatIdKind;
+    use super::{DamageConfig, RoleDamage, DEFAULT_BOTTOM_DAMAGE, DEFAULT_TOP_DAMAGE};
+
+    #[test]
+    fn test_parse() {
+        let config = DamageConfig::parse(DEFAULT_TOP_DAMAGE, DEFAULT_BOTTOM_DAMAGE, "-100123:bottom=0.1..0.2:0:0.3..0.3:1:-0.1, ")
+            .expect("the damage config must be valid");
+        assert_eq!(config.default.top, RoleDamage {
+            base: 1..=4,
+            critical_chance: 0.1,
+            critical: 5..=9,
+            lucky_chance: 0.4,
+            lucky: -5,
+        });
+        let profile = config.profile(&ChatIdKind::ID(ChatId(-100123)));
+        assert_eq!(profile.top, config.default.top);
+        assert_eq!(profile.bottom.base, 1..=2);
+        assert_eq!(config.profile(&ChatIdKind::ID(ChatId(1))), &config.default);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for top in ["0.1..0.4:0.1:0.5..0.9:0.4", "0.4..0.1:0.1:0.5..0.9:0.4:-0.5", "0.1..0.4:1.5:0.5..0.9:0.4:-0.5",
+                    "0.1-0.4:0.1:0.5..0.9:0.4:-0.5", "0.15..0.4:0.1:0.5..0.9:0.4:-0.5"] {
+            assert!(DamageConfig::parse(top, DEFAULT_BOTTOM_DAMAGE, "").is_err(), "Case: {top}");
+        }
+        for overrides in ["-100123=0.1..0.2:0:0.3..0.3:1:-0.1", "-100123:middle=0.1..0.2:0:0.3..0.3:1:-0.1",
+                          "chat:top=0.1..0.2:0:0.3..0.3:1:-0.1"] {
+            assert!(DamageConfig::parse(DEFAULT_TOP_DAMAGE, DEFAULT_BOTTOM_DAMAGE, overrides).is_err(), "Case: {overrides}");
+        }
+    }
+
+    #[test]
+    fn test_roll() {
+        let damage: RoleDamage = "0.2..0.2:0:0.7..0.7:1:-0.3".parse().expect("the damage must be valid");
+        assert_eq!(damage.roll(&mut StepRng::new(0, 1)), 2);
+        let damage: RoleDamage = "0.2..0.2:1:0.7..0.7:1:-0.3".parse().expect("the damage must be valid");
+        assert_eq!(damage.roll(&mut StepRng::new(0, 1)), -3);
+        let damage: RoleDamage = "0.2..0.2:1:0.7..0.7:0:-0.3".parse().expect("the damage must be valid");
+        assert_eq!(damage.roll(&mut StepRng::new(0, 1)), 7);
+        assert_eq!(format!("{:.1}", damage.complications_percentage()), "100.0");
+    }
+}
@@ -2,7 +2,7 @@ use std::error::Error;
 use std::fmt::Display;
 use std::str::FromStr;
 use anyhow::anyhow;
-use crate::domain::Ratio;
+use crate::domain::{Ratio, Tenths};
 
 pub(super) fn get_env_mandatory_value<T, E>(key: &str) -> anyhow::Result<T>
 where
@@ -32,6 +32,29 @@ where
         .unwrap_or(default)
 }
 
+/// Unlike `get_env_value_or_default`, fails if the variable is set to an invalid value.
+fn get_checked_env_value_or_default<T, E>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr<Err = E> + Display,
+    E: Error + Send + Sync + 'static
+{
+    match std::env::var(key) {
+        Ok(value) => value.parse()
+            .map_err(|e: E| anyhow!(e).context(format!("invalid value of the {key} environment variable"))),
+        Err(_) => {
+            log::warn!("no value was found for an optional environment variable {key}, using the default value {default}");
+            Ok(default)
+        }
+    }
+}
+
+/// Lengths are set in centimetres with at most one decimal digit and converted into tenths of the target type.
+pub fn get_length_env_value_or_default<T: TryFrom<i32>>(key: &str, default: Tenths) -> anyhow::Result<T> {
+    let value = get_checked_env_value_or_default(key, default)?;
+    T::try_from(value.value())
+        .map_err(|_| anyhow!("the value of the {key} environment variable is out of range: {value} cm"))
+}
+
 pub(super) fn get_optional_env_value<T>(key: &str) -> T
 where
     T: Default + FromStr + Display,
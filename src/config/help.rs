@@ -1,10 +1,12 @@
 use teloxide::types::Me;
+use crate::config::DamageProfile;
 use crate::config::env::get_env_mandatory_value;
+use crate::domain::Tenths;
 use crate::handlers::perks::HelpPussiesPerk;
 use crate::handlers::utils::Incrementor;
 use crate::help;
 
-pub fn build_context_for_help_messages(me: Me, incr: &Incrementor, competitor_bots: &[&str]) -> anyhow::Result<help::Context> {
+pub fn build_context_for_help_messages(me: Me, incr: &Incrementor, damage: &DamageProfile, competitor_bots: &[&str]) -> anyhow::Result<help::Context> {
     let other_bots = competitor_bots
         .iter()
         .map(|username| ensure_starts_with_at_sign(username.to_string()))
@@ -14,8 +16,11 @@ pub fn build_context_for_help_messages(me: Me, incr: &Incrementor, competitor_bo
 
     Ok(help::Context {
         bot_name: me.username().to_owned(),
-        grow_min: incr_cfg.growth_range_min().to_string(),
-        grow_max: incr_cfg.growth_range_max().to_string(),
+        grow_min: Tenths::from(i32::from(incr_cfg.growth_range_min())).to_string(),
+        grow_max: Tenths::from(i32::from(incr_cfg.growth_range_max())).to_string(),
+        shrink_max: incr_cfg.shrink_max().to_string(),
+        swell_max: incr_cfg.swell_max().to_string(),
+        shrink_percentage: incr_cfg.shrink_percentage(),
         other_bots,
         admin_channel_ru: ensure_starts_with_at_sign(get_env_mandatory_value("HELP_ADMIN_CHANNEL_RU")?),
         admin_channel_en: ensure_starts_with_at_sign(get_env_mandatory_value("HELP_ADMIN_CHANNEL_EN")?),
@@ -24,10 +29,26 @@ pub fn build_context_for_help_messages(me: Me, incr: &Incrementor, competitor_bo
         git_repo: get_env_mandatory_value("HELP_GIT_REPO")?,
         help_pussies_percentage: incr.find_perk_config::<HelpPussiesPerk>()
             .map(|payout_ratio| payout_ratio * 100.0)
-            .unwrap_or(0.0)
+            .unwrap_or(0.0),
+        top_damage_min: Tenths::from(*damage.top.base.start()).to_string(),
+        top_damage_max: Tenths::from(*damage.top.base.end()).to_string(),
+        top_lucky_percentage: format_percentage(damage.top.lucky_percentage()),
+        top_complications_percentage: format_percentage(damage.top.complications_percentage()),
+        bottom_damage_min: Tenths::from(*damage.bottom.base.start()).to_string(),
+        bottom_damage_max: Tenths::from(*damage.bottom.base.end()).to_string(),
+        bottom_lucky_percentage: format_percentage(damage.bottom.lucky_percentage()),
+        bottom_complications_percentage: format_percentage(damage.bottom.complications_percentage()),
     })
 }
 
+/// Rounds to one decimal place and drops the zero fraction, e.g. `4.000000000000001` becomes `4`.
+fn format_percentage(value: f64) -> String {
+    format!("{value:.1}")
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_owned()
+}
+
 fn ensure_starts_with_at_sign(s: String) -> String {
     if s.starts_with('@') {
         s
@@ -38,7 +59,7 @@ fn ensure_starts_with_at_sign(s: String) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::ensure_starts_with_at_sign;
+    use super::{ensure_starts_with_at_sign, format_percentage};
 
     #[test]
     fn test_ensure_starts_with_at_sign() {
@@ -46,4 +67,12 @@ mod test {
         assert_eq!(ensure_starts_with_at_sign("test".to_owned()), result);
         assert_eq!(ensure_starts_with_at_sign("@test".to_owned()), result);
     }
+
+    #[test]
+    fn test_format_percentage() {
+        assert_eq!(format_percentage(0.1 * 0.4 * 100.0), "4");
+        assert_eq!(format_percentage(0.15 * 0.7 * 100.0), "10.5");
+        assert_eq!(format_percentage(100.0), "100");
+        assert_eq!(format_percentage(0.0), "0");
+    }
 }
@@ -3,10 +3,14 @@ mod toggles;
 mod announcements;
 mod env;
 mod help;
+mod shop;
+mod damage;
 
 pub use app::*;
 pub use toggles::*;
 pub use announcements::*;
 pub use help::*;
+pub use shop::*;
+pub use damage::*;
 
-pub use env::get_env_value_or_default;
+pub use env::{get_env_value_or_default, get_length_env_value_or_default};
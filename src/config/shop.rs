@@ -0,0 +1,153 @@
+use std::str::FromStr;
+use anyhow::{anyhow, bail, Context};
+use crate::domain::Tenths;
+
+/// Format: `code=currency:price:effect:value[:charges:hours]` separated by commas.
+/// The prices in `cm` and the values of the shrink, boost and cushion effects are set in centimetres.
+pub const DEFAULT_SHOP_ITEMS: &str = "ointment=coins:5:shrink:1.5,suppository=coins:3:attempts:1,ice_pack=cm:1:shield:12,\
+    cream=coins:4:boost:0.5:3:72,cushion=coins:4:cushion:1:2:48";
+
+#[derive(Clone, Default)]
+pub struct ShopConfig {
+    pub items: Vec<ShopItem>,
+    pub coins_per_win: u16,
+}
+
+impl ShopConfig {
+    pub fn item(&self, code: &str) -> Option<&ShopItem> {
+        self.items.iter().find(|item| item.code == code)
+    }
+}
+
+/// The name and description of an item are in the locale files under the `items.<code>` keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShopItem {
+    pub code: String,
+    pub currency: ShopCurrency,
+    /// In tenths of a centimetre if the currency is the protrusion level.
+    pub price: u16,
+    pub effect: ShopItemEffect,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, strum_macros::Display, strum_macros::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ShopCurrency {
+    /// The protrusion level of the buyer is increased by the price.
+    Cm,
+    /// Earned by winning battles.
+    Coins,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShopItemEffect {
+    /// Decreases the protrusion level by the value in tenths of a centimetre.
+    Shrink(u16),
+    /// Additional treatments for today.
+    Attempts(u16),
+    /// A clench shield for the specified number of hours.
+    Shield(u16),
+    /// Improves the next treatments by the value in tenths of a centimetre.
+    Boost(Consumable),
+    /// Softens the swelling of the next battles by the value in tenths of a centimetre.
+    Cushion(Consumable),
+}
+
+/// An effect which stays active after the item is used until its charges run out or it expires.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Consumable {
+    pub value: u16,
+    pub charges: u16,
+    pub hours: u16,
+}
+
+impl FromStr for ShopItem {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, definition) = s.trim().split_once('=')
+            .ok_or(anyhow!("no '=' in the definition of the item: {s}"))?;
+        if code.is_empty() || code.contains(':') {
+            bail!("the code of the item must be non-empty and mustn't contain colons: {s}")
+        }
+        let parts: Vec<&str> = definition.split(':').collect();
+        let (currency, price, effect, value, charges, hours) = match parts[..] {
+            [currency, price, effect, value] => (currency, price, effect, value, "1", "24"),
+            [currency, price, effect, value, charges, hours] => (currency, price, effect, value, charges, hours),
+            _ => bail!("the definition of the item must consist of 4 or 6 parts: {s}")
+        };
+        let count = || value.parse().context(format!("invalid value of the effect: {s}"));
+        let length = || parse_length(value).context(format!("invalid value of the effect: {s}"));
+        let consumable = || -> anyhow::Result<Consumable> {
+            Ok(Consumable {
+                value: length()?,
+                charges: charges.parse().context(format!("invalid number of charges: {s}"))?,
+                hours: hours.parse().context(format!("invalid duration of the effect: {s}"))?,
+            })
+        };
+        let effect = match effect {
+            "shrink" => ShopItemEffect::Shrink(length()?),
+            "attempts" => ShopItemEffect::Attempts(count()?),
+            "shield" => ShopItemEffect::Shield(count()?),
+            "boost" => ShopItemEffect::Boost(consumable()?),
+            "cushion" => ShopItemEffect::Cushion(consumable()?),
+            _ => bail!("unknown effect of the item: {s}")
+        };
+        let currency = currency.parse().context(format!("unknown currency of the item: {s}"))?;
+        let price = match currency {
+            ShopCurrency::Cm => parse_length(price),
+            ShopCurrency::Coins => price.parse().map_err(Into::into),
+        }.context(format!("invalid price of the item: {s}"))?;
+        Ok(Self {
+            code: code.to_owned(),
+            currency,
+            price,
+            effect,
+        })
+    }
+}
+
+/// Converts centimetres into tenths.
+fn parse_length(s: &str) -> anyhow::Result<u16> {
+    let value: Tenths = s.parse()?;
+    u16::try_from(value.value()).map_err(|_| anyhow!("the length must be from 0 to 6553.5 cm: {s}"))
+}
+
+pub(super) fn parse_shop_items(items: &str) -> anyhow::Result<Vec<ShopItem>> {
+    items.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_shop_items, Consumable, ShopCurrency, ShopItem, ShopItemEffect, DEFAULT_SHOP_ITEMS};
+
+    #[test]
+    fn test_parse() {
+        let items = parse_shop_items(DEFAULT_SHOP_ITEMS).expect("the default items must be valid");
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0], ShopItem {
+            code: "ointment".to_owned(),
+            currency: ShopCurrency::Coins,
+            price: 5,
+            effect: ShopItemEffect::Shrink(15),
+        });
+        assert_eq!(items[2].currency, ShopCurrency::Cm);
+        assert_eq!(items[2].price, 10);
+        assert_eq!(items[2].effect, ShopItemEffect::Shield(12));
+        assert_eq!(items[3].effect, ShopItemEffect::Boost(Consumable { value: 5, charges: 3, hours: 72 }));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for items in ["broken", "gel=coins:1:heal:1", "pad=coins:1:cushion:1:2", "balm=coins:1:shrink:0.25",
+                      "pill=coins:1:attempts:0.5", "bandage=cm:1.25:shield:1", "cream=coins:1:boost:6553.6:1:24",
+                      "cream=coins:1:attempts:1, broken"] {
+            assert!(parse_shop_items(items).is_err(), "Case: {items}");
+        }
+        let items = parse_shop_items("cream=coins:1:attempts:1, ").expect("the item must be valid");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].code, "cream");
+    }
+}
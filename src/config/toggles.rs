@@ -38,10 +38,20 @@ impl Default for FeatureToggles {
 pub struct BattlesFeatureToggles {
     pub check_acceptor_length: bool,
     pub callback_locks: bool,
+    pub callback_locks_backend: CallbackLocksBackend,
     pub show_stats: bool,
     pub show_stats_notice: bool,
 }
 
+/// In-memory locks work only if there is a single replica of the bot.
+#[derive(Copy, Clone, Default, Debug, PartialEq, strum_macros::Display, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum CallbackLocksBackend {
+    #[default]
+    Memory,
+    Postgres,
+}
+
 #[derive(Clone, Default)]
 pub struct CachedEnvToggles {
     map: Arc<RwLock<HashMap<String, bool>>>
@@ -44,6 +44,15 @@ impl LanguageCode {
         }
     }
 
+    pub fn decimal_separator(&self) -> char {
+        let code = self.to_ascii_lowercase();
+        match code.get(..2) {
+            Some(prefix) if RU_SPEAKING_LOCALES.contains(&prefix) => ',',
+            Some("fa") => '٫',
+            _ => '.'
+        }
+    }
+
     fn get_language_code_or_log_if_missing(user: &User) -> Option<&String> {
         user.language_code.as_ref()
             .or_else(|| {
@@ -1,7 +1,11 @@
 mod username;
 mod ratio;
 mod langcode;
+mod tenths;
+mod rating;
 
 pub use username::*;
 pub use ratio::*;
 pub use langcode::*;
+pub use tenths::*;
+pub use rating::*;
@@ -0,0 +1,23 @@
+/// The Elo rating every player starts with, must match the default value of the `Battle_Stats.rating` column.
+pub const INITIAL_RATING: i32 = 1000;
+const K_FACTOR: f64 = 32.0;
+
+/// Returns how many points the winner takes from the loser.
+/// Beating a stronger opponent gives more than farming a weaker one, but at least one point.
+pub fn elo_delta(winner_rating: i32, loser_rating: i32) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((loser_rating - winner_rating) as f64 / 400.0));
+    (K_FACTOR * (1.0 - expected)).round().max(1.0) as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::{elo_delta, INITIAL_RATING};
+
+    #[test]
+    fn test_elo_delta() {
+        assert_eq!(elo_delta(INITIAL_RATING, INITIAL_RATING), 16);
+        assert_eq!(elo_delta(1200, 1000), 8);
+        assert_eq!(elo_delta(1000, 1200), 24);
+        assert_eq!(elo_delta(2400, 1000), 1);
+    }
+}
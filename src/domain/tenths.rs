@@ -0,0 +1,103 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use derive_more::From;
+use super::LanguageCode;
+
+/// Protrusion levels and everything derived from them (damage, debts, bonuses) are stored
+/// as fixed-point tenths of a centimetre.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, From)]
+pub struct Tenths(i32);
+
+impl Tenths {
+    pub fn from_cm(cm: i32) -> Self {
+        Self(cm * 10)
+    }
+
+    pub fn value(self) -> i32 {
+        self.0
+    }
+
+    /// Formats the value in centimetres, omitting the fractional part for whole numbers.
+    pub fn format(self, lang_code: &LanguageCode) -> String {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        self.format_abs(sign, lang_code.decimal_separator())
+    }
+
+    pub fn format_signed(self, lang_code: &LanguageCode) -> String {
+        let sign = if self.0 < 0 { "-" } else { "+" };
+        self.format_abs(sign, lang_code.decimal_separator())
+    }
+
+    fn format_abs(self, sign: &str, separator: char) -> String {
+        let abs = self.0.unsigned_abs();
+        match abs % 10 {
+            0 => format!("{sign}{}", abs / 10),
+            fraction => format!("{sign}{}{separator}{fraction}", abs / 10),
+        }
+    }
+}
+
+impl Display for Tenths {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        f.write_str(&self.format_abs(sign, '.'))
+    }
+}
+
+#[derive(Debug, derive_more::Display, derive_more::Error)]
+#[display("not a length in centimetres with at most one decimal digit: {_0}")]
+pub struct ParseTenthsError(#[error(not(source))] String);
+
+/// Parses centimetres like `1.5` or `-2`, which is how all the lengths are set in the environment variables.
+impl FromStr for Tenths {
+    type Err = ParseTenthsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseTenthsError(s.to_owned());
+        let (negative, abs) = match s.trim().strip_prefix('-') {
+            Some(abs) => (true, abs),
+            None => (false, s.trim().strip_prefix('+').unwrap_or(s.trim())),
+        };
+        let (whole, fraction) = abs.split_once('.').unwrap_or((abs, "0"));
+        let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        if !is_number(whole) || !is_number(fraction) || fraction.len() > 1 {
+            return Err(err())
+        }
+        let abs = whole.parse::<i32>().ok()
+            .and_then(|whole| whole.checked_mul(10))
+            .and_then(|tenths| tenths.checked_add(fraction.parse().ok()?))
+            .ok_or_else(err)?;
+        Ok(Self(if negative { -abs } else { abs }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tenths;
+    use crate::domain::LanguageCode;
+
+    #[test]
+    fn test_format() {
+        let en = LanguageCode::new("en".to_owned());
+        let ru = LanguageCode::new("ru".to_owned());
+        assert_eq!(Tenths::from(25).format(&en), "2.5");
+        assert_eq!(Tenths::from(25).format(&ru), "2,5");
+        assert_eq!(Tenths::from(30).format(&en), "3");
+        assert_eq!(Tenths::from(-7).format(&en), "-0.7");
+        assert_eq!(Tenths::from(7).format_signed(&en), "+0.7");
+        assert_eq!(Tenths::from(-15).format_signed(&ru), "-1,5");
+        assert_eq!(Tenths::from_cm(4).value(), 40);
+        assert_eq!(Tenths::from(-12).to_string(), "-1.2");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("1.5".parse::<Tenths>().ok(), Some(Tenths::from(15)));
+        assert_eq!("-0.5".parse::<Tenths>().ok(), Some(Tenths::from(-5)));
+        assert_eq!("+2".parse::<Tenths>().ok(), Some(Tenths::from(20)));
+        assert_eq!(" 10 ".parse::<Tenths>().ok(), Some(Tenths::from(100)));
+        for invalid in ["", "-", "1.", ".5", "1.25", "1,5", "abc", "--1", "999999999"] {
+            assert!(invalid.parse::<Tenths>().is_err(), "Case: {invalid}");
+        }
+    }
+}
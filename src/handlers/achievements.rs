@@ -0,0 +1,74 @@
+use anyhow::anyhow;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{Message, UserId};
+use crate::handlers::{FromRefs, HandlerResult, reply_html};
+use crate::{metrics, reply_html, repo};
+use crate::domain::LanguageCode;
+use crate::repo::ChatIdKind;
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum AchievementsCommands {
+    #[command(description = "achievements")]
+    Achievements,
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, repos: repo::Repositories) -> HandlerResult {
+    metrics::CMD_ACHIEVEMENTS_COUNTER.inc();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let chat_id = msg.chat.id.into();
+    let from_refs = FromRefs(from, &chat_id);
+
+    let answer = achievements_impl(&repos, from_refs).await?;
+    reply_html!(bot, msg, answer);
+    Ok(())
+}
+
+pub(crate) async fn achievements_impl(repos: &repo::Repositories, from_refs: FromRefs<'_>) -> anyhow::Result<String> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+
+    let achievements = repos.achievements.get_all(&chat_id, from.id).await?;
+    let unlocked = achievements.iter()
+        .filter(|a| a.unlocked_at.is_some())
+        .count();
+    let lines = achievements.iter()
+        .map(|a| {
+            let name = t!(&format!("achievements.{}.name", a.code), locale = &lang_code);
+            let description = t!(&format!("achievements.{}.description", a.code), locale = &lang_code);
+            match a.unlocked_at {
+                Some(unlocked_at) => t!("commands.achievements.line.unlocked", locale = &lang_code,
+                    name = name, description = description, date = unlocked_at.format("%d.%m.%Y")),
+                None => t!("commands.achievements.line.locked", locale = &lang_code,
+                    name = name, description = description),
+            }.to_string()
+        })
+        .collect::<Vec<String>>();
+    let title = t!("commands.achievements.title", locale = &lang_code,
+        unlocked = unlocked, total = achievements.len());
+    Ok(format!("{}\n\n{}", title, lines.join("\n")))
+}
+
+/// Evaluates the rules after something has happened to the user and announces the new badges.
+/// Errors are only logged since they mustn't break the main action.
+pub(crate) async fn check_and_announce(repos: &repo::Repositories, chat_id: &ChatIdKind, user_id: UserId,
+                                       name: &str, lang_code: &LanguageCode) -> String {
+    let codes = repos.achievements.check(chat_id, user_id)
+        .await
+        .inspect_err(|e| log::error!("couldn't check the achievements of {user_id} in {chat_id}: {e}"))
+        .unwrap_or_default();
+    announcement(&codes, name, lang_code)
+}
+
+pub(crate) fn announcement(codes: &[String], name: &str, lang_code: &LanguageCode) -> String {
+    codes.iter()
+        .map(|code| {
+            let achievement = t!(&format!("achievements.{code}.name"), locale = lang_code);
+            format!("\n\n{}", t!("commands.achievements.unlocked", locale = lang_code,
+                name = name, achievement = achievement))
+        })
+        .collect()
+}
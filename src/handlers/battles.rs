@@ -0,0 +1,205 @@
+use anyhow::anyhow;
+use derive_more::Display;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message, UserId};
+use teloxide::requests::Requester;
+use teloxide::types::{ParseMode, ReplyMarkup};
+use callbacks::{EditMessageReqParamsKind, InvalidCallbackData};
+
+use crate::{check_invoked_by_owner_and_get_answer_params, metrics, reply_html, repo};
+use crate::config::AppConfig;
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackButton, HandlerImplResult, HandlerResult, reply_html};
+use crate::handlers::checks::is_invoked_by_admin;
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::handlers::utils::page::Page;
+use crate::repo::{BattleRecord, ChatIdKind, ChatIdPartiality};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum BattlesCommands {
+    #[command(description = "battles")]
+    Battles,
+}
+
+/// Shows the battles of the invoker or, for administrators, of the author of the replied message.
+pub async fn cmd_handler(bot: Bot, msg: Message, repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_BATTLES_COUNTER.inc();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let target = msg.reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .filter(|user| !user.is_bot && user.id != from.id)
+        .map(|user| user.id);
+    if target.is_some() && !is_invoked_by_admin(&bot, &msg, from.id).await? {
+        reply_html!(bot, msg, t!("commands.battles.errors.not_admin", locale = &lang_code));
+        return Ok(())
+    }
+
+    let chat_id = ChatIdKind::ID(msg.chat.id);
+    let data = BattlesCallbackData {
+        viewer: from.id,
+        target: target.unwrap_or(from.id),
+        page: Page::first(),
+    };
+    let result = battles_impl(&repos, &config, &chat_id, &lang_code, data).await?;
+    let mut request = reply_html(bot, &msg, result.text());
+    request.reply_markup = result.keyboard().map(ReplyMarkup::InlineKeyboard);
+    request.await?;
+    Ok(())
+}
+
+pub(crate) async fn battles_impl(repos: &repo::Repositories, config: &AppConfig, chat_id: &ChatIdKind, lang_code: &LanguageCode,
+                                 data: BattlesCallbackData) -> anyhow::Result<HandlerImplResult<BattlesCallbackData>> {
+    let BattlesCallbackData { viewer, target, page } = data;
+    let limit = config.top_limit as u32;
+    let offset = page * limit;
+    let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
+
+    let records = repos.battles.get_page(chat_id, target, offset, query_limit).await?;
+    let has_more_pages = records.len() as u32 > limit;
+    let lines = records.iter()
+        .take(config.top_limit as usize)
+        .map(|record| format_record(record, target, lang_code))
+        .collect::<Vec<String>>();
+
+    if lines.is_empty() {
+        let text = t!("commands.battles.empty", locale = lang_code).to_string();
+        return Ok(HandlerImplResult::OnlyText(text))
+    }
+
+    let title = if viewer == target {
+        t!("commands.battles.title.own", locale = lang_code).to_string()
+    } else {
+        let name = repos.users.get(target).await?
+            .map(|user| user.name.escaped())
+            .unwrap_or_else(|| target.to_string());
+        t!("commands.battles.title.other", locale = lang_code, name = name).to_string()
+    };
+    let text = format!("{}\n\n{}", title, lines.join("\n"));
+    let mut buttons = Vec::new();
+    if page > 0 {
+        buttons.push(CallbackButton::new("◀️".to_owned(), BattlesCallbackData { viewer, target, page: page - 1 }));
+    }
+    if has_more_pages {
+        buttons.push(CallbackButton::new("▶️".to_owned(), BattlesCallbackData { viewer, target, page: page + 1 }));
+    }
+    let res = if buttons.is_empty() {
+        HandlerImplResult::OnlyText(text)
+    } else {
+        HandlerImplResult::WithKeyboard { text, buttons }
+    };
+    Ok(res)
+}
+
+fn format_record(record: &BattleRecord, user_id: UserId, lang_code: &LanguageCode) -> String {
+    let (role, own, opponent) = if record.top.uid == user_id {
+        ("top", &record.top, &record.bottom)
+    } else {
+        ("bottom", &record.bottom, &record.top)
+    };
+    let outcome = if record.winner == user_id { "won" } else { "lost" };
+    let challenge = if record.initiator == user_id { "initiated" } else { "accepted" };
+    let rematch = if record.rematch > 0 {
+        t!("commands.battles.rematch", locale = lang_code, number = record.rematch).to_string()
+    } else {
+        String::new()
+    };
+    let line = t!("commands.battles.line", locale = lang_code,
+        id = record.id,
+        date = record.fought_at.format("%d.%m.%Y %H:%M"),
+        outcome = t!(&format!("commands.battles.outcomes.{outcome}"), locale = lang_code),
+        opponent = opponent.name.escaped(),
+        role = t!(&format!("commands.battles.roles.{role}"), locale = lang_code),
+        challenge = t!(&format!("commands.battles.challenges.{challenge}"), locale = lang_code),
+        damage = Tenths::from(own.damage).format_signed(lang_code),
+        opponent_damage = Tenths::from(opponent.damage).format_signed(lang_code),
+        bet = record.bet);
+    format!("{line}{rematch}")
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    BattlesCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery,
+                              repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let data = BattlesCallbackData::parse(&query)?;
+    let (answer, lang_code) = check_invoked_by_owner_and_get_answer_params!(bot, query, data.viewer);
+
+    let edit_msg_params = callbacks::get_params_for_message_edit(&query)?;
+    let chat_id = ChatIdPartiality::Specific(edit_msg_params.clone().into());
+    let result = battles_impl(&repos, &config, &chat_id.kind(), &lang_code, data).await?;
+
+    match edit_msg_params {
+        EditMessageReqParamsKind::Chat(chat_id, message_id) => {
+            let mut request = bot.edit_message_text(chat_id, message_id, result.text());
+            request.parse_mode.replace(ParseMode::Html);
+            request.reply_markup = result.keyboard();
+            request.await?;
+        }
+        EditMessageReqParamsKind::Inline { inline_message_id, .. } => {
+            let mut request = bot.edit_message_text_inline(inline_message_id, result.text());
+            request.parse_mode.replace(ParseMode::Html);
+            request.reply_markup = result.keyboard();
+            request.await?;
+        }
+    }
+
+    answer.await?;
+    Ok(())
+}
+
+#[derive(Display)]
+#[display("{viewer}:{target}:{page}")]
+pub(crate) struct BattlesCallbackData {
+    /// Only the one who invoked the command may turn the pages.
+    viewer: UserId,
+    target: UserId,
+    page: Page,
+}
+
+impl CallbackDataWithPrefix for BattlesCallbackData {
+    fn prefix() -> &'static str {
+        "battles"
+    }
+}
+
+impl TryFrom<String> for BattlesCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.as_str().split(':');
+        let viewer = callbacks::parse_part(&mut parts, &err, "viewer").map(UserId)?;
+        let target = callbacks::parse_part(&mut parts, &err, "target").map(UserId)?;
+        let page = callbacks::parse_part(&mut parts, &err, "page").map(Page)?;
+        Ok(Self { viewer, target, page })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use teloxide::types::UserId;
+    use crate::handlers::battles::BattlesCallbackData;
+    use crate::handlers::utils::callbacks::CallbackDataWithPrefix;
+    use crate::handlers::utils::page::Page;
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let data = BattlesCallbackData { viewer: UserId(123456), target: UserId(654321), page: Page(2) };
+        let data_string = data.to_data_string();
+        assert_eq!(data_string, "battles:123456:654321:2");
+
+        let parsed = BattlesCallbackData::try_from("123456:654321:2".to_owned())
+            .expect("battles callback data must be parsed successfully");
+        assert_eq!(parsed.viewer, data.viewer);
+        assert_eq!(parsed.target, data.target);
+        assert_eq!(parsed.page, 2);
+    }
+}
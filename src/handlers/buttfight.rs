@@ -7,25 +7,28 @@ use teloxide::Bot;
 use teloxide::macros::BotCommands;
 use teloxide::payloads::AnswerInlineQuerySetters;
 use teloxide::requests::Requester;
-use teloxide::types::{CallbackQuery, ChatId, ChosenInlineResult, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText, Message, ParseMode, ReplyMarkup, User, UserId};
-use crate::handlers::{CallbackResult, HandlerResult, reply_html, send_error_callback_answer, utils};
+use teloxide::types::{CallbackQuery, ChatId, ChosenInlineResult, InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText, Message, MessageEntityKind, ParseMode, ReplyMarkup, User, UserId};
+use teloxide::utils::command::ParseError;
+use crate::handlers::{achievements, rules, CallbackResult, HandlerResult, reply_html, send_error_callback_answer, utils};
 use crate::{metrics, reply_html, repo};
-use crate::config::{AppConfig, BattlesFeatureToggles};
-use crate::domain::{LanguageCode, Username};
+use crate::config::{AppConfig, BattleLimitsConfig, BattlesFeatureToggles, ClenchConfig, DamageProfile};
+use crate::domain::{LanguageCode, Tenths, Username};
 use crate::handlers::utils::callbacks;
 use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder, NewLayoutValue};
+use crate::handlers::rules::BattleOutcome;
 use crate::handlers::utils::locks::LockCallbackServiceFacade;
-use crate::repo::{BattleStats, ChatIdPartiality, TreatmentResult, Repositories, WinRateAware};
+use crate::repo::{BattleBet, BattleStats, BetSide, SideBetError, ChallengeMessage, ChatIdKind, ChatIdPartiality, DailyLimit, EffectTarget, ExpiredChallenge, mercy_amount, MercyError, MercyRequest, MercyResult, NewBattle, PenetrationError, PenetrationResult, TreatmentResult, Repositories, WinRateAware};
 
 // let's calculate time offsets from 22.06.2024
 const TIMESTAMP_MILLIS_SINCE_2024: i64 = 1719014400000;
 
-#[derive(BotCommands, Clone, Copy)]
+#[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
 pub enum BattleCommands {
-    #[command(description = "buttfight")]
-    Penetrate(u16),
-    Buttfight(u16),
+    #[command(description = "buttfight", parse_with = parse_bet_and_target)]
+    Penetrate(u16, String),
+    #[command(parse_with = parse_bet_and_target)]
+    Buttfight(u16, String),
 }
 
 #[derive(BotCommands, Clone)]
@@ -38,29 +41,100 @@ pub enum BattleCommandsNoArgs {
 impl BattleCommands {
     fn bet(&self) -> u16 {
         match *self {
-            Self::Penetrate(bet) => bet,
-            Self::Buttfight(bet) => bet,
+            Self::Penetrate(bet, _) => bet,
+            Self::Buttfight(bet, _) => bet,
+        }
+    }
+
+    fn target(&self) -> &str {
+        match self {
+            Self::Penetrate(_, target) => target,
+            Self::Buttfight(_, target) => target,
         }
     }
 }
 
-#[derive(derive_more::Display)]
-#[display("{initiator}:{bet}:{timestamp}")]
+/// The bet may be followed by an @mention of the only person who can accept the challenge.
+fn parse_bet_and_target(input: String) -> Result<(u16, String), ParseError> {
+    let mut parts = input.trim().splitn(2, char::is_whitespace);
+    let bet = parts.next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let target = parts.next()
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+    Ok((bet, target))
+}
+
 pub(crate) struct BattleCallbackData {
     initiator: UserId,
     bet: u16,
 
     // used to prevent repeated clicks on the same button
-    timestamp: NewLayoutValue<i64>
+    timestamp: NewLayoutValue<i64>,
+
+    // if present, only this person can accept the challenge
+    target: Option<UserId>,
+
+    // the previous battle of the pair if the challenge is a rematch; always goes along with the target
+    rematch_of: Option<i64>,
 }
 
 impl BattleCallbackData {
-    fn new(initiator: UserId, bet: u16) -> Self {
+    fn new(initiator: UserId, bet: u16, target: Option<UserId>) -> Self {
         Self {
-            initiator, bet,
-            timestamp: new_short_timestamp()
+            initiator, bet, target,
+            timestamp: new_short_timestamp(),
+            rematch_of: None,
         }
     }
+
+    fn rematch(initiator: UserId, bet: u16, target: UserId, battle_id: i64) -> Self {
+        Self {
+            rematch_of: Some(battle_id),
+            ..Self::new(initiator, bet, Some(target))
+        }
+    }
+
+    /// Challenges of the old layout have no timestamp, so they're considered expired if there is a limit at all.
+    fn is_expired(&self, ttl_minutes: u32) -> bool {
+        match self.timestamp {
+            NewLayoutValue::Some(timestamp) => is_timestamp_expired(timestamp, ttl_minutes),
+            NewLayoutValue::None => ttl_minutes > 0
+        }
+    }
+
+    /// Identifies the challenge among the side bets of the chat; challenges of the old layout can't have any.
+    fn side_bets_key(&self) -> Option<String> {
+        match self.timestamp {
+            NewLayoutValue::Some(timestamp) => Some(side_bets_key(self.initiator, timestamp)),
+            NewLayoutValue::None => None
+        }
+    }
+}
+
+fn side_bets_key(initiator: UserId, timestamp: i64) -> String {
+    format!("{initiator}:{timestamp}")
+}
+
+fn is_timestamp_expired(timestamp: i64, ttl_minutes: u32) -> bool {
+    let now = chrono::Utc::now().timestamp_millis() - TIMESTAMP_MILLIS_SINCE_2024;
+    ttl_minutes > 0 && now - timestamp > i64::from(ttl_minutes) * 60 * 1000
+}
+
+impl std::fmt::Display for BattleCallbackData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.initiator, self.bet, self.timestamp)?;
+        if let Some(target) = self.target {
+            write!(f, ":{target}")?;
+            if let Some(battle_id) = self.rematch_of {
+                write!(f, ":{battle_id}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl CallbackDataWithPrefix for BattleCallbackData {
@@ -78,7 +152,15 @@ impl TryFrom<String> for BattleCallbackData {
         let initiator = callbacks::parse_part(&mut parts, &err, "uid").map(UserId)?;
         let bet: u16 = callbacks::parse_part(&mut parts, &err, "bet")?;
         let timestamp = callbacks::parse_optional_part(&mut parts, &err)?;
-        Ok(Self { initiator, bet, timestamp })
+        let target = parts.next()
+            .map(|uid| uid.parse().map(UserId))
+            .transpose()
+            .map_err(|e| err.parsing_err(e))?;
+        let rematch_of = parts.next()
+            .map(|battle_id| battle_id.parse())
+            .transpose()
+            .map_err(|e| err.parsing_err(e))?;
+        Ok(Self { initiator, bet, timestamp, target, rematch_of })
     }
 }
 
@@ -86,22 +168,58 @@ pub async fn cmd_handler(bot: Bot, msg: Message, cmd: BattleCommands,
                          repos: Repositories, config: AppConfig) -> HandlerResult {
     metrics::CMD_PVP_COUNTER.chat.inc();
 
-    let user = msg.from.as_ref().ok_or(anyhow!("no FROM field in the Penetrate command handler"))?.into();
+    let from = msg.from.as_ref().ok_or(anyhow!("no FROM field in the Penetrate command handler"))?;
     let lang_code = LanguageCode::from_maybe_user(msg.from.as_ref());
-    let params = BattleParams {
-        repos,
-        features: config.features.pvp,
-        chat_id: msg.chat.id.into(),
-        lang_code,
+    let target = match find_target(&repos.users, &msg, cmd.target()).await? {
+        Ok(target) => target,
+        Err(tr_key) => {
+            reply_html!(bot, msg, t!(tr_key, locale = &lang_code));
+            return Ok(())
+        }
     };
-    let (text, keyboard) = buttfight_impl_start(params, user, cmd.bet()).await?;
+    let params = BattleParams::new(repos.clone(), &config, msg.chat.id.into(), lang_code.clone());
+    let (text, keyboard) = buttfight_impl_start(params, from.into(), cmd.bet(), target).await?;
+    let is_challenge = keyboard.is_some();
 
     let mut answer = reply_html(bot, &msg, text);
     answer.reply_markup = keyboard.map(ReplyMarkup::InlineKeyboard);
-    answer.await?;
+    let sent = answer.await?;
+    if is_challenge {
+        repos.challenges.register(&ChallengeMessage::Chat(sent.chat.id, sent.id), &lang_code).await?;
+    }
     Ok(())
 }
 
+/// The target is either the author of the message replied to or a mentioned person.
+/// An error contains the key of a message to reply with.
+async fn find_target(users: &repo::Users, msg: &Message, mention: &str) -> anyhow::Result<Result<Option<UserInfo>, &'static str>> {
+    let text_mention = msg.parse_entities()
+        .unwrap_or_default()
+        .into_iter()
+        .find_map(|entity| match entity.kind() {
+            MessageEntityKind::TextMention { user } => Some(user.clone()),
+            _ => None
+        });
+    let target = if let Some(user) = text_mention {
+        Some(UserInfo::from(user))
+    } else if !mention.is_empty() {
+        match users.find_by_username(mention).await? {
+            Some(user) => Some(user.into()),
+            None => return Ok(Err("commands.penetrate.errors.unknown_target"))
+        }
+    } else {
+        msg.reply_to_message()
+            .and_then(|reply| reply.from.as_ref())
+            .filter(|user| !user.is_bot)
+            .map(UserInfo::from)
+    };
+    match target {
+        Some(target) if msg.from.as_ref().is_some_and(|from| from.id == target.uid) =>
+            Ok(Err("commands.penetrate.errors.same_person")),
+        target => Ok(Ok(target))
+    }
+}
+
 pub async fn cmd_handler_no_args(bot: Bot, msg: Message) -> HandlerResult {
     metrics::CMD_PVP_COUNTER.chat.inc();
 
@@ -111,22 +229,27 @@ pub async fn cmd_handler_no_args(bot: Bot, msg: Message) -> HandlerResult {
 }
 
 pub fn inline_filter(query: InlineQuery) -> bool {
-    let maybe_bet: Result<u32, _> = query.query.parse();
-    maybe_bet.is_ok()
+    parse_bet_and_target(query.query).is_ok()
 }
 
 pub fn chosen_inline_result_filter(result: ChosenInlineResult) -> bool {
-    let maybe_bet: Result<u32, _> = result.query.parse();
-    maybe_bet.is_ok()
+    parse_bet_and_target(result.query).is_ok()
 }
 
-pub async fn inline_handler(bot: Bot, query: InlineQuery) -> HandlerResult {
+pub async fn inline_handler(bot: Bot, query: InlineQuery, repos: Repositories, config: AppConfig) -> HandlerResult {
     metrics::INLINE_COUNTER.invoked();
 
-    let bet: u16 = query.query.parse()?;
+    let (bet, mention) = parse_bet_and_target(query.query.clone())?;
     let lang_code = LanguageCode::from_user(&query.from);
     let name = utils::get_full_name(&query.from);
-    let res = build_inline_keyboard_article_result(query.from.id, &lang_code, &name, bet);
+    let target = if mention.is_empty() {
+        None
+    } else {
+        repos.users.find_by_username(&mention).await?
+            .map(UserInfo::from)
+            .filter(|target| target.uid != query.from.id)
+    };
+    let res = build_inline_keyboard_article_result(query.from.id, &lang_code, &name, bet, target.as_ref(), config.pvp_side_bet);
 
     let mut answer = bot.answer_inline_query(&query.id, vec![res.clone()])
         .is_personal(true);
@@ -137,23 +260,64 @@ pub async fn inline_handler(bot: Bot, query: InlineQuery) -> HandlerResult {
     Ok(())
 }
 
-pub(super) fn build_inline_keyboard_article_result(uid: UserId, lang_code: &LanguageCode, name: &Username, bet: u16) -> InlineQueryResult {
-    log::debug!("Starting a buttfight for {uid} (bet = {bet})...");
+pub(super) fn build_inline_keyboard_article_result(uid: UserId, lang_code: &LanguageCode, name: &Username, bet: u16,
+                                                   target: Option<&UserInfo>, side_bet: u16) -> InlineQueryResult {
+    log::debug!("Starting a buttfight for {uid} (bet = {bet}, target = {:?})...", target.map(|t| t.uid));
 
-    let title = t!("inline.results.titles.penetrate", locale = lang_code, bet = bet);
-    let text = t!("commands.penetrate.results.start", locale = lang_code, name = name.escaped(), bet = bet);
+    let title = match target {
+        Some(target) => t!("inline.results.titles.penetrate_targeted", locale = lang_code, bet = bet, target_name = target.name.value_ref()),
+        None => t!("inline.results.titles.penetrate", locale = lang_code, bet = bet),
+    };
+    let text = start_text(lang_code, name, bet, target);
     let content = InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html));
-    let btn_label = t!("commands.penetrate.button", locale = lang_code);
-    let btn_data = BattleCallbackData::new(uid, bet).to_data_string();
+    let data = BattleCallbackData::new(uid, bet, target.map(|t| t.uid));
     InlineQueryResultArticle::new("penetrate", title, content)
-        .reply_markup(InlineKeyboardMarkup::new(vec![vec![
-            InlineKeyboardButton::callback(btn_label, btn_data)
-        ]]))
+        .reply_markup(challenge_keyboard(lang_code, &data, name, target, side_bet))
         .into()
 }
 
-pub async fn inline_chosen_handler() -> HandlerResult {
+/// The spectators may bet on either side while the challenge is open.
+fn challenge_keyboard(lang_code: &LanguageCode, data: &BattleCallbackData, initiator_name: &Username,
+                      target: Option<&UserInfo>, side_bet: u16) -> InlineKeyboardMarkup {
+    let btn_label = t!("commands.penetrate.button", locale = lang_code);
+    let mut buttons = vec![vec![InlineKeyboardButton::callback(btn_label, data.to_data_string())]];
+    if let (NewLayoutValue::Some(timestamp), true) = (&data.timestamp, side_bet > 0) {
+        let bet_on = |side: BetSide| SideBetCallbackData {
+            initiator: data.initiator,
+            timestamp: *timestamp,
+            side,
+            target: data.target,
+        }.to_data_string();
+        let acceptor_label = match target {
+            Some(target) => t!("commands.penetrate.side_bets.button", locale = lang_code, bet = side_bet, name = target.name.value_ref()),
+            None => t!("commands.penetrate.side_bets.button_acceptor", locale = lang_code, bet = side_bet),
+        };
+        buttons.push(vec![
+            InlineKeyboardButton::callback(t!("commands.penetrate.side_bets.button", locale = lang_code, bet = side_bet,
+                name = initiator_name.value_ref()), bet_on(BetSide::Initiator)),
+            InlineKeyboardButton::callback(acceptor_label, bet_on(BetSide::Acceptor)),
+        ]);
+    }
+    InlineKeyboardMarkup::new(buttons)
+}
+
+/// A targeted challenge mentions the challenged person, so they get a notification.
+fn start_text(lang_code: &LanguageCode, name: &Username, bet: u16, target: Option<&UserInfo>) -> String {
+    match target {
+        Some(target) => t!("commands.penetrate.results.start_targeted", locale = lang_code, name = name.escaped(), bet = bet,
+            target_uid = target.uid, target_name = target.name.escaped()),
+        None => t!("commands.penetrate.results.start", locale = lang_code, name = name.escaped(), bet = bet),
+    }.to_string()
+}
+
+pub async fn inline_chosen_handler(result: ChosenInlineResult, repos: Repositories) -> HandlerResult {
     metrics::INLINE_COUNTER.finished();
+
+    // the identifier is present only because the message has a keyboard
+    if let Some(inline_message_id) = result.inline_message_id {
+        let lang_code = LanguageCode::from_user(&result.from);
+        repos.challenges.register(&ChallengeMessage::Inline(inline_message_id), &lang_code).await?;
+    }
     Ok(())
 }
 
@@ -164,7 +328,39 @@ pub fn callback_filter(query: CallbackQuery) -> bool {
 
 pub async fn callback_handler(bot: Bot, query: CallbackQuery, repos: Repositories, config: AppConfig,
                               mut battle_locker: LockCallbackServiceFacade) -> HandlerResult {
-    let chat_id: ChatIdPartiality = query.message.as_ref()
+    let chat_id = resolve_chat_id(&query, &config);
+    let callback_data = BattleCallbackData::parse(&query)?;
+    if callback_data.initiator == query.from.id {
+        return send_error_callback_answer(bot, query, "commands.penetrate.errors.same_person").await;
+    }
+    if callback_data.target.is_some_and(|target| target != query.from.id) {
+        return send_error_callback_answer(bot, query, "commands.penetrate.errors.not_for_you").await;
+    }
+    if callback_data.is_expired(config.pvp_challenge_ttl_minutes) {
+        return send_error_callback_answer(bot, query, "commands.penetrate.errors.expired").await;
+    }
+    let _battle_guard = match battle_locker.try_lock(&callback_data).await {
+        Some(lock) => lock,
+        None => return send_error_callback_answer(bot, query, "commands.penetrate.errors.battle_already_in_progress").await
+    };
+
+    let params = BattleParams::new(repos.clone(), &config, chat_id, LanguageCode::from_user(&query.from));
+    let attack_result = buttfight_impl_attack(params, callback_data.initiator, query.from.clone().into(), callback_data.bet,
+                                              Some(&callback_data)).await?
+        .callback;
+    if let (CallbackResult::EditMessage(..), Some(message)) = (&attack_result, challenge_message(&query)) {
+        repos.challenges.remove(&message).await
+            .inspect_err(|e| log::error!("couldn't remove the accepted challenge: {e}"))
+            .ok();
+    }
+    attack_result.apply(bot, query).await?;
+
+    metrics::CMD_PVP_COUNTER.inline.inc();
+    Ok(())
+}
+
+fn resolve_chat_id(query: &CallbackQuery, config: &AppConfig) -> ChatIdPartiality {
+    query.message.as_ref()
         .map(|msg| msg.chat().id)
         .or_else(|| config.features.chats_merging
             .then_some(query.inline_message_id.as_ref())
@@ -176,37 +372,334 @@ pub async fn callback_handler(bot: Bot, query: CallbackQuery, repos: Repositorie
             .map(|info| ChatId(info.chat_id))
         )
         .map(ChatIdPartiality::from)
-        .unwrap_or(ChatIdPartiality::from(query.chat_instance.clone()));
+        .unwrap_or(ChatIdPartiality::from(query.chat_instance.clone()))
+}
 
-    let callback_data = BattleCallbackData::parse(&query)?;
-    if callback_data.initiator == query.from.id {
-        return send_error_callback_answer(bot, query, "commands.penetrate.errors.same_person").await;
+fn challenge_message(query: &CallbackQuery) -> Option<ChallengeMessage> {
+    query.message.as_ref()
+        .map(|msg| ChallengeMessage::Chat(msg.chat().id, msg.id()))
+        .or_else(|| query.inline_message_id.clone().map(ChallengeMessage::Inline))
+}
+
+/// Periodically replaces the keyboards of stale challenges with a notice, so they can't be accepted anymore.
+pub async fn expire_challenges(bot: Bot, challenges: repo::BattleChallenges, ttl_minutes: u32) {
+    if ttl_minutes == 0 {
+        return
     }
-    let _battle_guard = match battle_locker.try_lock(&callback_data) {
-        Some(lock) => lock,
-        None => return send_error_callback_answer(bot, query, "commands.penetrate.errors.battle_already_in_progress").await
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let expired = match challenges.take_expired(ttl_minutes).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                log::error!("couldn't take the expired challenges: {e}");
+                continue
+            }
+        };
+        for ExpiredChallenge { message, lang_code } in expired {
+            let text = t!("commands.penetrate.results.expired", locale = &lang_code).to_string();
+            let res = match &message {
+                ChallengeMessage::Chat(chat_id, message_id) => bot.edit_message_text(*chat_id, *message_id, text)
+                    .await.map(|_| ()),
+                ChallengeMessage::Inline(inline_message_id) => bot.edit_message_text_inline(inline_message_id, text)
+                    .await.map(|_| ()),
+            };
+            if let Err(e) = res {
+                log::warn!("couldn't mark the challenge in {message:?} as expired: {e}");
+            }
+        }
+    }
+}
+
+#[inline]
+pub fn mercy_callback_filter(query: CallbackQuery) -> bool {
+    MercyCallbackData::check_prefix(query)
+}
+
+pub async fn mercy_callback_handler(bot: Bot, query: CallbackQuery, repos: Repositories, config: AppConfig) -> HandlerResult {
+    let chat_id = resolve_chat_id(&query, &config);
+    let callback_data = MercyCallbackData::parse(&query)?;
+    let result = show_mercy_impl(&repos, &query, &chat_id.kind(), callback_data).await?;
+    result.apply(bot, query).await?;
+    Ok(())
+}
+
+async fn show_mercy_impl(repos: &Repositories, query: &CallbackQuery, chat_id: &ChatIdKind,
+                         data: MercyCallbackData) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    if query.from.id != data.winner {
+        return Ok(CallbackResult::ShowError(t!("commands.penetrate.mercy.errors.not_winner", locale = &lang_code).to_string()))
+    }
+
+    let request = MercyRequest {
+        battle_id: data.battle_id,
+        winner: data.winner,
+        battle_timestamp: data.timestamp,
+    };
+    let result = match repos.pvp_stats.show_mercy(chat_id, &request).await {
+        Ok(MercyResult { loser, amount, loser_level, mercies_shown }) => {
+            metrics::CMD_MERCY_COUNTER.inc();
+            let loser_name = repos.users.get(loser).await?
+                .ok_or(anyhow!("the pardoned loser must be present in the database!"))?
+                .name;
+            let text = t!("commands.penetrate.mercy.shown", locale = &lang_code,
+                winner_name = utils::get_full_name(&query.from).escaped(), loser_name = loser_name.escaped(),
+                amount = Tenths::from(amount).format(&lang_code), level = Tenths::from(loser_level).format(&lang_code),
+                mercies = mercies_shown);
+            CallbackResult::EditMessage(text.to_string(), None)
+        }
+        Err(MercyError::Other(e)) => Err(e)?,
+        Err(e) => CallbackResult::ShowError(t!(&format!("commands.penetrate.mercy.errors.{e}"), locale = &lang_code).to_string())
     };
+    Ok(result)
+}
+
+#[derive(derive_more::Display)]
+#[display("{battle_id}:{winner}:{timestamp}")]
+pub(crate) struct MercyCallbackData {
+    /// The amounts to return are taken from the log of battles.
+    battle_id: i64,
+    winner: UserId,
+    timestamp: i64,
+}
+
+impl CallbackDataWithPrefix for MercyCallbackData {
+    fn prefix() -> &'static str {
+        "mercy"
+    }
+}
 
-    let params = BattleParams {
-        repos,
-        features: config.features.pvp,
-        lang_code: LanguageCode::from_user(&query.from),
-        chat_id: chat_id.clone(),
+impl TryFrom<String> for MercyCallbackData {
+    type Error = callbacks::InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let battle_id = callbacks::parse_part(&mut parts, &err, "battle_id")?;
+        let winner = callbacks::parse_part(&mut parts, &err, "winner").map(UserId)?;
+        let timestamp = callbacks::parse_part(&mut parts, &err, "timestamp")?;
+        Ok(Self { battle_id, winner, timestamp })
+    }
+}
+
+#[inline]
+pub fn rematch_callback_filter(query: CallbackQuery) -> bool {
+    RematchCallbackData::check_prefix(query)
+}
+
+pub async fn rematch_callback_handler(bot: Bot, query: CallbackQuery, repos: Repositories, config: AppConfig) -> HandlerResult {
+    let chat_id = resolve_chat_id(&query, &config);
+    let callback_data = RematchCallbackData::parse(&query)?;
+    let result = rematch_impl(&repos, &query, &chat_id.kind(), callback_data).await?;
+    result.apply(bot, query).await?;
+    Ok(())
+}
+
+/// Replaces the rematch buttons with a challenge for the opponent, keeping the result of the battle and the mercy button.
+async fn rematch_impl(repos: &Repositories, query: &CallbackQuery, chat_id: &ChatIdKind,
+                      data: RematchCallbackData) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let Some(opponent) = data.opponent_of(query.from.id) else {
+        return Ok(CallbackResult::ShowError(t!("commands.penetrate.rematch.errors.not_participant", locale = &lang_code).to_string()))
     };
-    let attack_result = buttfight_impl_attack(params, callback_data.initiator, query.from.clone().into(), callback_data.bet).await?;
-    attack_result.apply(bot, query).await?;
+    if !repos.hemoroids.check_hemoroid(chat_id, query.from.id, Tenths::from_cm(data.bet.into())).await? {
+        return Ok(CallbackResult::ShowError(t!("commands.penetrate.errors.not_enough.initiator", locale = &lang_code).to_string()))
+    }
+    metrics::CMD_REMATCH_COUNTER.inc();
 
-    metrics::CMD_PVP_COUNTER.inline.inc();
+    let btn_label = t!("commands.penetrate.rematch.accept", locale = &lang_code,
+        name = utils::get_full_name(&query.from).value_ref(), bet = data.bet);
+    let btn_data = BattleCallbackData::rematch(query.from.id, data.bet, opponent, data.battle_id).to_data_string();
+    let mut buttons = vec![vec![InlineKeyboardButton::callback(btn_label, btn_data)]];
+    buttons.extend(mercy_rows(query));
+    // the message has become a challenge again, so it expires like the others
+    if let Some(message) = challenge_message(query) {
+        repos.challenges.register(&message, &lang_code).await?;
+    }
+    Ok(CallbackResult::EditKeyboard(InlineKeyboardMarkup::new(buttons)))
+}
+
+/// Inline messages aren't sent along with callback queries, so the mercy button survives only in ordinary chats.
+fn mercy_rows(query: &CallbackQuery) -> Vec<Vec<InlineKeyboardButton>> {
+    query.message.as_ref()
+        .and_then(|msg| msg.regular_message())
+        .and_then(|msg| msg.reply_markup())
+        .map(|keyboard| keyboard.inline_keyboard.iter()
+            .filter(|row| row.iter().any(|btn| matches!(&btn.kind,
+                InlineKeyboardButtonKind::CallbackData(data) if data.starts_with(MercyCallbackData::prefix()))))
+            .cloned()
+            .collect())
+        .unwrap_or_default()
+}
+
+fn rematch_keyboard_row(lang_code: &LanguageCode, battle_id: i64, players: (UserId, UserId), bet: u16) -> Vec<InlineKeyboardButton> {
+    let same_bet = RematchCallbackData { battle_id, players, bet };
+    let double_bet = RematchCallbackData { bet: bet.saturating_mul(2), ..same_bet };
+    vec![
+        InlineKeyboardButton::callback(t!("commands.penetrate.rematch.button", locale = lang_code, bet = same_bet.bet),
+            same_bet.to_data_string()),
+        InlineKeyboardButton::callback(t!("commands.penetrate.rematch.double", locale = lang_code, bet = double_bet.bet),
+            double_bet.to_data_string()),
+    ]
+}
+
+#[derive(derive_more::Display)]
+#[display("{battle_id}:{}:{}:{bet}", players.0, players.1)]
+pub(crate) struct RematchCallbackData {
+    battle_id: i64,
+    /// Only these two can press the button.
+    players: (UserId, UserId),
+    bet: u16,
+}
+
+impl RematchCallbackData {
+    fn opponent_of(&self, uid: UserId) -> Option<UserId> {
+        match self.players {
+            (first, second) if first == uid => Some(second),
+            (first, second) if second == uid => Some(first),
+            _ => None
+        }
+    }
+}
+
+impl CallbackDataWithPrefix for RematchCallbackData {
+    fn prefix() -> &'static str {
+        "rematch"
+    }
+}
+
+impl TryFrom<String> for RematchCallbackData {
+    type Error = callbacks::InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let battle_id = callbacks::parse_part(&mut parts, &err, "battle_id")?;
+        let first = callbacks::parse_part(&mut parts, &err, "first").map(UserId)?;
+        let second = callbacks::parse_part(&mut parts, &err, "second").map(UserId)?;
+        let bet = callbacks::parse_part(&mut parts, &err, "bet")?;
+        Ok(Self { battle_id, players: (first, second), bet })
+    }
+}
+
+#[inline]
+pub fn side_bet_callback_filter(query: CallbackQuery) -> bool {
+    SideBetCallbackData::check_prefix(query)
+}
+
+pub async fn side_bet_callback_handler(bot: Bot, query: CallbackQuery, repos: Repositories, config: AppConfig) -> HandlerResult {
+    let chat_id = resolve_chat_id(&query, &config);
+    let callback_data = SideBetCallbackData::parse(&query)?;
+    match side_bet_impl(&repos, &config, &query, &chat_id.kind(), callback_data).await? {
+        Ok(text) => {
+            bot.answer_callback_query(query.id)
+                .text(text)
+                .await?;
+        }
+        Err(result) => result.apply(bot, query).await?
+    }
     Ok(())
 }
 
+/// Returns either the text of a notification about the accepted bet or an error to show.
+async fn side_bet_impl(repos: &Repositories, config: &AppConfig, query: &CallbackQuery, chat_id: &ChatIdKind,
+                       data: SideBetCallbackData) -> anyhow::Result<Result<String, CallbackResult>> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let error = |key: &str| Err(CallbackResult::ShowError(t!(key, locale = &lang_code).to_string()));
+    if query.from.id == data.initiator || data.target.is_some_and(|target| target == query.from.id) {
+        return Ok(error("commands.penetrate.side_bets.errors.participant"))
+    }
+    if config.pvp_side_bet == 0 || is_timestamp_expired(data.timestamp, config.pvp_challenge_ttl_minutes) {
+        return Ok(error("commands.penetrate.errors.expired"))
+    }
+    let amount = Tenths::from_cm(config.pvp_side_bet.into());
+    if !repos.hemoroids.check_hemoroid(chat_id, query.from.id, amount).await? {
+        return Ok(error("commands.penetrate.side_bets.errors.not_enough"))
+    }
+
+    let key = side_bets_key(data.initiator, data.timestamp);
+    let result = match repos.side_bets.place(chat_id, &key, query.from.id, data.side, amount).await {
+        Ok(pools) => {
+            metrics::CMD_SIDE_BET_COUNTER.inc();
+            Ok(t!("commands.penetrate.side_bets.placed", locale = &lang_code, bet = config.pvp_side_bet,
+                side = t!(&format!("commands.penetrate.side_bets.sides.{}", data.side), locale = &lang_code),
+                initiator_pool = Tenths::from(pools.initiator as i32).format(&lang_code),
+                acceptor_pool = Tenths::from(pools.acceptor as i32).format(&lang_code)).to_string())
+        }
+        Err(SideBetError::Other(e)) => Err(e)?,
+        Err(e) => error(&format!("commands.penetrate.side_bets.errors.{e}"))
+    };
+    Ok(result)
+}
+
+#[derive(derive_more::Display)]
+#[display("{initiator}:{timestamp}:{side}{}", target.map(|uid| format!(":{uid}")).unwrap_or_default())]
+pub(crate) struct SideBetCallbackData {
+    initiator: UserId,
+    // the same as the one of the challenge
+    timestamp: i64,
+    side: BetSide,
+    // the participants can't bet on their own battle
+    target: Option<UserId>,
+}
+
+impl CallbackDataWithPrefix for SideBetCallbackData {
+    fn prefix() -> &'static str {
+        "sbet"
+    }
+}
+
+impl TryFrom<String> for SideBetCallbackData {
+    type Error = callbacks::InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let initiator = callbacks::parse_part(&mut parts, &err, "initiator").map(UserId)?;
+        let timestamp = callbacks::parse_part(&mut parts, &err, "timestamp")?;
+        let side = callbacks::parse_part(&mut parts, &err, "side")?;
+        let target = parts.next()
+            .map(|uid| uid.parse().map(UserId))
+            .transpose()
+            .map_err(|e| err.parsing_err(e))?;
+        Ok(Self { initiator, timestamp, side, target })
+    }
+}
+
 pub(crate) struct BattleParams {
     repos: Repositories,
     features: BattlesFeatureToggles,
+    clench: ClenchConfig,
+    damage: DamageProfile,
+    coins_per_win: u16,
+    side_bet: u16,
+    limits: BattleLimitsConfig,
     chat_id: ChatIdPartiality,
     lang_code: LanguageCode,
 }
 
+impl BattleParams {
+    pub(crate) fn new(repos: Repositories, config: &AppConfig, chat_id: ChatIdPartiality, lang_code: LanguageCode) -> Self {
+        Self {
+            repos,
+            features: config.features.pvp,
+            clench: config.clench,
+            damage: config.damage.profile(&chat_id.kind()).clone(),
+            coins_per_win: config.shop.coins_per_win,
+            side_bet: config.pvp_side_bet,
+            limits: config.battle_limits,
+            chat_id,
+            lang_code,
+        }
+    }
+}
+
+pub(crate) struct AttackResult {
+    pub callback: CallbackResult,
+    /// The winner of the battle or, if it didn't take place, the participant who was able to fight.
+    pub winner: UserId,
+}
+
 #[derive(Clone)]
 pub(crate) struct UserInfo {
     uid: UserId,
@@ -237,6 +730,15 @@ impl From<repo::User> for UserInfo {
     }
 }
 
+impl From<&repo::TournamentPlayer> for UserInfo {
+    fn from(value: &repo::TournamentPlayer) -> Self {
+        Self {
+            uid: value.uid,
+            name: value.name.clone()
+        }
+    }
+}
+
 #[allow(clippy::from_over_into)]
 impl Into<UserId> for UserInfo {
     fn into(self) -> UserId {
@@ -244,17 +746,15 @@ impl Into<UserId> for UserInfo {
     }
 }
 
-pub(crate) async fn buttfight_impl_start(p: BattleParams, initiator: UserInfo, bet: u16) -> anyhow::Result<(String, Option<InlineKeyboardMarkup>)> {
-    let enough = p.repos.hemoroids.check_hemoroid(&p.chat_id.kind(), initiator.uid, bet).await?;
+pub(crate) async fn buttfight_impl_start(p: BattleParams, initiator: UserInfo, bet: u16,
+                                        target: Option<UserInfo>) -> anyhow::Result<(String, Option<InlineKeyboardMarkup>)> {
+    let enough = p.repos.hemoroids.check_hemoroid(&p.chat_id.kind(), initiator.uid, Tenths::from_cm(bet.into())).await?;
     log::debug!("Starting a buttfight for {} in the chat with id = {} (bet = {bet}, enough = {enough})...", initiator.uid, p.chat_id);
 
     let data = if enough {
-        let text = t!("commands.penetrate.results.start", locale = &p.lang_code, name = initiator.name.escaped(), bet = bet).to_string();
-        let btn_label = t!("commands.penetrate.button", locale = &p.lang_code);
-        let btn_data = BattleCallbackData::new(initiator.uid, bet).to_data_string();
-        let keyboard = InlineKeyboardMarkup::new(vec![vec![
-            InlineKeyboardButton::callback(btn_label, btn_data)
-        ]]);
+        let text = start_text(&p.lang_code, &initiator.name, bet, target.as_ref());
+        let data = BattleCallbackData::new(initiator.uid, bet, target.as_ref().map(|t| t.uid));
+        let keyboard = challenge_keyboard(&p.lang_code, &data, &initiator.name, target.as_ref(), p.side_bet);
         (text, Some(keyboard))
     } else {
         (t!("commands.penetrate.errors.not_enough.initiator", locale = &p.lang_code).to_string(), None)
@@ -262,11 +762,27 @@ pub(crate) async fn buttfight_impl_start(p: BattleParams, initiator: UserInfo, b
     Ok(data)
 }
 
-async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: UserInfo, bet: u16) -> anyhow::Result<CallbackResult> {
+/// The challenge is absent if the battle was started by something else, e.g. a tournament.
+pub(crate) async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: UserInfo, bet: u16,
+                                         challenge: Option<&BattleCallbackData>) -> anyhow::Result<AttackResult> {
     let chat_id_kind = p.chat_id.kind();
+    let rules = rules::of(p.repos.chats.get_battle_rules(&chat_id_kind).await?);
+    let rematch_of = challenge.and_then(|data| data.rematch_of);
+    let side_bets_key = challenge.and_then(BattleCallbackData::side_bets_key);
+    // Tournaments are organized by the administrators, so only the challenges are limited
+    let limits = challenge.map(|_| &p.limits);
+    if let Some(limits) = limits {
+        if let Some(explanation) = check_battle_limits(&p, limits, initiator, acceptor.uid).await? {
+            return Ok(AttackResult {
+                callback: CallbackResult::ShowError(explanation),
+                winner: initiator,
+            })
+        }
+    }
+    let max_level = Tenths::from_cm(bet.into());
     let (enough_initiator, enough_acceptor) = join!(
-       p.repos.hemoroids.check_hemoroid(&chat_id_kind, initiator, bet),
-       p.repos.hemoroids.check_hemoroid(&chat_id_kind, acceptor.uid, if p.features.check_acceptor_length { bet } else { 0 }),
+       p.repos.hemoroids.check_hemoroid(&chat_id_kind, initiator, max_level),
+       p.repos.hemoroids.check_hemoroid(&chat_id_kind, acceptor.uid, if p.features.check_acceptor_length { max_level } else { Tenths::from(0) }),
     );
     let (enough_initiator, enough_acceptor) = (enough_initiator?, enough_acceptor?);
 
@@ -274,9 +790,13 @@ async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: Use
         p.chat_id, acceptor.uid);
 
     let result = if enough_initiator && enough_acceptor {
+        let damage_multiplier = p.repos.events.get_modifiers().await
+            .inspect_err(|e| log::error!("couldn't get the modifiers of the active events: {e}"))
+            .unwrap_or_default()
+            .battle_damage;
+
         // Randomly assign top and bottom roles
-        let mut rng = rand::thread_rng();
-        let initiator_is_top = rng.gen_bool(0.5);
+        let initiator_is_top = rand::thread_rng().gen_bool(0.5);
         
         // Get user info
         let initiator_info = get_user_info(&p.repos.users, initiator, &acceptor).await?;
@@ -288,28 +808,109 @@ async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: Use
             (acceptor.uid, acceptor_info.name.escaped(), initiator, initiator_info.name.escaped())
         };
         
-        // Calculate damage
-        let top_damage = calculate_top_damage(&mut rng);
-        let bottom_damage = calculate_bottom_damage(&mut rng);
-        
-        // Apply damage to the users
-        let (top_result, bottom_result) = p.repos.hemoroids.penetrate(&p.chat_id, top_id, bottom_id, top_damage, bottom_damage).await?;
+        // Rules without swelling leave the shields and cushions for the next battle
+        let Protection { top_damage, bottom_damage, text: shields, shielded } = match rules.roll_damage(&p.damage) {
+            Some((top_damage, bottom_damage)) => {
+                let top = (top_id, top_name.as_str(), scale_damage(top_damage, damage_multiplier));
+                let bottom = (bottom_id, bottom_name.as_str(), scale_damage(bottom_damage, damage_multiplier));
+                protect(&p, top, bottom).await?
+            }
+            None => Protection { top_damage: 0, bottom_damage: 0, text: String::new(), shielded: Vec::new() }
+        };
         
-        // Determine who received less damage (the winner)
+        // The winner takes the bet
+        let top_wins = rules.top_wins(top_damage, bottom_damage);
+        let battle = NewBattle {
+            initiator,
+            top: top_id,
+            bottom: bottom_id,
+            top_damage, bottom_damage, bet,
+            winner: if top_wins { top_id } else { bottom_id },
+            rematch_of,
+        };
+        let bet_escrow = BattleBet {
+            initiator,
+            acceptor: acceptor.uid,
+            winner: battle.winner,
+            amount: max_level,
+            check_acceptor: p.features.check_acceptor_length,
+        };
+        let PenetrationResult { battle_id, top: top_result, bottom: bottom_result, award, previous_pair_battles, withheld, side_bets } =
+            match p.repos.hemoroids.penetrate(&p.chat_id, &battle, bet_escrow, &shielded, side_bets_key.as_deref(), limits).await {
+                Ok(result) => result,
+                Err(PenetrationError::NotEnoughInitiator) => return Ok(AttackResult {
+                    callback: CallbackResult::EditMessage(t!("commands.penetrate.errors.not_enough.initiator", locale = &p.lang_code).to_string(), None),
+                    winner: acceptor.uid,
+                }),
+                Err(PenetrationError::NotEnoughAcceptor) => return Ok(AttackResult {
+                    callback: CallbackResult::ShowError(t!("commands.penetrate.errors.not_enough.acceptor", locale = &p.lang_code).to_string()),
+                    winner: initiator,
+                }),
+                Err(PenetrationError::LimitReached(limit)) => return Ok(AttackResult {
+                    callback: CallbackResult::ShowError(limit_explanation(&p.lang_code, limit)),
+                    winner: initiator,
+                }),
+                Err(PenetrationError::Other(e)) => Err(e)?,
+            };
+        let reward_ratio = p.limits.reward_ratio(previous_pair_battles);
+
         let (winner_id, winner_name, winner_damage, winner_level, loser_id, loser_name, loser_damage, loser_level) = 
-            if top_damage.abs() < bottom_damage.abs() {
-                (top_id, top_name, top_damage, top_result.new_protrusion_level, 
-                 bottom_id, bottom_name, bottom_damage, bottom_result.new_protrusion_level)
+            if top_wins {
+                (top_id, top_name.clone(), top_damage, top_result.new_protrusion_level, 
+                 bottom_id, bottom_name.clone(), bottom_damage, bottom_result.new_protrusion_level)
             } else {
-                (bottom_id, bottom_name, bottom_damage, bottom_result.new_protrusion_level, 
-                 top_id, top_name, top_damage, top_result.new_protrusion_level)
+                (bottom_id, bottom_name.clone(), bottom_damage, bottom_result.new_protrusion_level, 
+                 top_id, top_name.clone(), top_damage, top_result.new_protrusion_level)
             };
+        let withheld = if withheld > 0 {
+            format!("\n\n{}", t!("commands.penetrate.results.withheld", locale = &p.lang_code,
+                payout = Tenths::from(withheld).format(&p.lang_code)))
+        } else {
+            String::new()
+        };
+        let diminished = if reward_ratio < 1.0 {
+            format!("\n\n{}", t!("commands.penetrate.results.diminished", locale = &p.lang_code,
+                battles = previous_pair_battles, award = award.format(&p.lang_code)))
+        } else {
+            String::new()
+        };
+        let spectators = side_bets.iter()
+            .filter(|side_bet| side_bet.payout > 0)
+            .map(|side_bet| t!("commands.penetrate.side_bets.winner", locale = &p.lang_code,
+                name = side_bet.name.escaped(), payout = Tenths::from(side_bet.payout).format(&p.lang_code)).to_string())
+            .collect::<Vec<String>>();
+        let spectators = if spectators.is_empty() {
+            String::new()
+        } else {
+            format!("\n\n{}", t!("commands.penetrate.side_bets.winners", locale = &p.lang_code, winners = spectators.join(", ")))
+        };
         
-        let battle_stats = p.repos.pvp_stats.send_battle_result(&p.chat_id.kind(), winner_id, loser_id, bet).await
+        let series = match rematch_of {
+            Some(_) => p.repos.battles.get_series_score(battle_id).await
+                .inspect_err(|e| log::error!("couldn't get the series score of the battle {battle_id}: {e}"))
+                .ok()
+                .flatten(),
+            None => None
+        };
+        let series = series
+            .map(|score| format!("\n\n{}", t!("commands.penetrate.rematch.series", locale = &p.lang_code,
+                number = score.rematch,
+                winner_name = winner_name, winner_wins = score.wins_of(winner_id),
+                loser_name = loser_name, loser_wins = score.wins_of(loser_id))))
+            .unwrap_or_default();
+
+        let battle_stats = p.repos.pvp_stats.send_battle_result(&p.chat_id.kind(), winner_id, loser_id, award).await
             .inspect_err(|e| log::error!("couldn't send users' battle statistics for winner ({}) and loser ({}): {}", winner_id, loser_id, e))
-            .ok()
+            .ok();
+        let rating = battle_stats.as_ref()
+            .map(|stats| format!("\n\n{}", t!("commands.penetrate.results.rating", locale = &p.lang_code,
+                winner_name = winner_name, winner_rating = stats.winner.rating,
+                loser_name = loser_name, loser_rating = stats.loser.rating,
+                change = stats.rating_change)))
+            .unwrap_or_default();
+        let battle_stats = battle_stats
             .filter(|_| p.features.show_stats)
-            .map(|BattleStats { winner: winner_stats, loser: loser_stats }| {
+            .map(|BattleStats { winner: winner_stats, loser: loser_stats, .. }| {
                 let mut stats_str = t!("commands.penetrate.results.stats.text", locale = &p.lang_code,
                     winner_win_rate = winner_stats.win_rate_formatted(), loser_win_rate = loser_stats.win_rate_formatted(),
                     winner_win_streak = winner_stats.win_streak_current, winner_win_streak_max = winner_stats.win_streak_max,
@@ -324,11 +925,46 @@ async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: Use
             .map(|s| format!("\n\n{s}"))
             .unwrap_or_default();
         
-        // Generate battle description
-        let outcome = generate_battle_outcome(&p.lang_code, top_id, top_name, top_damage, 
-                                            bottom_id, bottom_name, bottom_damage,
-                                            winner_id, winner_name, loser_name,
-                                            bet);
+        let coins_per_win = (f32::from(p.coins_per_win) * reward_ratio).round() as u16;
+        let coins = if coins_per_win > 0 {
+            p.repos.shop.award_coins(&chat_id_kind, winner_id, coins_per_win).await
+                .inspect_err(|e| log::error!("couldn't award coins to the winner ({winner_id}): {e}"))
+                .ok()
+                .map(|total| format!("\n\n{}", t!("commands.penetrate.results.coins", locale = &p.lang_code,
+                    name = winner_name, coins = coins_per_win, total = total)))
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        let (winner_achievements, loser_achievements) = join!(
+            achievements::check_and_announce(&p.repos, &chat_id_kind, winner_id, &winner_name, &p.lang_code),
+            achievements::check_and_announce(&p.repos, &chat_id_kind, loser_id, &loser_name, &p.lang_code),
+        );
+
+        let mercy_row = (mercy_amount(award.value(), winner_damage, loser_damage) > 0)
+            .then(|| {
+                let data = MercyCallbackData {
+                    battle_id,
+                    winner: winner_id,
+                    timestamp: chrono::Utc::now().timestamp_millis() - TIMESTAMP_MILLIS_SINCE_2024,
+                };
+                let btn_label = t!("commands.penetrate.mercy.button", locale = &p.lang_code);
+                vec![InlineKeyboardButton::callback(btn_label, data.to_data_string())]
+            });
+        let rematch_row = rematch_keyboard_row(&p.lang_code, battle_id, (initiator, acceptor.uid), bet);
+        let keyboard = Some(InlineKeyboardMarkup::new(std::iter::once(rematch_row).chain(mercy_row)));
+
+        let outcome = rules.describe(&p.lang_code, &BattleOutcome {
+            top_name: &top_name,
+            top_damage,
+            bottom_name: &bottom_name,
+            bottom_damage,
+            winner_name: &winner_name,
+            winner_level,
+            loser_level,
+            bet,
+        });
         
         // Create position information if available
         let positions = if let (Some(winner_pos), Some(loser_pos)) = (top_result.pos_in_top, bottom_result.pos_in_top) {
@@ -339,90 +975,138 @@ async fn buttfight_impl_attack(p: BattleParams, initiator: UserId, acceptor: Use
             String::new()
         };
         
-        CallbackResult::EditMessage(format!("{outcome}{positions}{battle_stats}"), None)
+        let event_banner = utils::event_banner(&p.repos.events, &p.lang_code).await;
+
+        let text = format!("{outcome}{shields}{withheld}{diminished}{spectators}{series}{positions}{rating}{battle_stats}{coins}{winner_achievements}{loser_achievements}{event_banner}");
+        AttackResult {
+            callback: CallbackResult::EditMessage(text, keyboard),
+            winner: winner_id,
+        }
     } else if enough_acceptor {
         let text = t!("commands.penetrate.errors.not_enough.initiator", locale = &p.lang_code).to_string();
-        CallbackResult::EditMessage(text, None)
+        AttackResult {
+            callback: CallbackResult::EditMessage(text, None),
+            winner: acceptor.uid,
+        }
     } else {
         let text = t!("commands.penetrate.errors.not_enough.acceptor", locale = &p.lang_code).to_string();
-        CallbackResult::ShowError(text)
+        AttackResult {
+            callback: CallbackResult::ShowError(text),
+            winner: initiator,
+        }
     };
     Ok(result)
 }
 
-fn calculate_top_damage(rng: &mut impl Rng) -> i32 {
-    // Top typically receives minor damage (0.1-0.4cm)
-    // Positive = bad (swelling), negative = good (shrinking)
-    let critical_event = rng.gen_bool(0.1); // 10% chance of critical event
-    
-    if critical_event {
-        if rng.gen_bool(0.4) { // 4% overall chance of being lucky
-            -5 // Lucky! Significant healing
-        } else { // 6% overall chance of complications
-            rng.gen_range(5..10) // Complications! Major swelling
-        }
-    } else {
-        rng.gen_range(1..5) // Normal minor damage
+/// Returns the number of the battles of the pair today or an explanation why one more battle can't take place.
+/// Fails fast before the battle is fought; the limits are checked again along with its results.
+async fn check_battle_limits(p: &BattleParams, limits: &BattleLimitsConfig, initiator: UserId, acceptor: UserId) -> anyhow::Result<Option<String>> {
+    if limits.pair_daily == 0 && limits.user_daily == 0 {
+        return Ok(None)
     }
+    let today = p.repos.battles.count_today(&p.chat_id.kind(), initiator, acceptor).await?;
+    Ok(today.exceeded(limits).map(|limit| limit_explanation(&p.lang_code, limit)))
 }
 
-fn calculate_bottom_damage(rng: &mut impl Rng) -> i32 {
-    // Bottom typically receives major damage (0.4-1.8cm)
-    // Positive = bad (swelling), negative = good (shrinking)
-    let critical_event = rng.gen_bool(0.15); // 15% chance of critical event
-    
-    if critical_event {
-        if rng.gen_bool(0.3) { // 4.5% overall chance of being lucky
-            -10 // Lucky! Significant healing
-        } else { // 10.5% overall chance of severe trauma
-            rng.gen_range(18..25) // Trauma! Severe swelling
-        }
+pub(crate) fn limit_explanation(lang_code: &LanguageCode, limit: DailyLimit) -> String {
+    let (tr_key, limit) = match limit {
+        DailyLimit::Pair(limit) => ("commands.penetrate.errors.limits.pair", limit),
+        DailyLimit::Acceptor(limit) => ("commands.penetrate.errors.limits.acceptor", limit),
+        DailyLimit::Initiator(limit) => ("commands.penetrate.errors.limits.initiator", limit),
+    };
+    t!(tr_key, locale = lang_code, limit = limit).to_string()
+}
+
+/// Global events may aggravate or soften the swelling, but never the luck of a participant.
+pub(crate) fn scale_damage(damage: i32, multiplier: f32) -> i32 {
+    if damage > 0 {
+        (damage as f32 * multiplier.max(0.0)).round() as i32
     } else {
-        rng.gen_range(4..19) // Normal major damage
+        damage
     }
 }
 
-fn generate_battle_outcome(lang_code: &LanguageCode, 
-                           top_id: UserId, top_name: String, top_damage: i32,
-                           bottom_id: UserId, bottom_name: String, bottom_damage: i32,
-                           winner_id: UserId, winner_name: String, loser_name: String,
-                           bet: u16) -> String {
-    // Create battle description
-    let top_outcome = if top_damage > 0 {
-        t!("commands.penetrate.results.top_swelled", 
-          locale = lang_code,
-          name = top_name,
-          damage = top_damage.abs())
+/// Clenched participants soften the swelling with their shields and cushions. The shields are used up
+/// along with the results of the battle, so they're lost only if it takes place.
+/// Returns the damage left for the top and the bottom, the lines about the used protection and the shielded participants.
+async fn protect(p: &BattleParams, (top_id, top_name, top_damage): (UserId, &str, i32),
+                 (bottom_id, bottom_name, bottom_damage): (UserId, &str, i32)) -> anyhow::Result<Protection> {
+    let chat_id_kind = p.chat_id.kind();
+    let (top_shielded, bottom_shielded) = join!(
+        p.repos.clenches.has_shield(&chat_id_kind, top_id),
+        p.repos.clenches.has_shield(&chat_id_kind, bottom_id),
+    );
+    let (top_damage, top_absorbed) = absorb_damage(top_damage, top_shielded?, p.clench.damage_reduction);
+    let (bottom_damage, bottom_absorbed) = absorb_damage(bottom_damage, bottom_shielded?, p.clench.damage_reduction);
+    let (top_damage, top_cushions) = cushion_damage(&p.repos, &chat_id_kind, top_id, top_damage).await;
+    let (bottom_damage, bottom_cushions) = cushion_damage(&p.repos, &chat_id_kind, bottom_id, bottom_damage).await;
+    let cushions = [(top_name, top_cushions), (bottom_name, bottom_cushions)]
+        .into_iter()
+        .filter_map(|(name, cushions)| cushions.map(|(items, absorbed)| t!("commands.penetrate.results.cushion_used",
+            locale = &p.lang_code, name = name, absorbed = Tenths::from(absorbed).format(&p.lang_code),
+            items = items.iter()
+                .map(|item| t!(&format!("items.{item}.name"), locale = &p.lang_code).to_string())
+                .collect::<Vec<String>>()
+                .join(", ")).to_string()));
+    let shields = [(top_name, top_absorbed), (bottom_name, bottom_absorbed)]
+        .into_iter()
+        .filter_map(|(name, absorbed)| absorbed.map(|absorbed| t!("commands.penetrate.results.shield_used",
+            locale = &p.lang_code, name = name, absorbed = Tenths::from(absorbed).format(&p.lang_code)).to_string()))
+        .chain(cushions)
+        .collect::<Vec<String>>();
+    let shields = if shields.is_empty() {
+        String::new()
     } else {
-        t!("commands.penetrate.results.top_improved", 
-          locale = lang_code,
-          name = top_name,
-          improvement = top_damage.abs())
+        format!("\n\n{}", shields.join("\n"))
     };
-    
-    let bottom_outcome = if bottom_damage > 0 {
-        t!("commands.penetrate.results.bottom_swelled", 
-          locale = lang_code,
-          name = bottom_name,
-          damage = bottom_damage.abs())
+    let shielded = [(top_id, top_absorbed), (bottom_id, bottom_absorbed)]
+        .into_iter()
+        .filter_map(|(uid, absorbed)| absorbed.map(|_| uid))
+        .collect();
+    Ok(Protection { top_damage, bottom_damage, text: shields, shielded })
+}
+
+struct Protection {
+    top_damage: i32,
+    bottom_damage: i32,
+    text: String,
+    shielded: Vec<UserId>,
+}
+
+/// Returns the damage left after the shield and, if the shield was used, how much of the swelling it absorbed.
+fn absorb_damage(damage: i32, shielded: bool, reduction: f32) -> (i32, Option<i32>) {
+    if !shielded {
+        return (damage, None)
+    }
+    let absorbed = if damage > 0 {
+        (damage as f32 * reduction.clamp(0.0, 1.0)).round() as i32
     } else {
-        t!("commands.penetrate.results.bottom_improved", 
-          locale = lang_code,
-          name = bottom_name,
-          improvement = bottom_damage.abs())
+        0
     };
-    
-    let winner_msg = t!("commands.penetrate.results.winner", 
-                       locale = lang_code,
-                       name = winner_name,
-                       bet = bet);
-    
-    format!(
-        "{}\n\n{}\n\n{}",
-        top_outcome,
-        bottom_outcome,
-        winner_msg
-    )
+    (damage - absorbed, Some(absorbed))
+}
+
+/// Takes a charge of the active cushions only if there is some swelling to soften.
+/// Returns the damage left and, if any cushion was used, their items and how much of the swelling they absorbed.
+async fn cushion_damage(repos: &Repositories, chat_id: &ChatIdKind, user_id: UserId, damage: i32) -> (i32, Option<(Vec<String>, i32)>) {
+    if damage <= 0 {
+        return (damage, None)
+    }
+    let effects = repos.inventory.consume_effects(chat_id, user_id, EffectTarget::Battle)
+        .await
+        .inspect_err(|e| log::error!("couldn't consume the battle effects of {user_id} in {chat_id}: {e}"))
+        .unwrap_or_default();
+    if effects.is_empty() {
+        return (damage, None)
+    }
+    let absorbed = effects.iter()
+        .map(|effect| effect.value)
+        .sum::<i32>()
+        .clamp(0, damage);
+    let items = effects.into_iter()
+        .map(|effect| effect.item)
+        .collect();
+    (damage - absorbed, Some((items, absorbed)))
 }
 
 async fn get_user_info(users: &repo::Users, user_uid: UserId, acceptor: &UserInfo) -> anyhow::Result<UserInfo> {
@@ -439,3 +1123,102 @@ async fn get_user_info(users: &repo::Users, user_uid: UserId, acceptor: &UserInf
 pub fn new_short_timestamp() -> NewLayoutValue<i64> {
     NewLayoutValue::Some(chrono::Utc::now().timestamp_millis() - TIMESTAMP_MILLIS_SINCE_2024)
 }
+
+#[cfg(test)]
+mod test {
+    use teloxide::types::UserId;
+    use crate::config::BattleLimitsConfig;
+    use crate::handlers::buttfight::{parse_bet_and_target, side_bets_key, BattleCallbackData, RematchCallbackData, SideBetCallbackData};
+    use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, NewLayoutValue};
+    use crate::repo::{BetSide, DailyBattles, DailyLimit};
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let data = BattleCallbackData { initiator: UserId(123), bet: 5, timestamp: NewLayoutValue::Some(42), target: Some(UserId(456)), rematch_of: None };
+        assert_eq!(data.to_data_string(), "btf:123:5:42:456");
+
+        let parsed = BattleCallbackData::try_from("123:5:42:456".to_owned())
+            .expect("targeted battle callback data must be parsed successfully");
+        assert_eq!(parsed.target, Some(UserId(456)));
+
+        let parsed = BattleCallbackData::try_from("123:5:OLDVER".to_owned())
+            .expect("open battle callback data must be parsed successfully");
+        assert_eq!(parsed.initiator, UserId(123));
+        assert_eq!(parsed.target, None);
+
+        let data = BattleCallbackData { rematch_of: Some(789), ..data };
+        assert_eq!(data.to_data_string(), "btf:123:5:42:456:789");
+        let parsed = BattleCallbackData::try_from("123:5:42:456:789".to_owned())
+            .expect("rematch callback data must be parsed successfully");
+        assert_eq!(parsed.rematch_of, Some(789));
+    }
+
+    #[test]
+    fn test_rematch_serialize_and_parse() {
+        let data = RematchCallbackData { battle_id: 789, players: (UserId(123), UserId(456)), bet: 5 };
+        assert_eq!(data.to_data_string(), "rematch:789:123:456:5");
+
+        let parsed = RematchCallbackData::try_from("789:123:456:5".to_owned())
+            .expect("rematch callback data must be parsed successfully");
+        assert_eq!(parsed.battle_id, 789);
+        assert_eq!(parsed.bet, 5);
+        assert_eq!(parsed.opponent_of(UserId(123)), Some(UserId(456)));
+        assert_eq!(parsed.opponent_of(UserId(456)), Some(UserId(123)));
+        assert_eq!(parsed.opponent_of(UserId(42)), None);
+    }
+
+    #[test]
+    fn test_side_bet_serialize_and_parse() {
+        let data = SideBetCallbackData { initiator: UserId(123), timestamp: 42, side: BetSide::Acceptor, target: Some(UserId(456)) };
+        assert_eq!(data.to_data_string(), "sbet:123:42:acceptor:456");
+
+        let parsed = SideBetCallbackData::try_from("123:42:initiator".to_owned())
+            .expect("side bet callback data must be parsed successfully");
+        assert_eq!(parsed.side, BetSide::Initiator);
+        assert_eq!(parsed.target, None);
+
+        let challenge = BattleCallbackData { initiator: UserId(123), bet: 5, timestamp: NewLayoutValue::Some(42), target: None, rematch_of: None };
+        assert_eq!(challenge.side_bets_key(), Some(side_bets_key(parsed.initiator, parsed.timestamp)));
+        let challenge = BattleCallbackData { timestamp: NewLayoutValue::None, ..challenge };
+        assert_eq!(challenge.side_bets_key(), None);
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let data = BattleCallbackData::new(UserId(123), 5, None);
+        assert!(!data.is_expired(1));
+        assert!(!data.is_expired(0));
+
+        let NewLayoutValue::Some(now) = data.timestamp else { unreachable!("a new timestamp must be present") };
+        let data = BattleCallbackData { timestamp: NewLayoutValue::Some(now - 60 * 60 * 1000), ..data };
+        assert!(data.is_expired(30));
+        assert!(!data.is_expired(90));
+
+        let data = BattleCallbackData { timestamp: NewLayoutValue::None, ..data };
+        assert!(data.is_expired(30));
+        assert!(!data.is_expired(0));
+    }
+
+    #[test]
+    fn test_exceeded_limit() {
+        let limits = BattleLimitsConfig { pair_daily: 3, user_daily: 5, pair_reward_decay: 0.5 };
+        let today = DailyBattles { pair: 2, first: 4, second: 4 };
+        assert_eq!(today.exceeded(&limits), None);
+        assert_eq!(DailyBattles { pair: 3, ..today }.exceeded(&limits), Some(DailyLimit::Pair(3)));
+        assert_eq!(DailyBattles { first: 5, second: 5, ..today }.exceeded(&limits), Some(DailyLimit::Acceptor(5)));
+        assert_eq!(DailyBattles { first: 5, ..today }.exceeded(&limits), Some(DailyLimit::Initiator(5)));
+
+        let unlimited = BattleLimitsConfig { pair_daily: 0, user_daily: 0, pair_reward_decay: 1.0 };
+        assert_eq!(DailyBattles { pair: 100, first: 100, second: 100 }.exceeded(&unlimited), None);
+        assert_eq!(unlimited.reward_ratio(100), 1.0);
+        assert_eq!(limits.reward_ratio(0), 1.0);
+        assert_eq!(limits.reward_ratio(2), 0.25);
+    }
+
+    #[test]
+    fn test_parse_bet_and_target() {
+        assert_eq!(parse_bet_and_target("10".to_owned()).ok(), Some((10, String::new())));
+        assert_eq!(parse_bet_and_target(" 10  @user ".to_owned()).ok(), Some((10, "@user".to_owned())));
+        assert!(parse_bet_and_target("@user".to_owned()).is_err());
+    }
+}
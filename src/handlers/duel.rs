@@ -0,0 +1,455 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message};
+use teloxide::requests::Requester;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, ReplyMarkup};
+use callbacks::InvalidCallbackData;
+use crate::{metrics, reply_html, repo};
+use crate::config::AppConfig;
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackResult, HandlerResult, reply_html, utils};
+use crate::handlers::buttfight::{limit_explanation, scale_damage};
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::repo::{BattleBet, ChallengeMessage, ChatIdKind, ChatIdPartiality, Duel, DuelAction, DuelError, DuelStatus, NewBattle, NewDuel, PenetrationError, RoundOutcome};
+
+/// The swelling of the one who lost a round, in tenths of a centimetre.
+const ROUND_LOSS_DAMAGE: i32 = 10;
+/// The swelling of both players in a drawn round.
+const DRAW_DAMAGE: i32 = 3;
+const ACTIONS: [DuelAction; 3] = [DuelAction::Push, DuelAction::Clench, DuelAction::Dodge];
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum DuelCommands {
+    #[command(description = "duel")]
+    Duel(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: DuelCommands,
+                         repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_DUEL.invoked();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let DuelCommands::Duel(args) = cmd;
+
+    let Some((best_of, bet)) = parse_best_of_and_bet(&args, config.pvp_default_bet) else {
+        reply_html!(bot, msg, t!("commands.duel.errors.invalid_args", locale = &lang_code));
+        return Ok(())
+    };
+    if !repos.hemoroids.check_hemoroid(&ChatIdKind::ID(msg.chat.id), from.id, Tenths::from_cm(bet.into())).await? {
+        reply_html!(bot, msg, t!("commands.penetrate.errors.not_enough.initiator", locale = &lang_code));
+        return Ok(())
+    }
+
+    let params = NewDuel {
+        initiator: from.id,
+        lang_code: lang_code.clone(),
+        best_of, bet,
+    };
+    let duel = repos.duels.create(msg.chat.id, &params).await?;
+    let mut answer = reply_html(bot, &msg, duel_text(&duel, &config));
+    answer.reply_markup = Some(ReplyMarkup::InlineKeyboard(duel_keyboard(&duel)));
+    let sent = answer.await?;
+    repos.duels.set_message(duel.id, sent.id).await?;
+    repos.challenges.register(&ChallengeMessage::Chat(sent.chat.id, sent.id), &lang_code).await?;
+    Ok(())
+}
+
+/// Both arguments are optional: `/duel [3|5] [bet]`.
+fn parse_best_of_and_bet(args: &str, default_bet: u16) -> Option<(u16, u16)> {
+    let mut parts = args.split_whitespace();
+    let best_of = parts.next().map_or(Some(3), |s| s.parse().ok())?;
+    let bet = parts.next().map_or(Some(default_bet), |s| s.parse().ok())?;
+    let valid = parts.next().is_none() && [3, 5].contains(&best_of) && bet > 0;
+    valid.then_some((best_of, bet))
+}
+
+/// Push beats clench, clench beats dodge, and dodge beats push.
+fn beats(action: DuelAction, other: DuelAction) -> bool {
+    matches!((action, other),
+        (DuelAction::Push, DuelAction::Clench) | (DuelAction::Clench, DuelAction::Dodge) | (DuelAction::Dodge, DuelAction::Push))
+}
+
+/// Returns the swelling of the initiator and the acceptor in the round. The one who hasn't picked an action loses.
+fn round_damages(initiator: Option<DuelAction>, acceptor: Option<DuelAction>) -> (i32, i32) {
+    match (initiator, acceptor) {
+        (Some(i), Some(a)) if beats(i, a) => (0, ROUND_LOSS_DAMAGE),
+        (Some(i), Some(a)) if beats(a, i) => (ROUND_LOSS_DAMAGE, 0),
+        (Some(_), None) => (0, ROUND_LOSS_DAMAGE),
+        (None, Some(_)) => (ROUND_LOSS_DAMAGE, 0),
+        _ => (DRAW_DAMAGE, DRAW_DAMAGE),
+    }
+}
+
+fn resolve_round(duel: &Duel, damage_multiplier: f32) -> RoundOutcome {
+    let (initiator_damage, acceptor_damage) = round_damages(duel.initiator_action, duel.acceptor_action);
+    let winner = match initiator_damage.cmp(&acceptor_damage) {
+        std::cmp::Ordering::Less => Some(duel.initiator.uid),
+        std::cmp::Ordering::Greater => duel.acceptor.as_ref().map(|p| p.uid),
+        std::cmp::Ordering::Equal => None,
+    };
+    RoundOutcome {
+        winner,
+        initiator_damage: scale_damage(initiator_damage, damage_multiplier),
+        acceptor_damage: scale_damage(acceptor_damage, damage_multiplier),
+    }
+}
+
+fn duel_text(duel: &Duel, config: &AppConfig) -> String {
+    let lang_code = &duel.lang_code;
+    let Some(acceptor) = duel.acceptor.as_ref() else {
+        return t!("commands.duel.open", locale = lang_code, name = duel.initiator.name.escaped(),
+            best_of = duel.best_of, bet = duel.bet).to_string()
+    };
+
+    let mut parts = vec![t!("commands.duel.header", locale = lang_code,
+        initiator = duel.initiator.name.escaped(), acceptor = acceptor.name.escaped(),
+        best_of = duel.best_of, bet = duel.bet,
+        initiator_wins = duel.initiator_wins, acceptor_wins = duel.acceptor_wins).to_string()];
+    let action_name = |action: Option<DuelAction>| match action {
+        Some(action) => t!(&format!("commands.duel.actions.{action}"), locale = lang_code),
+        None => t!("commands.duel.round.no_answer", locale = lang_code),
+    };
+    let rounds = duel.rounds.iter()
+        .map(|round| {
+            let result = match round.winner {
+                Some(winner) if winner == duel.initiator.uid => t!("commands.duel.round.winner", locale = lang_code, name = duel.initiator.name.escaped()),
+                Some(_) => t!("commands.duel.round.winner", locale = lang_code, name = acceptor.name.escaped()),
+                None => t!("commands.duel.round.draw", locale = lang_code),
+            };
+            t!("commands.duel.round.line", locale = lang_code, round = round.round,
+                initiator_action = action_name(round.initiator_action), acceptor_action = action_name(round.acceptor_action),
+                result = result).to_string()
+        })
+        .collect::<Vec<String>>();
+    if !rounds.is_empty() {
+        parts.push(rounds.join("\n"));
+    }
+
+    if duel.status == DuelStatus::Running {
+        let status = |action: Option<DuelAction>| if action.is_some() { "✅" } else { "🤔" };
+        let mut prompt = t!("commands.duel.round.prompt", locale = lang_code, round = duel.round,
+            initiator = duel.initiator.name.escaped(), initiator_status = status(duel.initiator_action),
+            acceptor = acceptor.name.escaped(), acceptor_status = status(duel.acceptor_action)).to_string();
+        if config.duel_round_timeout_seconds > 0 {
+            prompt.push(' ');
+            prompt.push_str(&t!("commands.duel.round.timeout", locale = lang_code, seconds = config.duel_round_timeout_seconds));
+        }
+        parts.push(prompt);
+    }
+    parts.join("\n\n")
+}
+
+fn duel_keyboard(duel: &Duel) -> InlineKeyboardMarkup {
+    let lang_code = &duel.lang_code;
+    let buttons = if duel.status == DuelStatus::Open {
+        let data = DuelCallbackData { duel_id: duel.id, action: None };
+        vec![InlineKeyboardButton::callback(t!("commands.duel.button", locale = lang_code), data.to_data_string())]
+    } else {
+        ACTIONS.iter()
+            .map(|action| {
+                let data = DuelCallbackData { duel_id: duel.id, action: Some(*action) };
+                InlineKeyboardButton::callback(t!(&format!("commands.duel.actions.{action}"), locale = lang_code), data.to_data_string())
+            })
+            .collect()
+    };
+    InlineKeyboardMarkup::new(vec![buttons])
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    DuelCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery, repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let data = DuelCallbackData::parse(&query)?;
+    let result = match data.action {
+        None => Some(accept_impl(&repos, &config, &query, data.duel_id).await?),
+        Some(action) => choose_impl(&repos, &config, &query, data.duel_id, action).await?,
+    };
+    match result {
+        Some(result) => result.apply(bot, query).await?,
+        None => {
+            bot.answer_callback_query(query.id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn accept_impl(repos: &repo::Repositories, config: &AppConfig, query: &CallbackQuery, duel_id: i32) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let Some(duel) = repos.duels.get(duel_id).await? else {
+        return Ok(CallbackResult::ShowError(t!("commands.duel.errors.finished", locale = &lang_code).to_string()))
+    };
+    let max_level = if config.features.pvp.check_acceptor_length { Tenths::from_cm(duel.bet.into()) } else { Tenths::from(0) };
+    if !repos.hemoroids.check_hemoroid(&ChatIdKind::ID(duel.chat_id), query.from.id, max_level).await? {
+        return Ok(CallbackResult::ShowError(t!("commands.penetrate.errors.not_enough.acceptor", locale = &lang_code).to_string()))
+    }
+    // fails fast before the rounds are played; the limits are checked again along with the results
+    let today = repos.battles.count_today(&ChatIdKind::ID(duel.chat_id), duel.initiator.uid, query.from.id).await?;
+    if let Some(limit) = today.exceeded(&config.battle_limits) {
+        return Ok(CallbackResult::ShowError(limit_explanation(&lang_code, limit)))
+    }
+
+    let result = match repos.duels.accept(duel_id, query.from.id).await {
+        Ok(duel) => {
+            if let Some(message_id) = duel.message_id {
+                repos.challenges.remove(&ChallengeMessage::Chat(duel.chat_id, message_id)).await
+                    .inspect_err(|e| log::error!("couldn't remove the accepted duel {duel_id}: {e}"))
+                    .ok();
+            }
+            CallbackResult::EditMessage(duel_text(&duel, config), Some(duel_keyboard(&duel)))
+        }
+        Err(DuelError::Other(e)) => Err(e)?,
+        Err(e) => CallbackResult::ShowError(t!(&format!("commands.duel.errors.{e}"), locale = &lang_code).to_string())
+    };
+    Ok(result)
+}
+
+/// Returns `None` if there is nothing to show because the round has been completed concurrently.
+async fn choose_impl(repos: &repo::Repositories, config: &AppConfig, query: &CallbackQuery,
+                     duel_id: i32, action: DuelAction) -> anyhow::Result<Option<CallbackResult>> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let result = match repos.duels.choose(duel_id, query.from.id, action).await {
+        Ok(duel) if duel.initiator_action.is_some() && duel.acceptor_action.is_some() =>
+            complete_round(repos, config, &duel).await?
+                .map(|(text, keyboard)| CallbackResult::EditMessage(text, keyboard)),
+        Ok(duel) => Some(CallbackResult::EditMessage(duel_text(&duel, config), Some(duel_keyboard(&duel)))),
+        Err(DuelError::Other(e)) => Err(e)?,
+        Err(e) => Some(CallbackResult::ShowError(t!(&format!("commands.duel.errors.{e}"), locale = &lang_code).to_string()))
+    };
+    Ok(result)
+}
+
+/// Resolves the current round and returns the new text and keyboard of the duel message,
+/// or `None` if the round has already been completed by someone else.
+async fn complete_round(repos: &repo::Repositories, config: &AppConfig, duel: &Duel) -> anyhow::Result<Option<(String, Option<InlineKeyboardMarkup>)>> {
+    let damage_multiplier = repos.events.get_modifiers().await
+        .inspect_err(|e| log::error!("couldn't get the modifiers of the active events: {e}"))
+        .unwrap_or_default()
+        .battle_damage;
+    let outcome = resolve_round(duel, damage_multiplier);
+    let Some(duel) = repos.duels.complete_round(duel.id, duel.round, &outcome).await? else {
+        return Ok(None)
+    };
+
+    let update = if duel.status == DuelStatus::Finished {
+        metrics::CMD_DUEL.finished();
+        (finish_duel(repos, config, &duel).await?, None)
+    } else {
+        (duel_text(&duel, config), Some(duel_keyboard(&duel)))
+    };
+    Ok(Some(update))
+}
+
+/// Applies the swelling accumulated over the rounds and moves the bet to the one who won more rounds.
+async fn finish_duel(repos: &repo::Repositories, config: &AppConfig, duel: &Duel) -> anyhow::Result<String> {
+    let lang_code = &duel.lang_code;
+    let acceptor = duel.acceptor.as_ref()
+        .ok_or(anyhow!("the finished duel {} must have an acceptor", duel.id))?;
+    let (winner, loser) = if duel.initiator_wins > duel.acceptor_wins {
+        (&duel.initiator, acceptor)
+    } else {
+        (acceptor, &duel.initiator)
+    };
+    let chat_id = ChatIdPartiality::from(duel.chat_id);
+    let bet = BattleBet {
+        initiator: duel.initiator.uid,
+        acceptor: acceptor.uid,
+        winner: winner.uid,
+        amount: Tenths::from_cm(duel.bet.into()),
+        check_acceptor: config.features.pvp.check_acceptor_length,
+    };
+    let battle = NewBattle {
+        initiator: duel.initiator.uid,
+        top: duel.initiator.uid,
+        bottom: acceptor.uid,
+        top_damage: duel.initiator_damage,
+        bottom_damage: duel.acceptor_damage,
+        bet: duel.bet,
+        winner: winner.uid,
+        rematch_of: None,
+    };
+    let log = duel_text(duel, config);
+    let limits = &config.battle_limits;
+    let result = match repos.hemoroids.penetrate(&chat_id, &battle, bet, &[], None, Some(limits)).await {
+        Ok(result) => result,
+        Err(PenetrationError::Other(e)) => Err(e)?,
+        Err(PenetrationError::LimitReached(limit)) => return Ok(format!("{log}\n\n{}", limit_explanation(lang_code, limit))),
+        Err(PenetrationError::NotEnoughInitiator | PenetrationError::NotEnoughAcceptor) =>
+            return Ok(format!("{log}\n\n{}", t!("commands.duel.errors.not_enough", locale = lang_code)))
+    };
+
+    let rating = repos.pvp_stats.send_battle_result(&chat_id.kind(), winner.uid, loser.uid, result.award).await
+        .inspect_err(|e| log::error!("couldn't send the battle statistics of the duel {}: {e}", duel.id))
+        .ok()
+        .map(|stats| format!("\n\n{}", t!("commands.penetrate.results.rating", locale = lang_code,
+            winner_name = winner.name.escaped(), winner_rating = stats.winner.rating,
+            loser_name = loser.name.escaped(), loser_rating = stats.loser.rating,
+            change = stats.rating_change)))
+        .unwrap_or_default();
+    let withheld = if result.withheld > 0 {
+        format!("\n\n{}", t!("commands.penetrate.results.withheld", locale = lang_code,
+            payout = Tenths::from(result.withheld).format(lang_code)))
+    } else {
+        String::new()
+    };
+    let diminished = if limits.reward_ratio(result.previous_pair_battles) < 1.0 {
+        format!("\n\n{}", t!("commands.penetrate.results.diminished", locale = lang_code,
+            battles = result.previous_pair_battles, award = result.award.format(lang_code)))
+    } else {
+        String::new()
+    };
+
+    let score = format!("{}:{}", duel.initiator_wins.max(duel.acceptor_wins), duel.initiator_wins.min(duel.acceptor_wins));
+    let levels = [(&duel.initiator, duel.initiator_damage, result.top.new_protrusion_level),
+                  (acceptor, duel.acceptor_damage, result.bottom.new_protrusion_level)]
+        .map(|(player, damage, level)| t!("commands.duel.results.level", locale = lang_code,
+            name = player.name.escaped(), damage = Tenths::from(damage).format_signed(lang_code),
+            level = Tenths::from(level).format(lang_code)).to_string())
+        .join("\n");
+    let winner_line = t!("commands.duel.results.winner", locale = lang_code,
+        name = winner.name.escaped(), score = score, bet = duel.bet);
+    let event_banner = utils::event_banner(&repos.events, lang_code).await;
+    Ok(format!("{log}\n\n{winner_line}\n{levels}{withheld}{diminished}{rating}{event_banner}"))
+}
+
+/// Periodically completes the rounds where somebody hasn't picked an action in time.
+pub async fn run_duel_timeouts(bot: Bot, repos: repo::Repositories, config: AppConfig) {
+    if config.duel_round_timeout_seconds == 0 {
+        return
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+    loop {
+        interval.tick().await;
+        let duels = match repos.duels.get_timed_out(config.duel_round_timeout_seconds).await {
+            Ok(duels) => duels,
+            Err(e) => {
+                log::error!("couldn't get the timed out duels: {e}");
+                continue
+            }
+        };
+        for duel in duels {
+            if let Err(e) = time_out_round(&bot, &repos, &config, &duel).await {
+                log::error!("couldn't time out the round {} of the duel {}: {e}", duel.round, duel.id);
+            }
+        }
+    }
+}
+
+/// The one who hasn't responded forfeits the round; if neither has, the duel is called off.
+async fn time_out_round(bot: &Bot, repos: &repo::Repositories, config: &AppConfig, duel: &Duel) -> anyhow::Result<()> {
+    let update = if duel.initiator_action.is_none() && duel.acceptor_action.is_none() {
+        repos.duels.cancel(duel.id, duel.round).await?
+            .then(|| Duel { status: DuelStatus::Cancelled, ..duel.clone() })
+            .map(|duel| {
+                let text = format!("{}\n\n{}", duel_text(&duel, config), t!("commands.duel.cancelled", locale = &duel.lang_code));
+                (text, None)
+            })
+    } else {
+        complete_round(repos, config, duel).await?
+    };
+    let (Some((text, keyboard)), Some(message_id)) = (update, duel.message_id) else {
+        return Ok(())
+    };
+    let mut request = bot.edit_message_text(duel.chat_id, message_id, text);
+    request.parse_mode.replace(ParseMode::Html);
+    request.reply_markup = keyboard;
+    request.await?;
+    Ok(())
+}
+
+const ACCEPT: &str = "accept";
+
+pub(crate) struct DuelCallbackData {
+    duel_id: i32,
+    /// `None` means the duel is being accepted.
+    action: Option<DuelAction>,
+}
+
+impl std::fmt::Display for DuelCallbackData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.action {
+            Some(action) => write!(f, "{}:{action}", self.duel_id),
+            None => write!(f, "{}:{ACCEPT}", self.duel_id),
+        }
+    }
+}
+
+impl CallbackDataWithPrefix for DuelCallbackData {
+    fn prefix() -> &'static str {
+        "duel"
+    }
+}
+
+impl TryFrom<String> for DuelCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let duel_id = callbacks::parse_part(&mut parts, &err, "duel_id")?;
+        let action: String = callbacks::parse_part(&mut parts, &err, "action")?;
+        let action = if action == ACCEPT {
+            None
+        } else {
+            Some(DuelAction::from_str(&action).map_err(|e| err.parsing_err(e))?)
+        };
+        Ok(Self { duel_id, action })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::handlers::utils::callbacks::CallbackDataWithPrefix;
+    use crate::repo::DuelAction::{Clench, Dodge, Push};
+    use super::{parse_best_of_and_bet, round_damages, DuelCallbackData, DRAW_DAMAGE, ROUND_LOSS_DAMAGE};
+
+    #[test]
+    fn test_parse_best_of_and_bet() {
+        assert_eq!(parse_best_of_and_bet("", 1), Some((3, 1)));
+        assert_eq!(parse_best_of_and_bet("5", 1), Some((5, 1)));
+        assert_eq!(parse_best_of_and_bet(" 3  10 ", 1), Some((3, 10)));
+        assert_eq!(parse_best_of_and_bet("4", 1), None);
+        assert_eq!(parse_best_of_and_bet("3 0", 1), None);
+        assert_eq!(parse_best_of_and_bet("3 5 7", 1), None);
+    }
+
+    #[test]
+    fn test_round_damages() {
+        let (win, loss) = (0, ROUND_LOSS_DAMAGE);
+        assert_eq!(round_damages(Some(Push), Some(Clench)), (win, loss));
+        assert_eq!(round_damages(Some(Clench), Some(Dodge)), (win, loss));
+        assert_eq!(round_damages(Some(Dodge), Some(Push)), (win, loss));
+        assert_eq!(round_damages(Some(Clench), Some(Push)), (loss, win));
+        assert_eq!(round_damages(Some(Dodge), Some(Clench)), (loss, win));
+        assert_eq!(round_damages(Some(Push), Some(Dodge)), (loss, win));
+        for action in [Push, Clench, Dodge] {
+            assert_eq!(round_damages(Some(action), Some(action)), (DRAW_DAMAGE, DRAW_DAMAGE));
+            assert_eq!(round_damages(Some(action), None), (win, loss));
+            assert_eq!(round_damages(None, Some(action)), (loss, win));
+        }
+    }
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let data = DuelCallbackData { duel_id: 42, action: Some(Dodge) };
+        assert_eq!(data.to_data_string(), "duel:42:dodge");
+        let data = DuelCallbackData { duel_id: 42, action: None };
+        assert_eq!(data.to_data_string(), "duel:42:accept");
+
+        let parsed = DuelCallbackData::try_from("7:clench".to_owned())
+            .expect("duel callback data must be parsed successfully");
+        assert_eq!(parsed.duel_id, 7);
+        assert_eq!(parsed.action, Some(Clench));
+        let parsed = DuelCallbackData::try_from("7:accept".to_owned())
+            .expect("duel callback data must be parsed successfully");
+        assert_eq!(parsed.action, None);
+        assert!(DuelCallbackData::try_from("7:kick".to_owned()).is_err());
+    }
+}
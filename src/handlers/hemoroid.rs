@@ -1,7 +1,7 @@
 use std::future::IntoFuture;
 
 use anyhow::{anyhow, Context};
-use chrono::{Datelike, Utc};
+use chrono::Utc;
 use futures::future::join;
 use futures::TryFutureExt;
 use rand::{Rng, thread_rng};
@@ -14,14 +14,16 @@ use teloxide::types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup,
 use page::{InvalidPage, Page};
 
 use crate::{config, metrics, repo};
-use crate::domain::{LanguageCode, Username};
-use crate::handlers::{HandlerResult, reply_html, utils};
+use crate::domain::{LanguageCode, Tenths, Username};
+use crate::handlers::{achievements, HandlerResult, reply_html, utils};
 use crate::handlers::utils::{callbacks, page};
-use crate::repo::{ChatIdPartiality, UID};
+use crate::repo::{ChatIdPartiality, ClenchAttempt, UID};
 
 const TOMORROW_SQL_CODE: &str = "GD0E1";
 const CALLBACK_PREFIX_TOP_PAGE: &str = "top:page:";
 const CALLBACK_PREFIX_WORST_PAGE: &str = "worst:page:";
+const CALLBACK_PREFIX_STREAKS_PAGE: &str = "streaks:page:";
+const CALLBACK_PREFIX_RATING_PAGE: &str = "rating:page:";
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
@@ -34,6 +36,10 @@ pub enum HemoroidCommands {
     Top,
     #[command(description = "worst")]
     Worst,
+    #[command(description = "streaks")]
+    Streaks,
+    #[command(description = "rating")]
+    Rating,
     #[command(description = "clench")]
     Clench,
     #[command(description = "tip")]
@@ -78,8 +84,28 @@ pub async fn hemoroid_cmd_handler(bot: Bot, msg: Message, cmd: HemoroidCommands,
             }
             request
         },
+        HemoroidCommands::Streaks => {
+            metrics::CMD_TOP_COUNTER.chat.inc();
+            let streaks = streaks_impl(&repos, &config, from_refs, Page::first()).await?;
+            let mut request = reply_html(bot, &msg, streaks.lines);
+            if streaks.has_more_pages && config.features.top_unlimited {
+                let keyboard = ReplyMarkup::InlineKeyboard(build_pagination_keyboard(Page::first(), streaks.has_more_pages, CALLBACK_PREFIX_STREAKS_PAGE));
+                request.reply_markup.replace(keyboard);
+            }
+            request
+        },
+        HemoroidCommands::Rating => {
+            metrics::CMD_TOP_COUNTER.chat.inc();
+            let rating = rating_impl(&repos, &config, from_refs, Page::first()).await?;
+            let mut request = reply_html(bot, &msg, rating.lines);
+            if rating.has_more_pages && config.features.top_unlimited {
+                let keyboard = ReplyMarkup::InlineKeyboard(build_pagination_keyboard(Page::first(), rating.has_more_pages, CALLBACK_PREFIX_RATING_PAGE));
+                request.reply_markup.replace(keyboard);
+            }
+            request
+        },
         HemoroidCommands::Clench => {
-            let answer = clench_impl(&from_refs.0.id);
+            let answer = clench_impl(&repos, config.clench, from_refs).await?;
             reply_html(bot, &msg, answer)
         },
         HemoroidCommands::Tip => {
@@ -100,12 +126,16 @@ pub(crate) async fn level_impl(repos: &repo::Repositories, from_refs: FromRefs<'
         let position_str = hemoroid.position.map_or("".to_string(), |pos| {
             format!("\n{}", t!("commands.level.position", locale = &lang_code, pos = pos))
         });
+        let streak = repos.hemoroids.fetch_streak(from.id, &chat_id.kind()).await?
+            .unwrap_or_default();
+        let streak_str = format!("\n{}", t!("commands.level.streak", locale = &lang_code,
+            current = streak.current, max = streak.max));
         
         Ok(t!("commands.level.stats", 
             locale = &lang_code, 
-            level = hemoroid.protrusion_level,
+            level = Tenths::from(hemoroid.protrusion_level).format(&lang_code),
             pos = hemoroid.position.unwrap_or(0)
-        ).to_string() + &position_str)
+        ).to_string() + &position_str + &streak_str)
     } else {
         Ok(t!("commands.level.not_found", locale = &lang_code).to_string())
     }
@@ -115,24 +145,15 @@ pub(crate) async fn shrink_impl(repos: &repo::Repositories, incr: utils::Increme
     let (from, chat_id) = (from_refs.0, from_refs.1);
     let name = utils::get_full_name(from);
     let user = repos.users.create_or_update(from.id, &name).await?;
+    repos.users.update_username(from.id, from.username.as_deref()).await?;
     let days_since_registration = (Utc::now() - user.created_at).num_days() as u32;
     
-    let mut rng = thread_rng();
-    let shrink_chance = 0.7; // 70% chance to shrink
-    
-    // Generate a random event: positive = shrink (good), negative = swell (bad)
-    let is_shrink = rng.gen_bool(shrink_chance);
-    
-    // Generate random change amount
-    let change_amount = if is_shrink {
-        // Shrink by 0.1 to 1.0cm (negative value = shrink)
-        -(rng.gen_range(10..101) as i32) / 10
-    } else {
-        // Swell by 0.1 to 1.5cm (positive value = swell)
-        (rng.gen_range(10..151) as i32) / 10
-    };
+    // the increment is an improvement, so the protrusion level changes in the opposite direction
+    let increment = incr.growth_increment(from.id, chat_id.kind(), days_since_registration).await;
+    let change = -repo::LevelChange::from(&increment);
+    let change_amount = change.total();
     
-    let treatment_result = repos.hemoroids.create_or_shrink(from.id, chat_id, change_amount).await;
+    let treatment_result = repos.hemoroids.create_or_shrink(from.id, chat_id, change).await;
     let lang_code = LanguageCode::from_user(from);
 
     let main_part = match treatment_result {
@@ -143,14 +164,17 @@ pub(crate) async fn shrink_impl(repos: &repo::Repositories, incr: utils::Increme
             
             let answer = t!("commands.shrink.result", locale = &lang_code,
                 event = event, 
-                change = change_amount.abs(), 
-                level = new_protrusion_level);
+                change = Tenths::from(change_amount.abs()).format(&lang_code), 
+                level = Tenths::from(new_protrusion_level).format(&lang_code));
+            let perks_part = increment.perks_part_of_answer(&lang_code);
+            let achievements_part = achievements::check_and_announce(repos, &chat_id.kind(), from.id,
+                &name.escaped(), &lang_code).await;
             
             if let Some(pos) = pos_in_top {
                 let position = t!("commands.shrink.position", locale = &lang_code, pos = pos);
-                format!("{answer}\n{position}")
+                format!("{answer}\n{position}{perks_part}{achievements_part}")
             } else {
-                answer.to_string()
+                format!("{answer}{perks_part}{achievements_part}")
             }
         },
         Err(e) => {
@@ -165,8 +189,10 @@ pub(crate) async fn shrink_impl(repos: &repo::Repositories, incr: utils::Increme
             }
         }
     };
-    let time_left_part = utils::date::get_time_till_next_day_string(&lang_code);
-    Ok(format!("{main_part}{time_left_part}"))
+    let utc_offset = repos.chats.get_utc_offset(&chat_id.kind()).await?;
+    let time_left_part = utils::date::get_time_till_next_day_string(&lang_code, utc_offset);
+    let event_banner = utils::event_banner(&repos.events, &lang_code).await;
+    Ok(format!("{main_part}{time_left_part}{event_banner}"))
 }
 
 pub(crate) struct Top {
@@ -199,6 +225,7 @@ pub(crate) async fn top_impl(repos: &repo::Repositories, config: &config::AppCon
     let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
     
     let hemoroids = repos.hemoroids.get_top(&chat_id, offset, query_limit).await?;
+    let utc_offset = repos.chats.get_utc_offset(&chat_id).await?;
     let has_more_pages = hemoroids.len() as u32 > top_limit;
     
     let lines = hemoroids.into_iter()
@@ -211,13 +238,13 @@ pub(crate) async fn top_impl(repos: &repo::Repositories, config: &config::AppCon
             } else {
                 escaped_name
             };
-            let can_shrink = Utc::now().num_days_from_ce() > h.treated_at.num_days_from_ce();
+            let can_shrink = utils::date::is_before_today(h.treated_at, utc_offset);
             let pos = h.position.unwrap_or((i+1) as i64);
             let mut line = t!("commands.top.line", 
                 locale = &lang_code,
                 n = pos, 
                 name = name, 
-                level = h.protrusion_level).to_string();
+                level = Tenths::from(h.protrusion_level).format(&lang_code)).to_string();
             if can_shrink {
                 line.push_str(" [+]")
             };
@@ -249,6 +276,7 @@ pub(crate) async fn worst_impl(repos: &repo::Repositories, config: &config::AppC
     let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
     
     let hemoroids = repos.hemoroids.get_worst(&chat_id, offset, query_limit).await?;
+    let utc_offset = repos.chats.get_utc_offset(&chat_id).await?;
     let has_more_pages = hemoroids.len() as u32 > top_limit;
     
     let lines = hemoroids.into_iter()
@@ -261,13 +289,13 @@ pub(crate) async fn worst_impl(repos: &repo::Repositories, config: &config::AppC
             } else {
                 escaped_name
             };
-            let can_shrink = Utc::now().num_days_from_ce() > h.treated_at.num_days_from_ce();
+            let can_shrink = utils::date::is_before_today(h.treated_at, utc_offset);
             let pos = h.position.unwrap_or((i+1) as i64);
             let mut line = t!("commands.worst.line", 
                 locale = &lang_code,
                 n = pos, 
                 name = name, 
-                level = h.protrusion_level).to_string();
+                level = Tenths::from(h.protrusion_level).format(&lang_code)).to_string();
             if can_shrink {
                 line.push_str(" [+]")
             };
@@ -290,6 +318,98 @@ pub(crate) async fn worst_impl(repos: &repo::Repositories, config: &config::AppC
     Ok(res)
 }
 
+pub(crate) async fn streaks_impl(repos: &repo::Repositories, config: &config::AppConfig, from_refs: FromRefs<'_>,
+                                 page: Page) -> anyhow::Result<Top> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+    let top_limit = config.top_limit as u32;
+    let offset = page * top_limit;
+    let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
+
+    let holders = repos.hemoroids.get_streaks_top(&chat_id, offset, query_limit).await?;
+    let has_more_pages = holders.len() as u32 > top_limit;
+
+    let lines = holders.into_iter()
+        .take(config.top_limit as usize)
+        .enumerate()
+        .map(|(i, h)| {
+            let escaped_name = Username::new(h.owner_name).escaped();
+            let name = if from.id == <UID as Into<UserId>>::into(h.owner_uid) {
+                format!("<u>{escaped_name}</u>")
+            } else {
+                escaped_name
+            };
+            let pos = h.position.unwrap_or((i+1) as i64);
+            t!("commands.streaks.line",
+                locale = &lang_code,
+                n = pos,
+                name = name,
+                max = h.max,
+                current = h.current).to_string()
+        })
+        .collect::<Vec<String>>();
+
+    let res = if lines.is_empty() {
+        Top::from(t!("commands.streaks.empty", locale = &lang_code))
+    } else {
+        let title = t!("commands.streaks.title", locale = &lang_code);
+        let ending = t!("commands.streaks.ending", locale = &lang_code);
+        let text = format!("{}\n\n{}\n\n{}", title, lines.join("\n"), ending);
+        if has_more_pages {
+            Top::with_more_pages(text)
+        } else {
+            Top::from(text)
+        }
+    };
+    Ok(res)
+}
+
+pub(crate) async fn rating_impl(repos: &repo::Repositories, config: &config::AppConfig, from_refs: FromRefs<'_>,
+                                page: Page) -> anyhow::Result<Top> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+    let top_limit = config.top_limit as u32;
+    let offset = page * top_limit;
+    let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
+
+    let holders = repos.pvp_stats.get_rating_top(&chat_id, offset, query_limit).await?;
+    let has_more_pages = holders.len() as u32 > top_limit;
+
+    let lines = holders.into_iter()
+        .take(config.top_limit as usize)
+        .enumerate()
+        .map(|(i, h)| {
+            let escaped_name = Username::new(h.owner_name).escaped();
+            let name = if from.id == <UID as Into<UserId>>::into(h.owner_uid) {
+                format!("<u>{escaped_name}</u>")
+            } else {
+                escaped_name
+            };
+            let pos = h.position.unwrap_or((i+1) as i64);
+            t!("commands.rating.line",
+                locale = &lang_code,
+                n = pos,
+                name = name,
+                rating = h.rating,
+                battles = h.battles_total).to_string()
+        })
+        .collect::<Vec<String>>();
+
+    let res = if lines.is_empty() {
+        Top::from(t!("commands.rating.empty", locale = &lang_code))
+    } else {
+        let title = t!("commands.rating.title", locale = &lang_code);
+        let ending = t!("commands.rating.ending", locale = &lang_code);
+        let text = format!("{}\n\n{}\n\n{}", title, lines.join("\n"), ending);
+        if has_more_pages {
+            Top::with_more_pages(text)
+        } else {
+            Top::from(text)
+        }
+    };
+    Ok(res)
+}
+
 fn build_pagination_keyboard(page: Page, has_more_pages: bool, prefix: &str) -> InlineKeyboardMarkup {
     let mut buttons = Vec::new();
     if page.0 > 0 {
@@ -304,7 +424,9 @@ fn build_pagination_keyboard(page: Page, has_more_pages: bool, prefix: &str) ->
 pub fn page_callback_filter(query: CallbackQuery) -> bool {
     query.data
         .as_ref()
-        .filter(|d| d.starts_with(CALLBACK_PREFIX_TOP_PAGE) || d.starts_with(CALLBACK_PREFIX_WORST_PAGE))
+        .filter(|d| [CALLBACK_PREFIX_TOP_PAGE, CALLBACK_PREFIX_WORST_PAGE, CALLBACK_PREFIX_STREAKS_PAGE, CALLBACK_PREFIX_RATING_PAGE]
+            .iter()
+            .any(|prefix| d.starts_with(prefix)))
         .is_some()
 }
 
@@ -334,6 +456,24 @@ pub async fn page_callback_handler(bot: Bot, q: CallbackQuery,
                 .map(Page)
                 .map_err(|e| anyhow!(e))?,
              CALLBACK_PREFIX_WORST_PAGE)
+        } else if data.starts_with(CALLBACK_PREFIX_STREAKS_PAGE) {
+            (data.strip_prefix(CALLBACK_PREFIX_STREAKS_PAGE)
+                .map(str::to_owned)
+                .ok_or(InvalidPage::for_value(data, "invalid streaks prefix"))
+                .and_then(|r| r.parse()
+                    .map_err(|e| InvalidPage::for_value(&r, e)))
+                .map(Page)
+                .map_err(|e| anyhow!(e))?,
+             CALLBACK_PREFIX_STREAKS_PAGE)
+        } else if data.starts_with(CALLBACK_PREFIX_RATING_PAGE) {
+            (data.strip_prefix(CALLBACK_PREFIX_RATING_PAGE)
+                .map(str::to_owned)
+                .ok_or(InvalidPage::for_value(data, "invalid rating prefix"))
+                .and_then(|r| r.parse()
+                    .map_err(|e| InvalidPage::for_value(&r, e)))
+                .map(Page)
+                .map_err(|e| anyhow!(e))?,
+             CALLBACK_PREFIX_RATING_PAGE)
         } else {
             return Err(anyhow!("Unknown callback data prefix").into());
         }
@@ -345,10 +485,11 @@ pub async fn page_callback_handler(bot: Bot, q: CallbackQuery,
     let chat_id_partiality = ChatIdPartiality::Specific(chat_id_kind);
     let from_refs = FromRefs(&q.from, &chat_id_partiality);
     
-    let top = if prefix == CALLBACK_PREFIX_TOP_PAGE {
-        top_impl(&repos, &config, from_refs, page).await?
-    } else {
-        worst_impl(&repos, &config, from_refs, page).await?
+    let top = match prefix {
+        CALLBACK_PREFIX_TOP_PAGE => top_impl(&repos, &config, from_refs, page).await?,
+        CALLBACK_PREFIX_WORST_PAGE => worst_impl(&repos, &config, from_refs, page).await?,
+        CALLBACK_PREFIX_RATING_PAGE => rating_impl(&repos, &config, from_refs, page).await?,
+        _ => streaks_impl(&repos, &config, from_refs, page).await?,
     };
 
     let keyboard = build_pagination_keyboard(page, top.has_more_pages, prefix);
@@ -409,16 +550,30 @@ async fn answer_callback_feature_disabled<'a>(bot: Bot, callback_query: &'a Call
     Ok(())
 }
 
-fn clench_impl(user_id: &UserId) -> String {
-    let mut rng = thread_rng();
-    let success_chance = 0.3; // 30% chance to reduce battle damage
-    let success = rng.gen_bool(success_chance);
-    
-    if success {
-        format!("✅ <b>Clench successful!</b> You've activated your pelvic muscles. Your next battle damage might be reduced!")
-    } else {
-        format!("❌ <b>Clench failed!</b> Your pelvic muscles weren't strong enough this time.")
+pub(crate) async fn clench_impl(repos: &repo::Repositories, cfg: config::ClenchConfig, from_refs: FromRefs<'_>) -> anyhow::Result<String> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+    if repos.hemoroids.fetch_hemoroid(from.id, &chat_id).await?.is_none() {
+        return Ok(t!("commands.level.not_found", locale = &lang_code).to_string())
     }
+
+    let success = thread_rng().gen_bool(cfg.success_chance.clamp(0.0, 1.0));
+    let answer = match repos.clenches.attempt(&chat_id, from.id, success, cfg.shield_hours, cfg.cooldown_hours).await? {
+        ClenchAttempt::Shielded { expires_at } => {
+            let reduction = format!("{:.0}%", cfg.damage_reduction * 100.0);
+            // rounded up, otherwise a fresh shield is announced an hour shorter
+            let expires_in = ((expires_at - Utc::now()).num_minutes() + 59) / 60;
+            t!("commands.clench.success", locale = &lang_code, reduction = reduction, hours = expires_in)
+        }
+        ClenchAttempt::Failed => t!("commands.clench.failure", locale = &lang_code),
+        ClenchAttempt::OnCooldown { available_at } => {
+            let time_left = available_at - Utc::now();
+            let hrs = time_left.num_hours();
+            let mins = time_left.num_minutes() - hrs * 60;
+            t!("commands.clench.cooldown", locale = &lang_code, hours = hrs, minutes = mins)
+        }
+    };
+    Ok(answer.to_string())
 }
 
 fn get_random_tip(lang_code: &LanguageCode) -> String {
@@ -0,0 +1,170 @@
+use anyhow::anyhow;
+use derive_more::Display;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message, UserId};
+use teloxide::requests::Requester;
+use teloxide::types::{ParseMode, ReplyMarkup};
+use callbacks::{EditMessageReqParamsKind, InvalidCallbackData};
+
+use crate::{check_invoked_by_owner_and_get_answer_params, metrics, repo};
+use crate::config::AppConfig;
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackButton, FromRefs, HandlerImplResult, HandlerResult, reply_html};
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::handlers::utils::page::Page;
+use crate::handlers::utils::perk_title;
+use crate::repo::{ChangeSource, ChatIdPartiality, HistoryRecord};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum HistoryCommands {
+    #[command(description = "history")]
+    History,
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_HISTORY_COUNTER.inc();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let chat_id = msg.chat.id.into();
+    let from_refs = FromRefs(from, &chat_id);
+
+    let result = history_impl(&repos, &config, from_refs, Page::first()).await?;
+    let mut request = reply_html(bot, &msg, result.text());
+    request.reply_markup = result.keyboard().map(ReplyMarkup::InlineKeyboard);
+    request.await?;
+    Ok(())
+}
+
+pub(crate) async fn history_impl(repos: &repo::Repositories, config: &AppConfig, from_refs: FromRefs<'_>,
+                                 page: Page) -> anyhow::Result<HandlerImplResult<HistoryCallbackData>> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+    let limit = config.top_limit as u32;
+    let offset = page * limit;
+    let query_limit = config.top_limit + 1; // fetch +1 row to know whether more rows exist or not
+
+    let records = repos.history.get_page(&chat_id, from.id, offset, query_limit).await?;
+    let has_more_pages = records.len() as u32 > limit;
+    let lines = records.into_iter()
+        .take(config.top_limit as usize)
+        .map(|record| format_record(record, &lang_code))
+        .collect::<Vec<String>>();
+
+    if lines.is_empty() {
+        let text = t!("commands.history.empty", locale = &lang_code).to_string();
+        return Ok(HandlerImplResult::OnlyText(text))
+    }
+
+    let title = t!("commands.history.title", locale = &lang_code);
+    let text = format!("{}\n\n{}", title, lines.join("\n"));
+    let mut buttons = Vec::new();
+    if page > 0 {
+        buttons.push(CallbackButton::new("◀️".to_owned(), HistoryCallbackData { uid: from.id, page: page - 1 }));
+    }
+    if has_more_pages {
+        buttons.push(CallbackButton::new("▶️".to_owned(), HistoryCallbackData { uid: from.id, page: page + 1 }));
+    }
+    let res = if buttons.is_empty() {
+        HandlerImplResult::OnlyText(text)
+    } else {
+        HandlerImplResult::WithKeyboard { text, buttons }
+    };
+    Ok(res)
+}
+
+fn format_record(record: HistoryRecord, lang_code: &LanguageCode) -> String {
+    let source = match (record.source, record.reference_id) {
+        (ChangeSource::Perk, Some(perk)) => perk_title(&perk, lang_code),
+        (ChangeSource::Shop, Some(item)) => t!("commands.history.sources.shop_item", locale = lang_code,
+            item = t!(&format!("items.{item}.name"), locale = lang_code)).to_string(),
+        (ChangeSource::Item, Some(item)) => t!(&format!("items.{item}.name"), locale = lang_code).to_string(),
+        (source, _) => t!(&format!("commands.history.sources.{source}"), locale = lang_code).to_string(),
+    };
+    t!("commands.history.line", locale = lang_code,
+        date = record.created_at.format("%d.%m.%Y %H:%M"),
+        delta = Tenths::from(record.delta).format_signed(lang_code),
+        source = source).to_string()
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    HistoryCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery,
+                              repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let data = HistoryCallbackData::parse(&query)?;
+    let (answer, _) = check_invoked_by_owner_and_get_answer_params!(bot, query, data.uid);
+
+    let edit_msg_params = callbacks::get_params_for_message_edit(&query)?;
+    let chat_id = ChatIdPartiality::Specific(edit_msg_params.clone().into());
+    let from_refs = FromRefs(&query.from, &chat_id);
+    let result = history_impl(&repos, &config, from_refs, data.page).await?;
+
+    match edit_msg_params {
+        EditMessageReqParamsKind::Chat(chat_id, message_id) => {
+            let mut request = bot.edit_message_text(chat_id, message_id, result.text());
+            request.parse_mode.replace(ParseMode::Html);
+            request.reply_markup = result.keyboard();
+            request.await?;
+        }
+        EditMessageReqParamsKind::Inline { inline_message_id, .. } => {
+            let mut request = bot.edit_message_text_inline(inline_message_id, result.text());
+            request.parse_mode.replace(ParseMode::Html);
+            request.reply_markup = result.keyboard();
+            request.await?;
+        }
+    }
+
+    answer.await?;
+    Ok(())
+}
+
+#[derive(Display)]
+#[display("{uid}:{page}")]
+pub(crate) struct HistoryCallbackData {
+    uid: UserId,
+    page: Page,
+}
+
+impl CallbackDataWithPrefix for HistoryCallbackData {
+    fn prefix() -> &'static str {
+        "history"
+    }
+}
+
+impl TryFrom<String> for HistoryCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.as_str().split(':');
+        let uid = callbacks::parse_part(&mut parts, &err, "uid").map(UserId)?;
+        let page = callbacks::parse_part(&mut parts, &err, "page").map(Page)?;
+        Ok(Self { uid, page })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use teloxide::types::UserId;
+    use crate::handlers::history::HistoryCallbackData;
+    use crate::handlers::utils::callbacks::CallbackDataWithPrefix;
+    use crate::handlers::utils::page::Page;
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let data = HistoryCallbackData { uid: UserId(123456), page: Page(2) };
+        let data_string = data.to_data_string();
+        assert_eq!(data_string, "history:123456:2");
+
+        let parsed = HistoryCallbackData::try_from("123456:2".to_owned())
+            .expect("history callback data must be parsed successfully");
+        assert_eq!(parsed.uid, data.uid);
+        assert_eq!(parsed.page, 2);
+    }
+}
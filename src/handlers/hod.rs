@@ -7,8 +7,8 @@ use teloxide::payloads::SendMessageSetters;
 use teloxide::types::{LinkPreviewOptions, Message, UserId};
 use crate::{config, metrics, repo};
 use crate::config::DickOfDaySelectionMode;
-use crate::domain::LanguageCode;
-use crate::handlers::{FromRefs, HandlerResult, reply_html, utils};
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{achievements, FromRefs, HandlerResult, reply_html, utils};
 use crate::handlers::utils::Incrementor;
 
 const HOD_ALREADY_CHOSEN_SQL_CODE: &str = "GD0E2"; // Using the same SQL code as DOD
@@ -50,18 +50,23 @@ pub(crate) async fn hemoroid_of_day_impl(cfg: config::AppConfig, repos: &repo::R
     };
     let answer = match winner {
         Some(winner) => {
-            let improvement = incr.dod_increment(from.id, chat_id.kind()).await;
-            let hod_result = repos.hemoroids.set_hod_winner(chat_id, UserId(winner.uid as u64), improvement.total).await;
+            let winner_id = UserId(winner.uid as u64);
+            // the perks are calculated and settled for the winner, not for the one who has called the command
+            let improvement = incr.dod_increment(winner_id, chat_id.kind()).await;
+            let hod_result = repos.hemoroids.set_hod_winner(chat_id, winner_id, (&improvement).into()).await;
             let main_part = match hod_result {
                 Ok(Some(repo::TreatmentResult{ new_protrusion_level, pos_in_top })) => {
                     let answer = t!("commands.hod.result", locale = &lang_code,
-                        uid = winner.uid, name = winner.name.escaped(), improvement = improvement.total, level = new_protrusion_level);
+                        uid = winner.uid, name = winner.name.escaped(), improvement = Tenths::from(i32::from(improvement.total)).format(&lang_code),
+                        level = Tenths::from(new_protrusion_level).format(&lang_code));
                     let perks_part = improvement.perks_part_of_answer(&lang_code);
+                    let achievements_part = achievements::check_and_announce(repos, &chat_id.kind(), winner_id,
+                        &winner.name.escaped(), &lang_code).await;
                     if let Some(pos) = pos_in_top {
                         let position = t!("commands.hod.position", locale = &lang_code, pos = pos);
-                        format!("{answer}\n{position}{perks_part}")
+                        format!("{answer}\n{position}{perks_part}{achievements_part}")
                     } else {
-                        format!("{answer}{perks_part}")
+                        format!("{answer}{perks_part}{achievements_part}")
                     }
                 },
                 Ok(None) => {
@@ -79,7 +84,8 @@ pub(crate) async fn hemoroid_of_day_impl(cfg: config::AppConfig, repos: &repo::R
                     }
                 }
             };
-            let time_left_part = utils::date::get_time_till_next_day_string(&lang_code);
+            let utc_offset = repos.chats.get_utc_offset(&chat_id.kind()).await?;
+            let time_left_part = utils::date::get_time_till_next_day_string(&lang_code, utc_offset);
             format!("{main_part}{time_left_part}")
         },
         None => t!("commands.hod.no_candidates", locale = &lang_code).to_string()
@@ -87,7 +93,8 @@ pub(crate) async fn hemoroid_of_day_impl(cfg: config::AppConfig, repos: &repo::R
     let announcement = repos.announcements.get_new(&chat_id.kind(), &lang_code).await?
         .map(|announcement| format!("\n\n<i>{announcement}</i>"))
         .unwrap_or_default();
-    Ok(format!("{answer}{announcement}"))
+    let event_banner = utils::event_banner(&repos.events, &lang_code).await;
+    Ok(format!("{answer}{event_banner}{announcement}"))
 }
 
 fn disabled_link_preview() -> LinkPreviewOptions {
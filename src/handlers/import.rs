@@ -6,9 +6,9 @@ use regex::{Captures, Regex};
 use rust_i18n::t;
 use teloxide::Bot;
 use teloxide::macros::BotCommands;
-use teloxide::requests::Requester;
 use teloxide::types::{ChatId, Message, UserId};
 use crate::handlers::{HandlerResult, reply_html};
+use crate::handlers::checks::is_invoked_by_admin;
 use crate::{metrics, reply_html, repo};
 use crate::domain::{LanguageCode, Username};
 
@@ -182,15 +182,10 @@ pub async fn import_cmd_handler(bot: Bot, msg: Message, repos: repo::Repositorie
 }
 
 async fn check_and_parse_message(bot: &Bot, msg: &Message) -> Result<ParseResult, BeforeImportCheckErrors> {
-    let admin_ids = bot.get_chat_administrators(msg.chat.id)
-        .await?
-        .into_iter()
-        .map(|m| m.user.id);
     let from_id = msg.from.as_ref()
         .ok_or(BeforeImportCheckErrors::Other(anyhow!("not from a user")))?
         .id;
-    let invoked_by_admin = admin_ids.into_iter().any(|id| id == from_id);
-    if !invoked_by_admin {
+    if !is_invoked_by_admin(bot, msg, from_id).await? {
         return Err(BeforeImportCheckErrors::NotAdmin)
     }
 
@@ -14,7 +14,7 @@ use teloxide::types::*;
 use teloxide::types::ParseMode::Html;
 use crate::config::AppConfig;
 use crate::domain::{LanguageCode, Username};
-use crate::handlers::{build_pagination_keyboard, dick, dod, FromRefs, HandlerImplResult, HandlerResult, loan, stats, utils, pvp};
+use crate::handlers::{build_pagination_keyboard, dick, dod, FromRefs, HandlerImplResult, HandlerResult, loan, stats, utils, buttfight};
 use crate::handlers::utils::callbacks::CallbackDataWithPrefix;
 use crate::handlers::utils::Incrementor;
 use crate::handlers::utils::page::Page;
@@ -121,9 +121,10 @@ impl ExternalVariants {
 
 static EXTERNAL_VARIANTS: Lazy<ExternalVariants> = Lazy::new(|| ExternalVariants::new(&[
     ExternalVariant {
-        result_id: "pvp",
+        result_id: "penetrate",
         builder: |query, lang_code, app_config, name| {
-            pvp::build_inline_keyboard_article_result(query.from.id, lang_code, name, app_config.pvp_default_bet)
+            buttfight::build_inline_keyboard_article_result(query.from.id, lang_code, name, app_config.pvp_default_bet,
+                                                            None, app_config.pvp_side_bet)
         }
     }
 ]));
@@ -133,6 +134,7 @@ pub async fn inline_handler(bot: Bot, query: InlineQuery, repos: Repositories, a
 
     let name = utils::get_full_name(&query.from);
     repos.users.create_or_update(query.from.id, &name).await?;
+    repos.users.update_username(query.from.id, query.from.username.as_deref()).await?;
 
     let uid = query.from.id.0;
     let lang_code = LanguageCode::from_user(&query.from);
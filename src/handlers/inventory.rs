@@ -0,0 +1,110 @@
+use anyhow::anyhow;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::Message;
+use crate::{metrics, reply_html, repo};
+use crate::config::{AppConfig, ShopItem, ShopItemEffect};
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{FromRefs, HandlerResult, reply_html};
+use crate::handlers::shop::item_name;
+use crate::repo::{ActiveEffect, InventoryItem, UseError, UseResult};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum InventoryCommands {
+    #[command(description = "use")]
+    Use(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: InventoryCommands,
+                         repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let chat_id = msg.chat.id.into();
+    let from_refs = FromRefs(from, &chat_id);
+
+    let answer = match cmd {
+        InventoryCommands::Use(item) if item.trim().is_empty() => {
+            metrics::CMD_USE.invoked();
+            inventory_impl(&repos, from_refs).await?
+        }
+        InventoryCommands::Use(item) => {
+            metrics::CMD_USE.invoked();
+            use_impl(&repos, &config, from_refs, item.trim()).await?
+        }
+    };
+    reply_html!(bot, msg, answer);
+    Ok(())
+}
+
+pub(crate) async fn inventory_impl(repos: &repo::Repositories, from_refs: FromRefs<'_>) -> anyhow::Result<String> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+
+    let (items, effects) = futures::try_join!(
+        repos.inventory.get_items(&chat_id, from.id),
+        repos.inventory.get_active_effects(&chat_id, from.id),
+    )?;
+    if items.is_empty() && effects.is_empty() {
+        return Ok(t!("commands.use.empty", locale = &lang_code).to_string())
+    }
+
+    let mut parts = Vec::new();
+    if !items.is_empty() {
+        let lines = items.into_iter()
+            .map(|InventoryItem { item, quantity }| t!("commands.use.items.line", locale = &lang_code,
+                name = item_name(&item, &lang_code), code = item, quantity = quantity).to_string())
+            .collect::<Vec<String>>();
+        parts.push(format!("{}\n{}", t!("commands.use.items.title", locale = &lang_code), lines.join("\n")));
+    }
+    if !effects.is_empty() {
+        let lines = effects.into_iter()
+            .map(|ActiveEffect { item, target, value, charges_left, expires_at }| {
+                let t_key = format!("commands.use.effects.line.{target}");
+                t!(&t_key, locale = &lang_code, name = item_name(&item, &lang_code),
+                    value = Tenths::from(value).format(&lang_code), charges = charges_left,
+                    expires_at = expires_at.format("%d.%m.%Y %H:%M")).to_string()
+            })
+            .collect::<Vec<String>>();
+        parts.push(format!("{}\n{}", t!("commands.use.effects.title", locale = &lang_code), lines.join("\n")));
+    }
+    Ok(parts.join("\n\n"))
+}
+
+pub(crate) async fn use_impl(repos: &repo::Repositories, config: &AppConfig, from_refs: FromRefs<'_>,
+                             item: &str) -> anyhow::Result<String> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+
+    let Some(item) = find_item(config, item, &lang_code) else {
+        return Ok(t!("commands.use.errors.unknown_item", locale = &lang_code, item = item).to_string())
+    };
+    let answer = match repos.inventory.use_item(&chat_id, from.id, item).await {
+        Ok(UseResult { quantity_left, new_protrusion_level }) => {
+            metrics::CMD_USE.finished();
+            let name = item_name(&item.code, &lang_code);
+            let effect = match (item.effect, new_protrusion_level) {
+                (ShopItemEffect::Shrink(_), Some(level)) => t!("commands.use.success.shrink", locale = &lang_code,
+                    level = Tenths::from(level).format(&lang_code)),
+                (ShopItemEffect::Attempts(count), _) => t!("commands.use.success.attempts", locale = &lang_code, count = count),
+                (ShopItemEffect::Shield(hours), _) => t!("commands.use.success.shield", locale = &lang_code, hours = hours),
+                (ShopItemEffect::Boost(c) | ShopItemEffect::Cushion(c), _) => t!("commands.use.success.consumable", locale = &lang_code,
+                    charges = c.charges, hours = c.hours),
+                (ShopItemEffect::Shrink(_), None) => Err(anyhow!("no protrusion level after the use of {}", item.code))?,
+            };
+            t!("commands.use.success.template", locale = &lang_code,
+                name = name, effect = effect, left = quantity_left).to_string()
+        }
+        Err(UseError::Other(e)) => Err(e)?,
+        Err(e) => t!(&format!("commands.use.errors.{e}"), locale = &lang_code,
+            name = item_name(&item.code, &lang_code)).to_string()
+    };
+    Ok(answer)
+}
+
+/// Items can be referred to either by their codes or by their localized names.
+fn find_item<'a>(config: &'a AppConfig, item: &str, lang_code: &LanguageCode) -> Option<&'a ShopItem> {
+    let item = item.to_lowercase();
+    config.shop.items.iter()
+        .find(|i| i.code == item || item_name(&i.code, lang_code).to_lowercase() == item)
+}
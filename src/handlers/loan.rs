@@ -11,7 +11,7 @@ use callbacks::{EditMessageReqParamsKind, InvalidCallbackData};
 
 use crate::{check_invoked_by_owner_and_get_answer_params, metrics, repo};
 use crate::config::AppConfig;
-use crate::domain::LanguageCode;
+use crate::domain::{LanguageCode, Tenths};
 use crate::handlers::{CallbackButton, FromRefs, HandlerImplResult, HandlerResult, reply_html, try_resolve_chat_id};
 use crate::handlers::utils::callbacks;
 use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
@@ -50,7 +50,7 @@ pub(crate) async fn loan_impl(repos: &repo::Repositories, from_refs: FromRefs<'_
     let maybe_loan = repos.loans.get_active_loan(from.id, &chat_id_kind).await?;
     if let Some(Loan { debt, .. }) = maybe_loan {
         if !config.features.multiple_loans {
-            let left_to_pay = t!("commands.loan.debt", locale = &lang_code, debt = debt).to_string();
+            let left_to_pay = t!("commands.loan.debt", locale = &lang_code, debt = Tenths::from(i32::from(debt)).format(&lang_code)).to_string();
             return Ok(HandlerImplResult::OnlyText(left_to_pay))
         }
     }
@@ -88,7 +88,7 @@ pub(crate) async fn loan_impl(repos: &repo::Repositories, from_refs: FromRefs<'_
     );
     Ok(HandlerImplResult::WithKeyboard {
         text: t!("commands.loan.confirmation.text", locale = &lang_code,
-            debt = debt, payout_percentage = payout_percentage).to_string(),
+            debt = Tenths::from(i32::from(debt)).format(&lang_code), payout_percentage = payout_percentage).to_string(),
         buttons: vec![btn_agree, btn_disagree]
     })
 }
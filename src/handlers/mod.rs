@@ -11,6 +11,16 @@ pub mod buttfight;
 pub mod perks;
 pub mod loan;
 pub mod stats;
+pub mod history;
+pub mod battles;
+pub mod timezone;
+pub mod rules;
+pub mod achievements;
+pub mod shop;
+pub mod inventory;
+pub mod tournament;
+pub mod teambattle;
+pub mod duel;
 
 use derive_more::Constructor;
 use rust_i18n::t;
@@ -37,6 +47,8 @@ pub type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 pub enum CallbackResult {
     EditMessage(String, Option<InlineKeyboardMarkup>),
+    /// Keeps the text of the message intact.
+    EditKeyboard(InlineKeyboardMarkup),
     ShowError(String),
 }
 
@@ -68,6 +80,24 @@ impl CallbackResult {
                 };
                 answer_req.await?;
             },
+            CallbackResult::EditKeyboard(keyboard) => {
+                if let Some(message) = callback_query.message {
+                    let mut edit_req = bot.edit_message_reply_markup(message.chat().id, message.id());
+                    edit_req.reply_markup.replace(keyboard);
+                    if let Err(err) = edit_req.await {
+                        log::error!("couldn't edit the keyboard of the message ({}:{}): {}", message.chat().id, message.id(), err);
+                        Err(err)?;
+                    }
+                } else if let Some(inline_message_id) = callback_query.inline_message_id {
+                    let mut edit_req = bot.edit_message_reply_markup_inline(&inline_message_id);
+                    edit_req.reply_markup.replace(keyboard);
+                    if let Err(err) = edit_req.await {
+                        log::error!("couldn't edit the keyboard of the message ({}): {}", inline_message_id, err);
+                        Err(err)?;
+                    }
+                };
+                answer_req.await?;
+            },
             CallbackResult::ShowError(err) => {
                 answer_req
                     .text(err)
@@ -147,7 +177,8 @@ pub async fn send_error_callback_answer(bot: Bot, query: CallbackQuery, tr_key:
 pub mod checks {
     use rust_i18n::t;
     use teloxide::Bot;
-    use teloxide::types::Message;
+    use teloxide::requests::Requester;
+    use teloxide::types::{Message, UserId};
     use crate::domain::LanguageCode;
     use super::{HandlerResult, reply_html};
 
@@ -169,6 +200,14 @@ pub mod checks {
         Ok(())
     }
 
+    pub async fn is_invoked_by_admin(bot: &Bot, msg: &Message, from_id: UserId) -> anyhow::Result<bool> {
+        let invoked_by_admin = bot.get_chat_administrators(msg.chat.id)
+            .await?
+            .into_iter()
+            .any(|m| m.user.id == from_id);
+        Ok(invoked_by_admin)
+    }
+
     pub mod inline {
         use teloxide::Bot;
         use teloxide::payloads::AnswerInlineQuerySetters;
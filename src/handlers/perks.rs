@@ -1,19 +1,29 @@
+use std::collections::BTreeMap;
 use async_trait::async_trait;
 use num_traits::ToPrimitive;
 use sqlx::{Pool, Postgres};
-use crate::handlers::utils::{AdditionalChange, ChangeIntent, ConfigurablePerk, DickId, Perk};
+use crate::handlers::utils::{AdditionalChange, ChangeIntent, ConfigurablePerk, DickId, Perk, ITEM_PERK_PREFIX};
 use crate::{config, repo};
+use crate::domain::Tenths;
 
-pub fn all(pool: &Pool<Postgres>, cfg: &config::AppConfig) -> Vec<Box<dyn Perk>> {
+pub fn all(pool: &Pool<Postgres>, cfg: &config::AppConfig) -> anyhow::Result<Vec<Box<dyn Perk>>> {
     let help_pussies_coef = config::get_env_value_or_default("HELP_PUSSIES_COEF", 0.0);
     let loans = repo::Loans::new(pool.clone(), cfg);
+    let hemoroids = repo::Hemoroids::new(pool.clone(), cfg.features);
+    let inventory = repo::Inventory::new(pool.clone(), cfg.features);
     
-    vec![
+    Ok(vec![
         Box::new(HelpPussiesPerk {
             coefficient: help_pussies_coef,
         }),
-        Box::new(LoanPayoutPerk { loans })
-    ]
+        Box::new(LoanPayoutPerk { loans }),
+        Box::new(StreakPerk {
+            hemoroids,
+            bonus_per_day: config::get_length_env_value_or_default("STREAK_BONUS_PER_DAY", Tenths::from(1))?,
+            bonus_max: config::get_length_env_value_or_default("STREAK_BONUS_MAX", Tenths::from(5))?,
+        }),
+        Box::new(InventoryPerk { inventory }),
+    ])
 }
 
 pub struct HelpPussiesPerk {
@@ -79,19 +89,97 @@ impl Perk for LoanPayoutPerk {
         } else {
             0
         };
-        match self.loans.pay(dick_id.0, &dick_id.1, payout).await {
-            Ok(()) => AdditionalChange(-i32::from(payout)),
-            Err(e) => {
-                log::error!("couldn't pay {payout} cm for the loan ({dick_id}): {e}");
-                AdditionalChange(0)
-            }
+        AdditionalChange(-i32::from(payout))
+    }
+
+    fn settlement(&self, sources: &[(String, AdditionalChange)]) -> Option<repo::PerkSettlement> {
+        let payout: i32 = sources.iter()
+            .map(|(_, AdditionalChange(change))| -change)
+            .sum();
+        u16::try_from(payout).ok()
+            .filter(|payout| *payout > 0)
+            .map(repo::PerkSettlement::LoanPayout)
+    }
+}
+
+/// Rewards those who keep treating their hemorrhoids day after day.
+pub struct StreakPerk {
+    hemoroids: repo::Hemoroids,
+    bonus_per_day: u16,
+    bonus_max: u16,
+}
+
+#[async_trait]
+impl Perk for StreakPerk {
+    fn name(&self) -> &str {
+        "streak"
+    }
+
+    async fn apply(&self, dick_id: &DickId, _: ChangeIntent) -> AdditionalChange {
+        let streak = self.hemoroids.fetch_streak(dick_id.0, &dick_id.1)
+            .await
+            .inspect_err(|e| log::error!("couldn't fetch the streak ({dick_id}): {e}"))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        AdditionalChange(streak_bonus(streak, self.bonus_per_day, self.bonus_max))
+    }
+
+    fn enabled(&self) -> bool {
+        self.bonus_per_day > 0 && self.bonus_max > 0
+    }
+}
+
+/// Applies the active effects of the used consumable items, like creams.
+pub struct InventoryPerk {
+    inventory: repo::Inventory,
+}
+
+#[async_trait]
+impl Perk for InventoryPerk {
+    fn name(&self) -> &str {
+        "inventory"
+    }
+
+    async fn apply(&self, dick_id: &DickId, change_intent: ChangeIntent) -> AdditionalChange {
+        let total = self.apply_by_sources(dick_id, change_intent).await
+            .into_iter()
+            .map(|(_, AdditionalChange(change))| change)
+            .sum();
+        AdditionalChange(total)
+    }
+
+    async fn apply_by_sources(&self, dick_id: &DickId, _: ChangeIntent) -> Vec<(String, AdditionalChange)> {
+        let effects = self.inventory.get_active_effects(&dick_id.1, dick_id.0)
+            .await
+            .inspect_err(|e| log::error!("couldn't get the effects of the items ({dick_id}): {e}"))
+            .unwrap_or_default();
+        let mut by_items: BTreeMap<String, i32> = BTreeMap::new();
+        for effect in effects.into_iter().filter(|effect| effect.target == repo::EffectTarget::Treatment) {
+            *by_items.entry(effect.item).or_default() += effect.value;
         }
+        by_items.into_iter()
+            .map(|(item, value)| (format!("{ITEM_PERK_PREFIX}{item}"), AdditionalChange(value)))
+            .collect()
+    }
+
+    /// The charges are taken only if the treatment has taken place.
+    fn settlement(&self, sources: &[(String, AdditionalChange)]) -> Option<repo::PerkSettlement> {
+        (!sources.is_empty()).then_some(repo::PerkSettlement::ConsumeEffects(repo::EffectTarget::Treatment))
     }
 }
 
+/// The streak is kept only if the hemorrhoid was treated yesterday and hasn't been treated yet today.
+fn streak_bonus(streak: repo::Streak, bonus_per_day: u16, bonus_max: u16) -> i32 {
+    if streak.treated_today || streak.current <= 0 {
+        return 0
+    }
+    (streak.current * i32::from(bonus_per_day)).min(i32::from(bonus_max))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::handlers::perks::{HelpPussiesPerk, LoanPayoutPerk};
+    use crate::handlers::perks::{streak_bonus, HelpPussiesPerk, LoanPayoutPerk};
     use crate::handlers::utils::{ChangeIntent, DickId, Perk};
     use crate::{config, repo};
     use crate::repo::test::{CHAT_ID_KIND, start_postgres, USER_ID};
@@ -125,15 +213,16 @@ mod test {
             };
             repo::Loans::new(db.clone(), &cfg)
         };
+        let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+        let chat_id: repo::ChatIdPartiality = CHAT_ID_KIND.into();
 
         {
             let users = repo::Users::new(db.clone());
             users.create_or_update(USER_ID, "")
                 .await.expect("couldn't create a user");
             
-            let dicks = repo::Dicks::new(db, Default::default());
-            dicks.create_or_grow(USER_ID, &CHAT_ID_KIND.into(), 0)
-                .await.expect("couldn't create a dick");
+            hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+                .await.expect("couldn't create a hemorrhoid");
         }
 
         let perk = LoanPayoutPerk { loans: loans.clone() };
@@ -148,19 +237,50 @@ mod test {
         loans.borrow(USER_ID, &CHAT_ID_KIND, 10)
             .await.expect("couldn't create a loan");
 
-        assert_eq!(perk.apply(&dick_id, change_intent_positive_increment).await.0, -1);
-        let debt = loans.get_active_loan(USER_ID, &CHAT_ID_KIND)
-            .await.expect("couldn't fetch the active loan")
-            .expect("loan must be found")
-            .debt;
-        assert_eq!(debt, 9);
+        let sources = perk.apply_by_sources(&dick_id, change_intent_positive_increment).await;
+        assert_eq!(sources[0].1.0, -1);
+        let settlement = perk.settlement(&sources);
+        assert_eq!(settlement, Some(repo::PerkSettlement::LoanPayout(1)));
+        // nothing is paid until the change is applied
+        assert_eq!(get_debt(&loans).await, 10);
+
+        let change = repo::LevelChange {
+            settlements: settlement.into_iter().collect(),
+            ..(-1).into()
+        };
+        // the hemorrhoid has been already treated today
+        assert!(hemoroids.create_or_shrink(USER_ID, &chat_id, change.clone()).await.is_err());
+        assert_eq!(get_debt(&loans).await, 10);
+
+        // a bonus attempt lets the update pass through the trigger without affecting the streak
+        sqlx::query!("UPDATE Hemoroids SET updated_at = updated_at - interval '1 day', bonus_attempts = bonus_attempts + 1")
+            .execute(&db)
+            .await.expect("couldn't move the last treatment back");
+        hemoroids.create_or_shrink(USER_ID, &chat_id, change)
+            .await.expect("couldn't treat the hemorrhoid");
+        assert_eq!(get_debt(&loans).await, 9);
 
-        assert_eq!(perk.apply(&dick_id, change_intent_positive_increment_small).await.0, 0);
-        assert_eq!(perk.apply(&dick_id, change_intent_negative_increment).await.0, 0);
-        let debt = loans.get_active_loan(USER_ID, &CHAT_ID_KIND)
+        for change_intent in [change_intent_positive_increment_small, change_intent_negative_increment] {
+            let sources = perk.apply_by_sources(&dick_id, change_intent).await;
+            assert_eq!(sources[0].1.0, 0);
+            assert_eq!(perk.settlement(&sources), None);
+        }
+    }
+
+    async fn get_debt(loans: &repo::Loans) -> u16 {
+        loans.get_active_loan(USER_ID, &CHAT_ID_KIND)
             .await.expect("couldn't fetch the active loan")
             .expect("loan must be found")
-            .debt;
-        assert_eq!(debt, 9);
+            .debt
+    }
+
+    #[test]
+    fn test_streak_bonus() {
+        let streak = |current, treated_today| repo::Streak { current, max: current, treated_today };
+        assert_eq!(streak_bonus(streak(0, false), 1, 5), 0);
+        assert_eq!(streak_bonus(streak(3, true), 1, 5), 0);
+        assert_eq!(streak_bonus(streak(3, false), 1, 5), 3);
+        assert_eq!(streak_bonus(streak(10, false), 1, 5), 5);
+        assert_eq!(streak_bonus(streak(2, false), 2, 5), 4);
     }
 }
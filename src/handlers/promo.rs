@@ -8,9 +8,9 @@ use teloxide::macros::BotCommands;
 use teloxide::payloads::AnswerInlineQuerySetters;
 use teloxide::prelude::{Dialogue, InlineQuery, Requester};
 use teloxide::types::{InlineQueryResultsButton, InlineQueryResultsButtonKind, Message, User};
-use crate::handlers::{HandlerResult, reply_html};
+use crate::handlers::{achievements, HandlerResult, reply_html};
 use crate::{metrics, reply_html, repo};
-use crate::domain::LanguageCode;
+use crate::domain::{LanguageCode, Tenths, Username};
 use crate::repo::ActivationError;
 
 pub(crate) const PROMO_START_PARAM_PREFIX: &str = "promo-";
@@ -50,7 +50,7 @@ pub async fn promo_cmd_handler(bot: Bot, msg: Message, cmd: PromoCommands, dialo
         PromoCommands::Promo(code) => {
             dialogue.exit().await?;
             
-            promo_activation_impl(repos.promo, user, &code).await?
+            promo_activation_impl(&repos, user, &code).await?
         },
     };
     reply_html!(bot, msg, answer);
@@ -64,7 +64,7 @@ pub async fn promo_requested_handler(bot: Bot, msg: Message, dialogue: PromoCode
             dialogue.exit().await?;
             
             let user = msg.from.as_ref().ok_or("no from user")?;
-            promo_activation_impl(repos.promo, user, code).await?
+            promo_activation_impl(&repos, user, code).await?
         },
         None => {
             let lang_code = LanguageCode::from_maybe_user(msg.from.as_ref());
@@ -101,9 +101,9 @@ pub async fn promo_inline_handler(bot: Bot, query: InlineQuery) -> HandlerResult
     Ok(())
 }
 
-pub(crate) async fn promo_activation_impl(promo_repo: repo::Promo, user: &User, promo_code: &str) -> anyhow::Result<String> {
+pub(crate) async fn promo_activation_impl(repos: &repo::Repositories, user: &User, promo_code: &str) -> anyhow::Result<String> {
     let lang_code = LanguageCode::from_user(user);
-    let answer = match promo_repo.activate(user.id, promo_code).await {
+    let answer = match repos.promo.activate(user.id, promo_code).await {
         Ok(res) => {
             metrics::CMD_PROMO.finished.inc();
             let suffix = if res.chats_affected > 1 {
@@ -112,11 +112,17 @@ pub(crate) async fn promo_activation_impl(promo_repo: repo::Promo, user: &User,
                 "singular"
             };
             let chats_in_russian = get_chats_in_russian(res.chats_affected);
-            t!("commands.promo.success.template", locale = &lang_code,
+            let answer = t!("commands.promo.success.template", locale = &lang_code,
                 ending = t!(&format!("commands.promo.success.{suffix}"), locale = &lang_code,
-                    growth = res.bonus_length, affected_chats = res.chats_affected,
+                    growth = Tenths::from(res.bonus_length).format(&lang_code), affected_chats = res.chats_affected,
                     word_chats = chats_in_russian))
-                .to_string()
+                .to_string();
+            let codes = repos.achievements.check_in_all_chats(user.id)
+                .await
+                .inspect_err(|e| log::error!("couldn't check the achievements of {} after promo code activation: {e}", user.id))
+                .unwrap_or_default();
+            let name = Username::new(user.first_name.clone()).escaped();
+            format!("{answer}{}", achievements::announcement(&codes, &name, &lang_code))
         },
         Err(e) => {
             let suffix = match e {
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use rand::Rng;
+use rust_i18n::t;
+use strum::IntoEnumIterator;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::Message;
+use teloxide::utils::html;
+use crate::handlers::{HandlerResult, reply_html};
+use crate::handlers::checks::is_invoked_by_admin;
+use crate::{metrics, reply_html, repo};
+use crate::config::DamageProfile;
+use crate::domain::{LanguageCode, Tenths};
+use crate::repo::{BattleRuleSet, ChatIdPartiality};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum RulesCommands {
+    #[command(description = "rules")]
+    Rules(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: RulesCommands, repos: repo::Repositories) -> HandlerResult {
+    metrics::CMD_RULES_COUNTER.inc();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let chat_id: ChatIdPartiality = msg.chat.id.into();
+    let RulesCommands::Rules(rules) = cmd;
+    let rules = rules.trim();
+
+    let answer = if rules.is_empty() {
+        let current = repos.chats.get_battle_rules(&chat_id.kind()).await?;
+        t!("commands.rules.current", locale = &lang_code, rules = rule_set_name(current, &lang_code),
+            available = available_rule_sets(&lang_code)).to_string()
+    } else if !is_invoked_by_admin(&bot, &msg, from.id).await? {
+        t!("commands.rules.errors.not_admin", locale = &lang_code).to_string()
+    } else {
+        match BattleRuleSet::from_str(&rules.to_lowercase()) {
+            Ok(rule_set) => {
+                repos.chats.set_battle_rules(&chat_id, rule_set).await?;
+                t!("commands.rules.success", locale = &lang_code, rules = rule_set_name(rule_set, &lang_code)).to_string()
+            }
+            Err(_) => t!("commands.rules.errors.unknown", locale = &lang_code, rules = html::escape(rules),
+                available = available_rule_sets(&lang_code)).to_string()
+        }
+    };
+    reply_html!(bot, msg, answer);
+    Ok(())
+}
+
+fn rule_set_name(rule_set: BattleRuleSet, lang_code: &LanguageCode) -> String {
+    t!(&format!("commands.rules.names.{rule_set}"), locale = lang_code).to_string()
+}
+
+fn available_rule_sets(lang_code: &LanguageCode) -> String {
+    BattleRuleSet::iter()
+        .map(|rule_set| t!("commands.rules.available", locale = lang_code, key = rule_set,
+            name = rule_set_name(rule_set, lang_code),
+            summary = t!(&format!("commands.rules.summaries.{rule_set}"), locale = lang_code)).to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Decides how a battle is fought. Everything around it, i.e. the challenges, the locks, the bets and the statistics,
+/// is shared by all the rule sets in the engine of the `buttfight` module.
+pub(crate) trait BattleRules: Send + Sync {
+    /// Rolls the swelling of the top and the bottom before it's softened.
+    /// `None` means nobody swells, so the shields and cushions are kept for the next battle.
+    fn roll_damage(&self, profile: &DamageProfile) -> Option<(i32, i32)>;
+
+    /// Called with the final swelling of the participants.
+    fn top_wins(&self, top_damage: i32, bottom_damage: i32) -> bool;
+
+    fn describe(&self, lang_code: &LanguageCode, outcome: &BattleOutcome) -> String;
+}
+
+/// The names are escaped already.
+pub(crate) struct BattleOutcome<'a> {
+    pub top_name: &'a str,
+    pub top_damage: i32,
+    pub bottom_name: &'a str,
+    pub bottom_damage: i32,
+    pub winner_name: &'a str,
+    pub winner_level: i32,
+    pub loser_level: i32,
+    pub bet: u16,
+}
+
+pub(crate) fn of(rule_set: BattleRuleSet) -> &'static dyn BattleRules {
+    match rule_set {
+        BattleRuleSet::TopBottom => &TopBottomRules,
+        BattleRuleSet::Classic => &ClassicRules,
+    }
+}
+
+/// Both participants swell, and the one who has swelled less wins.
+struct TopBottomRules;
+
+impl BattleRules for TopBottomRules {
+    fn roll_damage(&self, profile: &DamageProfile) -> Option<(i32, i32)> {
+        let mut rng = rand::thread_rng();
+        Some((profile.top.roll(&mut rng), profile.bottom.roll(&mut rng)))
+    }
+
+    fn top_wins(&self, top_damage: i32, bottom_damage: i32) -> bool {
+        top_damage.abs() < bottom_damage.abs()
+    }
+
+    fn describe(&self, lang_code: &LanguageCode, outcome: &BattleOutcome) -> String {
+        let top_outcome = if outcome.top_damage > 0 {
+            t!("commands.penetrate.results.top_swelled", locale = lang_code, name = outcome.top_name,
+                damage = Tenths::from(outcome.top_damage.abs()).format(lang_code))
+        } else {
+            t!("commands.penetrate.results.top_improved", locale = lang_code, name = outcome.top_name,
+                improvement = Tenths::from(outcome.top_damage.abs()).format(lang_code))
+        };
+        let bottom_outcome = if outcome.bottom_damage > 0 {
+            t!("commands.penetrate.results.bottom_swelled", locale = lang_code, name = outcome.bottom_name,
+                damage = Tenths::from(outcome.bottom_damage.abs()).format(lang_code))
+        } else {
+            t!("commands.penetrate.results.bottom_improved", locale = lang_code, name = outcome.bottom_name,
+                improvement = Tenths::from(outcome.bottom_damage.abs()).format(lang_code))
+        };
+        let winner_msg = t!("commands.penetrate.results.winner", locale = lang_code, name = outcome.winner_name, bet = outcome.bet);
+        format!("{top_outcome}\n\n{bottom_outcome}\n\n{winner_msg}")
+    }
+}
+
+/// The bet just moves to the winner chosen by a coin toss, like in the length battles of old.
+struct ClassicRules;
+
+impl BattleRules for ClassicRules {
+    fn roll_damage(&self, _profile: &DamageProfile) -> Option<(i32, i32)> {
+        None
+    }
+
+    fn top_wins(&self, _top_damage: i32, _bottom_damage: i32) -> bool {
+        rand::thread_rng().gen_bool(0.5)
+    }
+
+    fn describe(&self, lang_code: &LanguageCode, outcome: &BattleOutcome) -> String {
+        t!("commands.penetrate.results.finish", locale = lang_code, winner_name = outcome.winner_name,
+            winner_level = Tenths::from(outcome.winner_level).format(lang_code),
+            loser_level = Tenths::from(outcome.loser_level).format(lang_code),
+            bet = outcome.bet).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use crate::config::DamageProfile;
+    use crate::repo::BattleRuleSet;
+    use super::{of, BattleRules, TopBottomRules};
+
+    #[test]
+    fn test_rule_sets() {
+        assert_eq!(BattleRuleSet::from_str("classic").ok(), Some(BattleRuleSet::Classic));
+        assert_eq!(BattleRuleSet::TopBottom.to_string(), "top_bottom");
+        assert!(BattleRuleSet::from_str("pvp").is_err());
+
+        let profile = DamageProfile::default();
+        assert!(of(BattleRuleSet::TopBottom).roll_damage(&profile).is_some());
+        assert!(of(BattleRuleSet::Classic).roll_damage(&profile).is_none());
+    }
+
+    #[test]
+    fn test_top_bottom_winner() {
+        assert!(TopBottomRules.top_wins(5, 10));
+        assert!(TopBottomRules.top_wins(-5, 10));
+        assert!(!TopBottomRules.top_wins(10, -5));
+        // a draw goes to the bottom
+        assert!(!TopBottomRules.top_wins(5, 5));
+    }
+}
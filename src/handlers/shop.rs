@@ -0,0 +1,160 @@
+use anyhow::anyhow;
+use derive_more::Display;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message, UserId};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ReplyMarkup};
+use callbacks::InvalidCallbackData;
+
+use crate::{metrics, repo};
+use crate::config::{AppConfig, ShopCurrency, ShopItem, ShopItemEffect};
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackResult, FromRefs, HandlerResult, reply_html};
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::repo::{ChatIdKind, PurchaseError, PurchaseResult};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum ShopCommands {
+    #[command(description = "shop")]
+    Shop,
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_SHOP.invoked();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let chat_id = msg.chat.id.into();
+    let from_refs = FromRefs(from, &chat_id);
+
+    let (text, keyboard) = shop_impl(&repos, &config, from_refs).await?;
+    let mut request = reply_html(bot, &msg, text);
+    request.reply_markup = keyboard.map(ReplyMarkup::InlineKeyboard);
+    request.await?;
+    Ok(())
+}
+
+pub(crate) async fn shop_impl(repos: &repo::Repositories, config: &AppConfig,
+                              from_refs: FromRefs<'_>) -> anyhow::Result<(String, Option<InlineKeyboardMarkup>)> {
+    let (from, chat_id) = (from_refs.0, from_refs.1.kind());
+    let lang_code = LanguageCode::from_user(from);
+
+    if config.shop.items.is_empty() {
+        return Ok((t!("errors.feature_disabled", locale = &lang_code).to_string(), None))
+    }
+
+    let coins = repos.shop.get_coins(&chat_id, from.id).await?;
+    Ok((render_catalogue(config, coins, &lang_code), Some(build_keyboard(config, from.id, &lang_code))))
+}
+
+fn render_catalogue(config: &AppConfig, coins: i32, lang_code: &LanguageCode) -> String {
+    let lines = config.shop.items.iter()
+        .map(|item| t!("commands.shop.line", locale = lang_code,
+            name = item_name(&item.code, lang_code),
+            description = item_description(item, lang_code),
+            price = format_price(item, lang_code)).to_string())
+        .collect::<Vec<String>>();
+    let title = t!("commands.shop.title", locale = lang_code, coins = coins,
+        coins_per_win = config.shop.coins_per_win);
+    format!("{}\n\n{}", title, lines.join("\n"))
+}
+
+fn build_keyboard(config: &AppConfig, uid: UserId, lang_code: &LanguageCode) -> InlineKeyboardMarkup {
+    let buttons = config.shop.items.iter()
+        .map(|item| {
+            let title = t!("commands.shop.button", locale = lang_code,
+                name = item_name(&item.code, lang_code), price = format_price(item, lang_code));
+            let data = ShopCallbackData { uid, item: item.code.clone() };
+            vec![InlineKeyboardButton::callback(title, data.to_data_string())]
+        });
+    InlineKeyboardMarkup::new(buttons)
+}
+
+pub(crate) fn item_name(code: &str, lang_code: &LanguageCode) -> String {
+    t!(&format!("items.{code}.name"), locale = lang_code).to_string()
+}
+
+fn item_description(item: &ShopItem, lang_code: &LanguageCode) -> String {
+    let key = format!("items.{}.description", item.code);
+    match item.effect {
+        ShopItemEffect::Shrink(value) => t!(&key, locale = lang_code, value = Tenths::from(i32::from(value)).format(lang_code)),
+        ShopItemEffect::Attempts(value) | ShopItemEffect::Shield(value) => t!(&key, locale = lang_code, value = value),
+        ShopItemEffect::Boost(c) | ShopItemEffect::Cushion(c) => t!(&key, locale = lang_code,
+            value = Tenths::from(i32::from(c.value)).format(lang_code), charges = c.charges, hours = c.hours),
+    }.to_string()
+}
+
+fn format_price(item: &ShopItem, lang_code: &LanguageCode) -> String {
+    match item.currency {
+        ShopCurrency::Cm => t!("commands.shop.price.cm", locale = lang_code,
+            price = Tenths::from(i32::from(item.price)).format(lang_code)),
+        ShopCurrency::Coins => t!("commands.shop.price.coins", locale = lang_code, price = item.price),
+    }.to_string()
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    ShopCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery,
+                              repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let data = ShopCallbackData::parse(&query)?;
+    let chat_id: ChatIdKind = callbacks::get_params_for_message_edit(&query)?.into();
+    let result = buy_impl(&repos, &config, &query, &chat_id, data).await?;
+    result.apply(bot, query).await?;
+    Ok(())
+}
+
+async fn buy_impl(repos: &repo::Repositories, config: &AppConfig, query: &CallbackQuery,
+                  chat_id: &ChatIdKind, data: ShopCallbackData) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    if query.from.id != data.uid {
+        return Ok(CallbackResult::ShowError(t!("inline.callback.errors.another_user", locale = &lang_code).to_string()))
+    }
+    let Some(item) = config.shop.item(&data.item) else {
+        return Ok(CallbackResult::ShowError(t!("commands.shop.errors.unknown_item", locale = &lang_code).to_string()))
+    };
+
+    let result = match repos.shop.buy(chat_id, data.uid, item).await {
+        Ok(PurchaseResult { new_protrusion_level, coins_left, quantity }) => {
+            metrics::CMD_SHOP.finished();
+            let success = t!("commands.shop.success", locale = &lang_code,
+                name = item_name(&item.code, &lang_code), code = item.code, quantity = quantity,
+                level = Tenths::from(new_protrusion_level).format(&lang_code));
+            let catalogue = render_catalogue(config, coins_left, &lang_code);
+            let keyboard = build_keyboard(config, data.uid, &lang_code);
+            CallbackResult::EditMessage(format!("{success}\n\n{catalogue}"), Some(keyboard))
+        }
+        Err(PurchaseError::Other(e)) => Err(e)?,
+        Err(e) => CallbackResult::ShowError(t!(&format!("commands.shop.errors.{e}"), locale = &lang_code).to_string())
+    };
+    Ok(result)
+}
+
+#[derive(Display)]
+#[display("{uid}:{item}")]
+pub(crate) struct ShopCallbackData {
+    uid: UserId,
+    item: String,
+}
+
+impl CallbackDataWithPrefix for ShopCallbackData {
+    fn prefix() -> &'static str {
+        "shop"
+    }
+}
+
+impl TryFrom<String> for ShopCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.as_str().split(':');
+        let uid = callbacks::parse_part(&mut parts, &err, "uid").map(UserId)?;
+        let item = callbacks::parse_part(&mut parts, &err, "item")?;
+        Ok(Self { uid, item })
+    }
+}
@@ -28,7 +28,7 @@ pub async fn start_cmd_handler(bot: Bot, msg: Message, cmd: StartCommands,
                 let encoded_promo_code = promo_code.strip_prefix(PROMO_START_PARAM_PREFIX)
                     .expect("promo start param prefix must be present here");
                 let promo_code = decode_promo_code(encoded_promo_code)?;
-                promo_activation_impl(repos.promo, user, &promo_code).await?
+                promo_activation_impl(&repos, user, &promo_code).await?
             }
             StartCommands::Start(_) => {
                 metrics::CMD_START_COUNTER.inc();
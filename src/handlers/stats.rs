@@ -6,7 +6,7 @@ use teloxide::prelude::Message;
 use crate::handlers::{FromRefs, HandlerResult, reply_html};
 use crate::{metrics, reply_html, repo};
 use crate::config::{AppConfig, BattlesFeatureToggles};
-use crate::domain::LanguageCode;
+use crate::domain::{LanguageCode, Tenths};
 use crate::repo::WinRateAware;
 
 #[derive(BotCommands, Clone)]
@@ -47,21 +47,30 @@ async fn personal_stats_impl(repos: &repo::Repositories, from_refs: FromRefs<'_>
 
 pub(crate) async fn chat_stats_impl(repos: &repo::Repositories, from_refs: FromRefs<'_>, features: BattlesFeatureToggles) -> anyhow::Result<String> {
     let lang_code = LanguageCode::from_user(from_refs.0);
-    let (length, position) = repos.dicks.fetch_dick(from_refs.0.id, &from_refs.1.kind()).await?
-        .map(|dick| (dick.length, dick.position.unwrap_or_default()))
+    let chat_id_kind = from_refs.1.kind();
+    let (level, position) = repos.hemoroids.fetch_hemoroid(from_refs.0.id, &chat_id_kind).await?
+        .map(|hemoroid| (hemoroid.protrusion_level, hemoroid.position.unwrap_or_default()))
+        .unwrap_or_default();
+    let streak = repos.hemoroids.fetch_streak(from_refs.0.id, &chat_id_kind).await?
         .unwrap_or_default();
     let length_stats = t!("commands.stats.length", locale = &lang_code,
-        length = length, pos = position);
+        length = Tenths::from(level).format(&lang_code), pos = position);
+    let streak_stats = t!("commands.stats.streak", locale = &lang_code,
+        current = streak.current, max = streak.max);
     let pvp_stats = repos.pvp_stats.get_stats(&from_refs.1.kind(), from_refs.0.id).await
         .map(|stats| t!("commands.stats.pvp", locale = &lang_code,
             win_rate = stats.win_rate_formatted(), win_streak = stats.win_streak_max,
             battles = stats.battles_total, wins = stats.battles_won,
-            acquired = stats.acquired_length, lost = stats.lost_length))
+            acquired = Tenths::from(stats.acquired_length as i32).format(&lang_code),
+            lost = Tenths::from(stats.lost_length as i32).format(&lang_code),
+            mercies = stats.mercies_shown,
+            team_battles = stats.team_battles_total, team_wins = stats.team_battles_won,
+            rating = stats.rating))
         .map(|s| if features.show_stats_notice {
             let notice = t!("commands.stats.notice", locale = &lang_code);
             format!("{}\n\n<i>{}</i>", s, notice)
         } else {
             s.to_string()
         })?;
-    Ok(format!("{length_stats}\n\n{pvp_stats}"))
+    Ok(format!("{length_stats}\n{streak_stats}\n\n{pvp_stats}"))
 }
@@ -0,0 +1,239 @@
+use anyhow::anyhow;
+use derive_more::Display;
+use rand::Rng;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message, UserId};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ReplyMarkup};
+use callbacks::InvalidCallbackData;
+use crate::{metrics, reply_html, repo};
+use crate::config::AppConfig;
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackResult, HandlerResult, reply_html, utils};
+use crate::handlers::buttfight::scale_damage;
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::repo::{ChatIdKind, ChatIdPartiality, Team, TeamJoinError, TeamLobby};
+
+const MAX_TEAM_SIZE: u16 = 5;
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum TeamBattleCommands {
+    #[command(description = "teambattle")]
+    Teambattle(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: TeamBattleCommands,
+                         repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_TEAMBATTLE.invoked();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let chat_id = ChatIdKind::ID(msg.chat.id);
+    let TeamBattleCommands::Teambattle(args) = cmd;
+
+    let Some((team_size, bet)) = parse_size_and_bet(&args, config.pvp_default_bet) else {
+        reply_html!(bot, msg, t!("commands.teambattle.errors.invalid_args", locale = &lang_code, max = MAX_TEAM_SIZE));
+        return Ok(())
+    };
+    if !repos.hemoroids.check_hemoroid(&chat_id, from.id, Tenths::from_cm(bet.into())).await? {
+        reply_html!(bot, msg, t!("commands.penetrate.errors.not_enough.initiator", locale = &lang_code));
+        return Ok(())
+    }
+
+    let lobby = repos.team_battles.create(&chat_id, from.id, team_size, bet).await?;
+    let mut answer = reply_html(bot, &msg, lobby_text(&lobby, &utils::get_full_name(from).escaped(), &lang_code));
+    answer.reply_markup = Some(ReplyMarkup::InlineKeyboard(lobby_keyboard(&lobby, &lang_code)));
+    answer.await?;
+    Ok(())
+}
+
+/// Both arguments are optional: `/teambattle [size] [bet]`.
+fn parse_size_and_bet(args: &str, default_bet: u16) -> Option<(u16, u16)> {
+    let mut parts = args.split_whitespace();
+    let team_size = parts.next().map_or(Some(2), |s| s.parse().ok())?;
+    let bet = parts.next().map_or(Some(default_bet), |s| s.parse().ok())?;
+    let valid = parts.next().is_none() && (2..=MAX_TEAM_SIZE).contains(&team_size) && bet > 0;
+    valid.then_some((team_size, bet))
+}
+
+fn lobby_text(lobby: &TeamLobby, initiator_name: &str, lang_code: &LanguageCode) -> String {
+    let team_list = |team: Team| {
+        let names = lobby.team(team)
+            .map(|m| format!("• {}", m.name.escaped()))
+            .collect::<Vec<String>>();
+        if names.is_empty() {
+            t!("commands.teambattle.empty_team", locale = lang_code).to_string()
+        } else {
+            names.join("\n")
+        }
+    };
+    t!("commands.teambattle.lobby", locale = lang_code, name = initiator_name, size = lobby.team_size, bet = lobby.bet,
+        team_a = team_list(Team::A), team_b = team_list(Team::B)).to_string()
+}
+
+fn lobby_keyboard(lobby: &TeamLobby, lang_code: &LanguageCode) -> InlineKeyboardMarkup {
+    let buttons = [Team::A, Team::B].map(|team| {
+        let label = t!("commands.teambattle.button", locale = lang_code, team = team_name(team),
+            count = lobby.team(team).count(), size = lobby.team_size);
+        let data = TeamBattleCallbackData { battle_id: lobby.id, team };
+        InlineKeyboardButton::callback(label, data.to_data_string())
+    });
+    InlineKeyboardMarkup::new(vec![buttons.to_vec()])
+}
+
+fn team_name(team: Team) -> String {
+    team.to_string().to_uppercase()
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    TeamBattleCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery, repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    let data = TeamBattleCallbackData::parse(&query)?;
+    let chat_id = query.message.as_ref()
+        .map(|msg| msg.chat().id)
+        .ok_or(anyhow!("team battles are started by commands, so the message must be present"))?;
+    let result = join_impl(&repos, &config, &query, ChatIdKind::ID(chat_id), data).await?;
+    result.apply(bot, query).await?;
+    Ok(())
+}
+
+async fn join_impl(repos: &repo::Repositories, config: &AppConfig, query: &CallbackQuery,
+                   chat_id: ChatIdKind, data: TeamBattleCallbackData) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let Some(lobby) = repos.team_battles.get_lobby(data.battle_id).await? else {
+        return Ok(CallbackResult::ShowError(t!("commands.teambattle.errors.started", locale = &lang_code).to_string()))
+    };
+    let max_level = if config.features.pvp.check_acceptor_length { Tenths::from_cm(lobby.bet.into()) } else { Tenths::from(0) };
+    if !repos.hemoroids.check_hemoroid(&chat_id, query.from.id, max_level).await? {
+        return Ok(CallbackResult::ShowError(t!("commands.penetrate.errors.not_enough.acceptor", locale = &lang_code).to_string()))
+    }
+
+    let result = match repos.team_battles.join(lobby.id, query.from.id, data.team).await {
+        Ok(lobby) if lobby.started => {
+            metrics::CMD_TEAMBATTLE.finished();
+            let text = fight_impl(repos, config, chat_id, &lobby, &lang_code).await?;
+            CallbackResult::EditMessage(text, None)
+        }
+        Ok(lobby) => {
+            let initiator_name = lobby.team(Team::A)
+                .find(|m| m.uid == lobby.initiator)
+                .map(|m| m.name.escaped())
+                .unwrap_or_default();
+            CallbackResult::EditMessage(lobby_text(&lobby, &initiator_name, &lang_code), Some(lobby_keyboard(&lobby, &lang_code)))
+        }
+        Err(TeamJoinError::Other(e)) => Err(e)?,
+        Err(e) => CallbackResult::ShowError(t!(&format!("commands.teambattle.errors.{e}"), locale = &lang_code).to_string())
+    };
+    Ok(result)
+}
+
+/// One team takes the top role and another one the bottom role; every member rolls the damage of the role.
+/// The team with less swelling in total wins.
+async fn fight_impl(repos: &repo::Repositories, config: &AppConfig, chat_id: ChatIdKind, lobby: &TeamLobby, lang_code: &LanguageCode) -> anyhow::Result<String> {
+    let damage_multiplier = repos.events.get_modifiers().await
+        .inspect_err(|e| log::error!("couldn't get the modifiers of the active events: {e}"))
+        .unwrap_or_default()
+        .battle_damage;
+
+    let profile = config.damage.profile(&chat_id);
+    let (top_team, damages) = {
+        let mut rng = rand::thread_rng();
+        let top_team = if rng.gen_bool(0.5) { Team::A } else { Team::B };
+        let damages = lobby.members.iter()
+            .map(|m| {
+                let damage = if m.team == top_team { profile.top.roll(&mut rng) } else { profile.bottom.roll(&mut rng) };
+                (m.uid, scale_damage(damage, damage_multiplier))
+            })
+            .collect::<Vec<(UserId, i32)>>();
+        (top_team, damages)
+    };
+    let levels = repos.hemoroids.penetrate_teams(&ChatIdPartiality::from(chat_id.clone()), &damages).await?;
+
+    let total = |team: Team| lobby.members.iter()
+        .zip(&damages)
+        .filter(|(m, _)| m.team == team)
+        .map(|(_, (_, damage))| damage)
+        .sum::<i32>();
+    let bottom_team = if top_team == Team::A { Team::B } else { Team::A };
+    let (winner_team, loser_team) = if total(top_team) < total(bottom_team) {
+        (top_team, bottom_team)
+    } else {
+        (bottom_team, top_team)
+    };
+    let members_of = |team: Team| lobby.team(team)
+        .map(|m| m.uid)
+        .collect::<Vec<UserId>>();
+    repos.pvp_stats.send_team_battle_result(&chat_id, &members_of(winner_team), &members_of(loser_team)).await
+        .inspect_err(|e| log::error!("couldn't send the team battle statistics of {}: {e}", lobby.id))
+        .ok();
+
+    let mut parts = vec![t!("commands.teambattle.results.title", locale = lang_code, size = lobby.team_size).to_string()];
+    for team in [top_team, bottom_team] {
+        let role = if team == top_team { "top" } else { "bottom" };
+        let lines = lobby.members.iter()
+            .zip(damages.iter().zip(&levels))
+            .filter(|(m, _)| m.team == team)
+            .map(|(m, ((_, damage), level))| {
+                let key = if *damage > 0 { "commands.teambattle.results.swelled" } else { "commands.teambattle.results.improved" };
+                t!(key, locale = lang_code, name = m.name.escaped(), damage = Tenths::from(damage.abs()).format(lang_code),
+                    level = Tenths::from(*level).format(lang_code)).to_string()
+            })
+            .collect::<Vec<String>>();
+        parts.push(format!("{}\n{}",
+            t!(&format!("commands.teambattle.results.team.{role}"), locale = lang_code, team = team_name(team)),
+            lines.join("\n")));
+    }
+    parts.push(t!("commands.teambattle.results.winner", locale = lang_code, team = team_name(winner_team),
+        total = Tenths::from(total(winner_team)).format(lang_code),
+        other = Tenths::from(total(loser_team)).format(lang_code)).to_string());
+
+    let event_banner = utils::event_banner(&repos.events, lang_code).await;
+    Ok(format!("{}{event_banner}", parts.join("\n\n")))
+}
+
+#[derive(Display)]
+#[display("{battle_id}:{team}")]
+pub(crate) struct TeamBattleCallbackData {
+    battle_id: i32,
+    team: Team,
+}
+
+impl CallbackDataWithPrefix for TeamBattleCallbackData {
+    fn prefix() -> &'static str {
+        "team"
+    }
+}
+
+impl TryFrom<String> for TeamBattleCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let battle_id = callbacks::parse_part(&mut parts, &err, "battle_id")?;
+        let team = callbacks::parse_part(&mut parts, &err, "team")?;
+        Ok(Self { battle_id, team })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_size_and_bet;
+
+    #[test]
+    fn test_parse_size_and_bet() {
+        assert_eq!(parse_size_and_bet("", 1), Some((2, 1)));
+        assert_eq!(parse_size_and_bet("3", 1), Some((3, 1)));
+        assert_eq!(parse_size_and_bet(" 3  10 ", 1), Some((3, 10)));
+        assert_eq!(parse_size_and_bet("1", 1), None);
+        assert_eq!(parse_size_and_bet("2 0", 1), None);
+        assert_eq!(parse_size_and_bet("2 5 7", 1), None);
+        assert_eq!(parse_size_and_bet("two", 1), None);
+    }
+}
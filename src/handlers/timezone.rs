@@ -0,0 +1,42 @@
+use anyhow::anyhow;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::Message;
+use teloxide::utils::html;
+use crate::handlers::{HandlerResult, reply_html};
+use crate::handlers::checks::is_invoked_by_admin;
+use crate::{metrics, reply_html, repo};
+use crate::domain::LanguageCode;
+use crate::repo::ChatIdPartiality;
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum TimezoneCommands {
+    #[command(description = "timezone")]
+    Timezone(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: TimezoneCommands, repos: repo::Repositories) -> HandlerResult {
+    metrics::CMD_TIMEZONE_COUNTER.inc();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let chat_id: ChatIdPartiality = msg.chat.id.into();
+    let TimezoneCommands::Timezone(timezone) = cmd;
+    let timezone = timezone.trim();
+
+    let answer = if timezone.is_empty() {
+        let current = repos.chats.get_timezone(&chat_id.kind()).await?;
+        t!("commands.timezone.current", locale = &lang_code, timezone = current).to_string()
+    } else if !is_invoked_by_admin(&bot, &msg, from.id).await? {
+        t!("commands.timezone.errors.not_admin", locale = &lang_code).to_string()
+    } else {
+        match repos.chats.set_timezone(&chat_id, timezone).await? {
+            Some(timezone) => t!("commands.timezone.success", locale = &lang_code, timezone = timezone).to_string(),
+            None => t!("commands.timezone.errors.unknown", locale = &lang_code, timezone = html::escape(timezone)).to_string(),
+        }
+    };
+    reply_html!(bot, msg, answer);
+    Ok(())
+}
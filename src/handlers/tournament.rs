@@ -0,0 +1,348 @@
+use anyhow::anyhow;
+use derive_more::Display;
+use rust_i18n::t;
+use teloxide::Bot;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::{CallbackQuery, Message, UserId};
+use teloxide::requests::Requester;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, ReplyMarkup};
+use callbacks::InvalidCallbackData;
+use crate::{metrics, reply_html, repo};
+use crate::config::AppConfig;
+use crate::domain::{LanguageCode, Tenths};
+use crate::handlers::{CallbackResult, HandlerResult, reply_html};
+use crate::handlers::buttfight::{buttfight_impl_attack, BattleParams};
+use crate::handlers::checks::is_invoked_by_admin;
+use crate::handlers::utils::callbacks;
+use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackDataBuilder};
+use crate::repo::{ChatIdKind, ChatIdPartiality, CreateTournamentError, NewTournament, Tournament, TournamentMatch, TournamentPlayer, TournamentStatus};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum TournamentCommands {
+    #[command(description = "tournament")]
+    Tournament(String),
+}
+
+pub async fn cmd_handler(bot: Bot, msg: Message, cmd: TournamentCommands,
+                         repos: repo::Repositories, config: AppConfig) -> HandlerResult {
+    metrics::CMD_TOURNAMENT.invoked();
+
+    let from = msg.from.as_ref().ok_or(anyhow!("unexpected absence of a FROM field"))?;
+    let lang_code = LanguageCode::from_user(from);
+    let TournamentCommands::Tournament(bet) = cmd;
+    let bet = match bet.trim() {
+        "" => Some(config.pvp_default_bet),
+        bet => bet.parse().ok().filter(|bet| *bet > 0),
+    };
+
+    let error_key = if !is_invoked_by_admin(&bot, &msg, from.id).await? {
+        "commands.tournament.errors.not_admin"
+    } else if let Some(bet) = bet {
+        let params = NewTournament {
+            lang_code: lang_code.clone(),
+            bet,
+            prize: config.tournament.prize,
+            registration_minutes: config.tournament.registration_minutes,
+        };
+        match repos.tournaments.create(&msg.chat.id.into(), msg.chat.id, &params).await {
+            Ok(tournament) => {
+                metrics::CMD_TOURNAMENT.finished();
+                let mut answer = reply_html(bot, &msg, registration_text(&tournament, &[]));
+                answer.reply_markup = Some(ReplyMarkup::InlineKeyboard(join_keyboard(&tournament)));
+                let sent = answer.await?;
+                repos.tournaments.set_message(tournament.id, sent.id).await?;
+                return Ok(())
+            }
+            Err(CreateTournamentError::AlreadyExists) => "commands.tournament.errors.already_exists",
+            Err(CreateTournamentError::Other(e)) => Err(e)?,
+        }
+    } else {
+        "commands.tournament.errors.invalid_bet"
+    };
+    reply_html!(bot, msg, t!(error_key, locale = &lang_code));
+    Ok(())
+}
+
+fn registration_text(tournament: &Tournament, players: &[TournamentPlayer]) -> String {
+    let lang_code = &tournament.lang_code;
+    let players = if players.is_empty() {
+        t!("commands.tournament.no_players", locale = lang_code).to_string()
+    } else {
+        players.iter()
+            .map(|p| format!("• {}", p.name.escaped()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    t!("commands.tournament.registration", locale = lang_code,
+        bet = tournament.bet, prize = Tenths::from(tournament.prize).format(lang_code),
+        ends_at = tournament.registration_ends_at.format("%H:%M"), players = players).to_string()
+}
+
+fn join_keyboard(tournament: &Tournament) -> InlineKeyboardMarkup {
+    let btn_label = t!("commands.tournament.button", locale = &tournament.lang_code);
+    let data = TournamentCallbackData { tournament_id: tournament.id };
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(btn_label, data.to_data_string())
+    ]])
+}
+
+#[inline]
+pub fn callback_filter(query: CallbackQuery) -> bool {
+    TournamentCallbackData::check_prefix(query)
+}
+
+pub async fn callback_handler(bot: Bot, query: CallbackQuery, repos: repo::Repositories) -> HandlerResult {
+    let data = TournamentCallbackData::parse(&query)?;
+    let result = join_impl(&repos, &query, data).await?;
+    result.apply(bot, query).await?;
+    Ok(())
+}
+
+async fn join_impl(repos: &repo::Repositories, query: &CallbackQuery, data: TournamentCallbackData) -> anyhow::Result<CallbackResult> {
+    let lang_code = LanguageCode::from_user(&query.from);
+    let Some(tournament) = repos.tournaments.get(data.tournament_id).await? else {
+        return Ok(CallbackResult::ShowError(t!("commands.tournament.errors.closed", locale = &lang_code).to_string()))
+    };
+    let chat_id = ChatIdKind::ID(tournament.chat_id);
+    if !repos.hemoroids.check_hemoroid(&chat_id, query.from.id, Tenths::from_cm(tournament.bet.into())).await? {
+        return Ok(CallbackResult::ShowError(t!("commands.tournament.errors.not_enough", locale = &lang_code,
+            bet = tournament.bet).to_string()))
+    }
+
+    let result = match repos.tournaments.join(tournament.id, query.from.id).await {
+        Ok(()) => {
+            let players = repos.tournaments.get_players(tournament.id).await?;
+            CallbackResult::EditMessage(registration_text(&tournament, &players), Some(join_keyboard(&tournament)))
+        }
+        Err(repo::JoinTournamentError::Other(e)) => Err(e)?,
+        Err(e) => CallbackResult::ShowError(t!(&format!("commands.tournament.errors.{e}"), locale = &lang_code).to_string())
+    };
+    Ok(result)
+}
+
+#[derive(Display)]
+#[display("{tournament_id}")]
+pub(crate) struct TournamentCallbackData {
+    tournament_id: i32,
+}
+
+impl CallbackDataWithPrefix for TournamentCallbackData {
+    fn prefix() -> &'static str {
+        "trn"
+    }
+}
+
+impl TryFrom<String> for TournamentCallbackData {
+    type Error = InvalidCallbackData;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let err = InvalidCallbackDataBuilder(&data);
+        let mut parts = data.split(':');
+        let tournament_id = callbacks::parse_part(&mut parts, &err, "tournament_id")?;
+        Ok(Self { tournament_id })
+    }
+}
+
+/// Closes the registrations and plays one round of every running tournament each minute, so the chat can follow the bracket.
+pub async fn run_tournaments(bot: Bot, repos: repo::Repositories, config: AppConfig) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let tournaments = match repos.tournaments.get_due().await {
+            Ok(tournaments) => tournaments,
+            Err(e) => {
+                log::error!("couldn't get the due tournaments: {e}");
+                continue
+            }
+        };
+        for tournament in tournaments {
+            let id = tournament.id;
+            if let Err(e) = advance_tournament(&bot, &repos, &config, tournament).await {
+                log::error!("couldn't advance the tournament {id}: {e}");
+            }
+        }
+    }
+}
+
+async fn advance_tournament(bot: &Bot, repos: &repo::Repositories, config: &AppConfig, tournament: Tournament) -> anyhow::Result<()> {
+    let lang_code = &tournament.lang_code;
+    let players = repos.tournaments.get_players(tournament.id).await?;
+
+    if tournament.status == TournamentStatus::Registration {
+        if players.len() < 2 {
+            repos.tournaments.cancel(tournament.id).await?;
+            let text = t!("commands.tournament.cancelled", locale = lang_code).to_string();
+            edit_tournament_message(bot, &tournament, text).await;
+        } else {
+            let uids = players.iter()
+                .map(|p| p.uid)
+                .collect::<Vec<UserId>>();
+            let matches = seed_bracket(&uids);
+            repos.tournaments.start_round(tournament.id, 1, &matches).await?;
+            edit_tournament_message(bot, &tournament, bracket_text(&tournament, &players, &matches)).await;
+        }
+        return Ok(())
+    }
+
+    // the matches already played before a restart are skipped
+    let current_round = repos.tournaments.get_matches(tournament.id).await?
+        .into_iter()
+        .filter(|m| m.round == tournament.round);
+    let mut winners = Vec::new();
+    for m in current_round {
+        let winner = match (m.winner, m.player2) {
+            (Some(winner), _) => winner,
+            (None, None) => m.player1,
+            (None, Some(player2)) => {
+                let acceptor = players.iter()
+                    .find(|p| p.uid == player2)
+                    .ok_or(anyhow!("the player {player2} of the tournament {} is not registered", tournament.id))?;
+                let params = BattleParams::new(repos.clone(), config, ChatIdPartiality::from(tournament.chat_id), lang_code.clone());
+                buttfight_impl_attack(params, m.player1, acceptor.into(), tournament.bet, None).await?.winner
+            }
+        };
+        repos.tournaments.set_winner(tournament.id, m.round, m.position, winner).await?;
+        winners.push(winner);
+    }
+
+    let text = match winners.as_slice() {
+        [] => Err(anyhow!("no matches in the round {} of the tournament {}", tournament.round, tournament.id))?,
+        [champion] => {
+            let level = repos.tournaments.finish(tournament.id, *champion).await?;
+            let matches = repos.tournaments.get_matches(tournament.id).await?;
+            let champion = player_name(&players, *champion);
+            let mut text = format!("{}\n\n{}", bracket_text(&tournament, &players, &matches),
+                t!("commands.tournament.champion", locale = lang_code, name = champion,
+                    prize = Tenths::from(tournament.prize).format(lang_code)));
+            if let Some(level) = level {
+                text.push(' ');
+                text.push_str(&t!("commands.tournament.champion_level", locale = lang_code,
+                    level = Tenths::from(level).format(lang_code)));
+            }
+            text
+        }
+        winners => {
+            let next_round = pair_winners(tournament.round + 1, winners);
+            repos.tournaments.start_round(tournament.id, tournament.round + 1, &next_round).await?;
+            let matches = repos.tournaments.get_matches(tournament.id).await?;
+            bracket_text(&tournament, &players, &matches)
+        }
+    };
+    edit_tournament_message(bot, &tournament, text).await;
+    Ok(())
+}
+
+async fn edit_tournament_message(bot: &Bot, tournament: &Tournament, text: String) {
+    let Some(message_id) = tournament.message_id else {
+        return
+    };
+    // the keyboard is removed since the registration is closed
+    let mut request = bot.edit_message_text(tournament.chat_id, message_id, text);
+    request.parse_mode.replace(ParseMode::Html);
+    if let Err(e) = request.await {
+        log::warn!("couldn't edit the message of the tournament {}: {e}", tournament.id);
+    }
+}
+
+fn bracket_text(tournament: &Tournament, players: &[TournamentPlayer], matches: &[TournamentMatch]) -> String {
+    let lang_code = &tournament.lang_code;
+    let mut lines = vec![t!("commands.tournament.bracket.title", locale = lang_code, bet = tournament.bet,
+        prize = Tenths::from(tournament.prize).format(lang_code)).to_string()];
+    let mut round = 0;
+    for m in matches {
+        if m.round != round {
+            round = m.round;
+            lines.push(String::new());
+            lines.push(t!("commands.tournament.bracket.round", locale = lang_code, round = round).to_string());
+        }
+        let player1 = player_name(players, m.player1);
+        let line = match (m.player2, m.winner) {
+            (None, _) => t!("commands.tournament.bracket.bye", locale = lang_code, player = player1),
+            (Some(player2), None) => t!("commands.tournament.bracket.match", locale = lang_code,
+                player1 = player1, player2 = player_name(players, player2)),
+            (Some(player2), Some(winner)) => t!("commands.tournament.bracket.match_finished", locale = lang_code,
+                player1 = player1, player2 = player_name(players, player2), winner = player_name(players, winner)),
+        };
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+fn player_name(players: &[TournamentPlayer], uid: UserId) -> String {
+    players.iter()
+        .find(|p| p.uid == uid)
+        .map(|p| p.name.escaped())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+/// Pairs the players, ordered by their seeds, so that the strongest ones meet each other as late as possible.
+/// The top seeds advance without a battle if the number of players is not a power of two.
+fn seed_bracket(players: &[UserId]) -> Vec<TournamentMatch> {
+    let size = players.len().next_power_of_two();
+    let mut seeds = vec![0];
+    while seeds.len() < size {
+        let n = seeds.len() * 2;
+        seeds = seeds.into_iter()
+            .flat_map(|s| [s, n - 1 - s])
+            .collect();
+    }
+    seeds.chunks(2)
+        .enumerate()
+        .map(|(position, pair)| {
+            let player1 = players[pair[0]];
+            let player2 = pair.get(1).and_then(|s| players.get(*s)).copied();
+            new_match(1, position, player1, player2)
+        })
+        .collect()
+}
+
+fn pair_winners(round: i16, winners: &[UserId]) -> Vec<TournamentMatch> {
+    winners.chunks(2)
+        .enumerate()
+        .map(|(position, pair)| new_match(round, position, pair[0], pair.get(1).copied()))
+        .collect()
+}
+
+fn new_match(round: i16, position: usize, player1: UserId, player2: Option<UserId>) -> TournamentMatch {
+    TournamentMatch {
+        round,
+        position: position as i16,
+        player1,
+        player2,
+        winner: player2.is_none().then_some(player1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use teloxide::types::UserId;
+    use super::{pair_winners, seed_bracket};
+
+    #[test]
+    fn test_seed_bracket() {
+        let players = (1..=6).map(UserId).collect::<Vec<UserId>>();
+        let pairs = seed_bracket(&players).into_iter()
+            .map(|m| (m.player1.0, m.player2.map(|p| p.0), m.winner.map(|p| p.0)))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![
+            (1, None, Some(1)),
+            (4, Some(5), None),
+            (2, None, Some(2)),
+            (3, Some(6), None),
+        ]);
+
+        let matches = seed_bracket(&[UserId(1), UserId(2)]);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player2, Some(UserId(2)));
+    }
+
+    #[test]
+    fn test_pair_winners() {
+        let matches = pair_winners(2, &[UserId(1), UserId(4), UserId(2)]);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].round, matches[0].position), (2, 0));
+        assert_eq!(matches[0].player2, Some(UserId(4)));
+        assert_eq!(matches[1].winner, Some(UserId(2)));
+    }
+}
@@ -11,13 +11,18 @@ use rand::rngs::OsRng;
 use rust_i18n::t;
 use teloxide::types::UserId;
 use crate::{config, repo};
+use crate::domain::{LanguageCode, Tenths};
 use crate::repo::ChatIdKind;
 
+pub const ITEM_PERK_PREFIX: &str = "item:";
+pub const EVENT_PERK_NAME: &str = "event";
+
 #[derive(Clone)]
 pub struct Incrementor {
     config: Config,
     perks: Vec<Arc<dyn Perk>>,
-    dicks: repo::Dicks,
+    hemoroids: repo::Hemoroids,
+    events: repo::Events,
 }
 
 #[derive(Clone)]
@@ -33,6 +38,17 @@ pub trait Perk: Send + Sync + Downcast {
     fn name(&self) -> &str;
     async fn apply(&self, dick_id: &DickId, change_intent: ChangeIntent) -> AdditionalChange;
 
+    /// Perks made up of several sources, like items, may report the contribution of each of them separately.
+    async fn apply_by_sources(&self, dick_id: &DickId, change_intent: ChangeIntent) -> Vec<(String, AdditionalChange)> {
+        vec![(self.name().to_owned(), self.apply(dick_id, change_intent).await)]
+    }
+
+    /// Perks must not change anything while the increment is calculated, since it may never be applied.
+    /// Their side effects are carried out by the repository along with the change instead.
+    fn settlement(&self, _sources: &[(String, AdditionalChange)]) -> Option<repo::PerkSettlement> {
+        None
+    }
+
     fn enabled(&self) -> bool {
         let env_key = format!("DISABLE_{}", self.name().to_uppercase().replace('-', "_"));
         !config::get_env_value_or_default(&env_key, false)
@@ -63,6 +79,7 @@ pub struct Increment<T: PrimInt + std::fmt::Display> {
     pub base: T,
     pub by_perks: HashMap<String, i32>,
     pub total: T,
+    pub settlements: Vec<repo::PerkSettlement>,
 }
 
 pub type SignedIncrement = Increment<i32>;
@@ -80,13 +97,30 @@ impl Config {
             .max()
             .unwrap_or(0)
     }
+
+    /// The biggest swelling a treatment may cause (the range is measured in improvements).
+    pub fn swell_max(&self) -> Tenths {
+        Tenths::from(i32::from(self.growth_range_min().min(0)).abs())
+    }
+
+    pub fn shrink_max(&self) -> Tenths {
+        Tenths::from(i32::from(self.growth_range_max().max(0)))
+    }
+
+    pub fn shrink_percentage(&self) -> f32 {
+        if self.growth_range_min() > 0 {
+            100.0
+        } else {
+            (self.grow_shrink_ratio * 100.0).clamp(0.0, 100.0).round()
+        }
+    }
 }
 
 impl Incrementor {
-    pub fn from_env(dicks: &repo::Dicks, perks: Vec<Box<dyn Perk>>) -> Self {
-        let growth_range_min = config::get_env_value_or_default("GROWTH_MIN", -5);
-        let growth_range_max = config::get_env_value_or_default("GROWTH_MAX", 10);
-        let dod_max_bonus = config::get_env_value_or_default("GROWTH_DOD_BONUS_MAX", 5);
+    pub fn from_env(hemoroids: &repo::Hemoroids, events: &repo::Events, perks: Vec<Box<dyn Perk>>) -> anyhow::Result<Self> {
+        let growth_range_min = config::get_length_env_value_or_default("GROWTH_MIN", Tenths::from_cm(-5))?;
+        let growth_range_max = config::get_length_env_value_or_default("GROWTH_MAX", Tenths::from_cm(10))?;
+        let dod_max_bonus = config::get_length_env_value_or_default("GROWTH_DOD_BONUS_MAX", Tenths::from_cm(5))?;
         
         let perks = perks
             .into_iter()
@@ -94,7 +128,7 @@ impl Incrementor {
             .map(Arc::from)
             .collect();
 
-        Self {
+        Ok(Self {
             config: Config {
                 growth_range: growth_range_min..=growth_range_max,
                 grow_shrink_ratio: config::get_env_value_or_default("GROW_SHRINK_RATIO", 0.5),
@@ -102,8 +136,9 @@ impl Incrementor {
                 dod_bonus_range: 1..=dod_max_bonus,
             },
             perks,
-            dicks: dicks.clone(),
-        }
+            hemoroids: hemoroids.clone(),
+            events: events.clone(),
+        })
     }
 
     pub fn get_config(&self) -> Config {
@@ -124,6 +159,7 @@ impl Incrementor {
             .collect();
     }
 
+    /// Positive values are improvements, i.e. the protrusion level must be decreased by them.
     pub async fn growth_increment(&self, user_id: UserId, chat_id: ChatIdKind, days_since_registration: u32) -> SignedIncrement {
         let dick_id = DickId(user_id, chat_id);
         let grow_shrink_ratio = if days_since_registration > self.config.newcomers_grace_days {
@@ -132,25 +168,34 @@ impl Incrementor {
             1.0
         };
         let base_incr = get_base_increment(self.config.growth_range.clone(), grow_shrink_ratio);
-        self.add_additional_incr(dick_id, BaseIncrement(base_incr)).await
+        let multiplier = self.event_modifiers().await.shrink;
+        self.add_additional_incr(dick_id, BaseIncrement(base_incr), multiplier).await
     }
 
     pub async fn dod_increment(&self, user_id: UserId, chat_id: ChatIdKind) -> UnsignedIncrement {
         let dick_id = DickId(user_id, chat_id);
         let base_incr = OsRng.gen_range(self.config.dod_bonus_range.clone());
-        self.add_additional_incr(dick_id, BaseIncrement(base_incr)).await
+        let multiplier = self.event_modifiers().await.hod_bonus;
+        self.add_additional_incr(dick_id, BaseIncrement(base_incr), multiplier).await
     }
-    
-    async fn add_additional_incr<T, R>(&self, dick: DickId, base_increment: BaseIncrement<T>) -> Increment<R>
+
+    async fn event_modifiers(&self) -> repo::EventModifiers {
+        self.events.get_modifiers().await
+            .inspect_err(|e| log::error!("couldn't get the modifiers of the active events: {e}"))
+            .unwrap_or_default()
+    }
+
+    /// The multiplier of the active events is applied to positive base increments only, i.e. to improvements.
+    async fn add_additional_incr<T, R>(&self, dick: DickId, base_increment: BaseIncrement<T>, event_multiplier: f32) -> Increment<R>
     where
         T: PrimInt + std::fmt::Display + Into<i16>,
         R: PrimInt + std::fmt::Display + From<T> + TryFrom<i32>,
         <R as TryFrom<i32>>::Error: std::fmt::Display
     {
-        let current_length = match self.dicks.fetch_length(dick.0, &dick.1).await {
-            Ok(length) => length,
+        let current_length = match self.hemoroids.fetch_protrusion_level(dick.0, &dick.1).await {
+            Ok(level) => level,
             Err(e) => {
-                log::error!("couldn't fetch the length of a dick: {e}");
+                log::error!("couldn't fetch the protrusion level of a hemorrhoid: {e}");
                 return base_increment.only()
             }
         };
@@ -161,12 +206,23 @@ impl Incrementor {
 
         let mut additional_change = 0;
         let mut by_perks = HashMap::new();
+        let mut settlements = Vec::new();
         for perk in self.perks.iter() {
-            let AdditionalChange(ac) = perk.apply(&dick, change_intent).await;
-            if !ac.is_zero() {
-                by_perks.insert(perk.name().to_owned(), ac);
+            let sources = perk.apply_by_sources(&dick, change_intent).await;
+            settlements.extend(perk.settlement(&sources));
+            for (source, AdditionalChange(ac)) in sources {
+                if !ac.is_zero() {
+                    by_perks.insert(source, ac);
+                }
+                additional_change += ac
+            }
+        }
+        if change_intent.base_increment > 0 {
+            let event_change = (change_intent.base_increment as f32 * (event_multiplier - 1.0)).round() as i32;
+            if !event_change.is_zero() {
+                by_perks.insert(EVENT_PERK_NAME.to_owned(), event_change);
+                additional_change += event_change
             }
-            additional_change += ac
         }
         
         let base = <R as From<T>>::from(base_increment.0);
@@ -181,9 +237,10 @@ impl Incrementor {
         if base == total && !additional_change.is_zero() {
             log::info!("The following perks affected the calculation: {by_perks:?}");
             by_perks.clear();
+            settlements.clear();
         }
         
-        Increment { base, by_perks, total }
+        Increment { base, by_perks, total, settlements }
     }
 }
 
@@ -199,7 +256,8 @@ impl <T: PrimInt + Into<i16>> BaseIncrement<T> {
         Increment {
             base: value,
             by_perks: HashMap::default(),
-            total: value
+            total: value,
+            settlements: Vec::default(),
         }
     }
 
@@ -209,14 +267,13 @@ impl <T: PrimInt + Into<i16>> BaseIncrement<T> {
 }
 
 impl <T: PrimInt + std::fmt::Display + Into<i32>> Increment<T> {    
-    pub fn perks_part_of_answer(&self, lang_code: &str) -> String {
+    pub fn perks_part_of_answer(&self, lang_code: &LanguageCode) -> String {
         if self.base != self.total {
             let top_line = t!("titles.perks.top_line", locale = lang_code);
             let perks = self.by_perks.iter()
                 .map(|(perk, value)| {
-                    let t_key = format!("titles.perks.{perk}");
-                    let name = t!(&t_key, locale = lang_code);
-                    format!("— {name} ({value:+})")
+                    let name = perk_title(perk, lang_code);
+                    format!("— {name} ({})", Tenths::from(*value).format_signed(lang_code))
                 })
                 .collect::<Vec<String>>()
                 .join("\n");
@@ -227,6 +284,24 @@ impl <T: PrimInt + std::fmt::Display + Into<i32>> Increment<T> {
     }
 }
 
+/// Items are reported by the inventory perk under the `item:<code>` names.
+pub fn perk_title(perk: &str, lang_code: &LanguageCode) -> String {
+    match perk.strip_prefix(ITEM_PERK_PREFIX) {
+        Some(item) => t!(&format!("items.{item}.name"), locale = lang_code),
+        None => t!(&format!("titles.perks.{perk}"), locale = lang_code),
+    }.to_string()
+}
+
+impl <T: PrimInt + std::fmt::Display + Into<i32>> From<&Increment<T>> for repo::LevelChange {
+    fn from(value: &Increment<T>) -> Self {
+        Self {
+            base: value.base.into(),
+            by_perks: value.by_perks.clone(),
+            settlements: value.settlements.clone(),
+        }
+    }
+}
+
 fn get_base_increment<T>(range: RangeInclusive<T>, sign_ratio: f32) -> T
 where
     T: PrimInt + PartialOrd + SampleUniform + From<i8>
@@ -255,7 +330,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::get_base_increment;
+    use super::{get_base_increment, Config};
 
     #[test]
     fn test_gen_increment() {
@@ -277,6 +352,23 @@ mod test {
         assert!(increments.iter().all(|n| n <= &10));
         assert!(increments.iter().all(|n| n >= &5));
     }
+
+    #[test]
+    fn test_config_for_help() {
+        let mut cfg = Config {
+            growth_range: -5..=10,
+            grow_shrink_ratio: 0.7,
+            newcomers_grace_days: 7,
+            dod_bonus_range: 1..=5,
+        };
+        assert_eq!(cfg.swell_max().to_string(), "0.5");
+        assert_eq!(cfg.shrink_max().to_string(), "1");
+        assert_eq!(cfg.shrink_percentage(), 70.0);
+
+        cfg.growth_range = 1..=10;
+        assert_eq!(cfg.swell_max().value(), 0);
+        assert_eq!(cfg.shrink_percentage(), 100.0);
+    }
 }
 
 #[cfg(test)]
@@ -285,15 +377,16 @@ mod test_incrementor {
 
     use async_trait::async_trait;
     use futures::future::join_all;
+    use sqlx::{Pool, Postgres};
 
-    use crate::handlers::utils::{AdditionalChange, ChangeIntent, Config, DickId, Incrementor, Perk};
+    use crate::handlers::utils::{AdditionalChange, ChangeIntent, Config, DickId, Incrementor, Perk, EVENT_PERK_NAME};
     use crate::repo;
     use crate::repo::test::{CHAT_ID_KIND, start_postgres, USER_ID};
 
     #[tokio::test]
     async fn test_incrementor() {
         let (_container, db) = start_postgres().await;
-        let dicks = repo::Dicks::new(db.clone(), Default::default());
+        let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
         let incr = Incrementor {
             config: Config {
                 growth_range: -1..=1,
@@ -301,7 +394,8 @@ mod test_incrementor {
                 newcomers_grace_days: 1,
                 dod_bonus_range: 1..=2,
             },
-            dicks,
+            hemoroids,
+            events: repo::Events::new(db.clone()),
             perks: Vec::default()
         };
 
@@ -309,6 +403,7 @@ mod test_incrementor {
         test_dod_increment_base(&incr).await;
         test_with_perks(&incr).await;
         test_perk_with_overflow(&incr).await;
+        test_event_multiplier(&incr, &db).await;
     }
 
     async fn test_growth_increment_base(incr: &Incrementor) {
@@ -404,4 +499,26 @@ mod test_incrementor {
         assert_eq!(increment.base, increment.total);
         assert!(increment.by_perks.is_empty());
     }
+
+    async fn test_event_multiplier(incr: &Incrementor, db: &Pool<Postgres>) {
+        sqlx::query!("INSERT INTO Events (code, starts_at, ends_at, shrink_multiplier, hod_bonus_multiplier)
+                VALUES ('test', current_timestamp - interval '1 hour', current_timestamp + interval '1 hour', 3.0, 2.0)")
+            .execute(db)
+            .await.expect("couldn't create an event");
+
+        let increment = incr.dod_increment(USER_ID, CHAT_ID_KIND).await;
+        assert_eq!(increment.total, increment.base * 2);
+        assert_eq!(increment.by_perks[EVENT_PERK_NAME], i32::from(increment.base));
+
+        // swelling isn't affected by the multiplier
+        let increments = (0..100)
+            .map(|_| incr.growth_increment(USER_ID, CHAT_ID_KIND, 1));
+        for increment in join_all(increments).await {
+            if increment.base > 0 {
+                assert_eq!(increment.total, increment.base * 3);
+            } else {
+                assert_eq!(increment.total, increment.base);
+            }
+        }
+    }
 }
@@ -1,17 +1,19 @@
 use std::sync::Arc;
+use async_trait::async_trait;
 use derive_more::Display;
 use flurry::HashSet;
-use crate::config::FeatureToggles;
+use sqlx::{Pool, Postgres};
+use crate::config::{CallbackLocksBackend, FeatureToggles};
 
 use crate::handlers::utils::callbacks::CallbackDataWithPrefix;
 
-// TODO: create a Redis based implementation
+#[async_trait]
 pub trait LockCallbackServiceImplTrait : Clone + Send + Sync {
     type Guard;
-    
-    fn try_lock<T>(&mut self, callback_data: &T) -> Option<Self::Guard> 
+
+    async fn try_lock<T>(&mut self, callback_data: &T) -> Option<Self::Guard>
     where Self::Guard: Guard,
-          T: CallbackDataWithPrefix;
+          T: CallbackDataWithPrefix + Sync;
 }
 
 pub trait Guard: Send + Sync {}
@@ -20,25 +22,35 @@ pub trait Guard: Send + Sync {}
 pub enum LockCallbackServiceFacade {
     NoOp,
     InMemory(InMemoryLockCallbackService),
+    Postgres(PostgresLockCallbackService),
 }
 
 impl LockCallbackServiceFacade {
-    pub fn from_config(features: FeatureToggles) -> Self {
-        if features.pvp.callback_locks {
-            log::info!("LockCallbackService: in-memory");
-            Self::InMemory(InMemoryLockCallbackService::default())
-        } else {
+    pub fn from_config(features: FeatureToggles, db_conn: &Pool<Postgres>) -> Self {
+        if !features.pvp.callback_locks {
             log::info!("LockCallbackService: none");
-            Self::NoOp
+            return Self::NoOp
+        }
+        match features.pvp.callback_locks_backend {
+            CallbackLocksBackend::Memory => {
+                log::info!("LockCallbackService: in-memory");
+                Self::InMemory(InMemoryLockCallbackService::default())
+            }
+            CallbackLocksBackend::Postgres => {
+                log::info!("LockCallbackService: Postgres lock table");
+                Self::Postgres(PostgresLockCallbackService::new(db_conn.clone()))
+            }
         }
     }
 
-    pub fn try_lock<T>(&mut self, callback_data: &T) -> Option<Box<dyn Guard>>
-    where T: CallbackDataWithPrefix,
+    pub async fn try_lock<T>(&mut self, callback_data: &T) -> Option<Box<dyn Guard>>
+    where T: CallbackDataWithPrefix + Sync,
     {
         match self {
             Self::NoOp => Some(Box::<NoOpGuard>::default()),
-            Self::InMemory(service) => service.try_lock(callback_data)
+            Self::InMemory(service) => service.try_lock(callback_data).await
+                .map(|guard| Box::new(guard) as Box<dyn Guard>),
+            Self::Postgres(service) => service.try_lock(callback_data).await
                 .map(|guard| Box::new(guard) as Box<dyn Guard>),
         }
     }
@@ -53,20 +65,21 @@ pub struct InMemoryLockCallbackService {
     inner_set: Arc<HashSet<String>>
 }
 
+#[async_trait]
 impl LockCallbackServiceImplTrait for InMemoryLockCallbackService {
     type Guard = InMemorySetGuard;
-    
-    fn try_lock<T>(&mut self, callback_data: &T) -> Option<Self::Guard>
+
+    async fn try_lock<T>(&mut self, callback_data: &T) -> Option<Self::Guard>
     where Self::Guard: Guard,
-          T: CallbackDataWithPrefix
+          T: CallbackDataWithPrefix + Sync
     {
         let key = callback_data.to_string();
-        if self.inner_set.contains(&key, &self.inner_set.guard()) {
+        // the check and the insertion must be atomic, otherwise two simultaneous clicks may both succeed
+        if self.inner_set.insert(key.clone(), &self.inner_set.guard()) {
+            Some(InMemorySetGuard::new(&self.inner_set, key))
+        } else {
             log::debug!("double attack on: {key}");
             None
-        } else {
-            self.inner_set.insert(key.clone(), &self.inner_set.guard());
-            Some(InMemorySetGuard::new(&self.inner_set, key))
         }
     }
 }
@@ -95,3 +108,178 @@ impl Drop for InMemorySetGuard {
 }
 
 impl Guard for InMemorySetGuard {}
+
+/// How long a lock of a dead replica prevents the challenge from being accepted.
+const POSTGRES_LOCK_TTL_SECONDS: f64 = 60.0;
+
+/// Shares the locks between several replicas of the bot. The locks are rows of a table, so no connection is held
+/// while a battle is fought; if the process dies, its locks expire after `POSTGRES_LOCK_TTL_SECONDS`.
+#[derive(Clone)]
+pub struct PostgresLockCallbackService {
+    pool: Pool<Postgres>
+}
+
+impl PostgresLockCallbackService {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LockCallbackServiceImplTrait for PostgresLockCallbackService {
+    type Guard = LockRowGuard;
+
+    /// Errors of the database are treated as a taken lock, so a battle is never fought twice.
+    async fn try_lock<T>(&mut self, callback_data: &T) -> Option<Self::Guard>
+    where Self::Guard: Guard,
+          T: CallbackDataWithPrefix + Sync
+    {
+        let key = callback_data.to_string();
+        let token: i64 = rand::random();
+        let locked = sqlx::query_scalar!("INSERT INTO Callback_Locks (key, token, expires_at) VALUES ($1, $2, current_timestamp + make_interval(secs => $3))
+                ON CONFLICT (key) DO UPDATE SET token = excluded.token, expires_at = excluded.expires_at
+                    WHERE Callback_Locks.expires_at <= current_timestamp
+                RETURNING token",
+                key, token, POSTGRES_LOCK_TTL_SECONDS)
+            .fetch_optional(&self.pool)
+            .await
+            .inspect_err(|e| log::error!("couldn't take a lock on {key}: {e}"))
+            .ok()?;
+        if locked.is_some() {
+            Some(LockRowGuard::new(self.pool.clone(), key, token))
+        } else {
+            log::debug!("double attack on: {key}");
+            None
+        }
+    }
+}
+
+#[derive(Display)]
+#[display("LockRowGuard({key})")]
+pub struct LockRowGuard {
+    pool: Pool<Postgres>,
+    key: String,
+    token: i64,
+}
+
+impl LockRowGuard {
+    fn new(pool: Pool<Postgres>, key: String, token: i64) -> Self {
+        let guard = Self { pool, key, token };
+        log::debug!("taking a lock guard: {guard}");
+        guard
+    }
+}
+
+impl Drop for LockRowGuard {
+    /// The lock is released in the background since it requires a query. If it fails, the lock just expires.
+    fn drop(&mut self) {
+        log::debug!("dropping the lock guard: {self}");
+        let pool = self.pool.clone();
+        let key = std::mem::take(&mut self.key);
+        let token = self.token;
+        tokio::spawn(async move {
+            let released = sqlx::query!("DELETE FROM Callback_Locks WHERE key = $1 AND token = $2",
+                    key, token)
+                .execute(&pool)
+                .await;
+            match released {
+                Ok(res) if res.rows_affected() > 0 => {},
+                Ok(_) => log::warn!("the lock on {key} had expired before it was released"),
+                Err(e) => log::error!("couldn't release the lock on {key}: {e}"),
+            }
+        });
+    }
+}
+
+impl Guard for LockRowGuard {}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use derive_more::Display;
+    use futures::future::join_all;
+    use crate::handlers::utils::callbacks::{CallbackDataWithPrefix, InvalidCallbackData, InvalidCallbackDataBuilder};
+    use crate::repo::test::start_postgres;
+    use super::{InMemoryLockCallbackService, LockCallbackServiceFacade, PostgresLockCallbackService};
+
+    #[derive(Display)]
+    struct TestCallbackData(u32);
+
+    impl CallbackDataWithPrefix for TestCallbackData {
+        fn prefix() -> &'static str {
+            "test"
+        }
+    }
+
+    impl TryFrom<String> for TestCallbackData {
+        type Error = InvalidCallbackData;
+
+        fn try_from(data: String) -> Result<Self, Self::Error> {
+            let err = InvalidCallbackDataBuilder(&data);
+            data.parse().map(Self).map_err(|e| err.parsing_err(e))
+        }
+    }
+
+    async fn count_concurrent_locks(services: Vec<LockCallbackServiceFacade>, data: &TestCallbackData) -> usize {
+        let attempts = services.into_iter()
+            .map(|mut service| async move { service.try_lock(data).await });
+        join_all(attempts).await
+            .into_iter()
+            .flatten()
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_concurrent_accepts() {
+        let service = LockCallbackServiceFacade::InMemory(InMemoryLockCallbackService::default());
+        let data = TestCallbackData(1);
+        let services = vec![service.clone(); 10];
+        assert_eq!(count_concurrent_locks(services, &data).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_concurrent_accepts() {
+        let (_container, db) = start_postgres().await;
+        let new_replica = || LockCallbackServiceFacade::Postgres(PostgresLockCallbackService::new(db.clone()));
+        let data = TestCallbackData(1);
+
+        let mut replica = new_replica();
+        let guard = replica.try_lock(&data).await
+            .expect("the lock must be taken");
+        let services = (0..5).map(|_| new_replica()).collect();
+        assert_eq!(count_concurrent_locks(services, &data).await, 0);
+        assert!(replica.try_lock(&TestCallbackData(2)).await.is_some());
+
+        drop(guard);
+        let mut relocked = None;
+        for _ in 0..50 {
+            relocked = new_replica().try_lock(&data).await;
+            if relocked.is_some() {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(relocked.is_some(), "the lock must be released after the guard is dropped");
+
+        let services = (0..5).map(|_| new_replica()).collect();
+        assert_eq!(count_concurrent_locks(services, &TestCallbackData(3)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_guards_hold_no_connections() {
+        let (_container, db) = start_postgres().await;
+        let mut service = LockCallbackServiceFacade::Postgres(PostgresLockCallbackService::new(db.clone()));
+        // more guards than connections in the pool
+        let mut guards = Vec::new();
+        for i in 0..db.options().get_max_connections() * 2 {
+            let guard = service.try_lock(&TestCallbackData(i)).await
+                .expect("the lock must be taken");
+            guards.push(guard);
+        }
+        let one = tokio::time::timeout(Duration::from_secs(5), sqlx::query_scalar!(r#"SELECT 1 AS "one!""#).fetch_one(&db))
+            .await.expect("the pool must not be starved by the guards")
+            .expect("couldn't query the database");
+        assert_eq!(one, 1);
+        assert!(service.try_lock(&TestCallbackData(0)).await.is_none());
+    }
+}
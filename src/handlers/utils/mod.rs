@@ -7,8 +7,10 @@ mod incrementor;
 pub use tghack::*;
 pub use incrementor::*;
 
+use rust_i18n::t;
 use teloxide::types::User;
-use crate::domain::Username;
+use crate::domain::{LanguageCode, Username};
+use crate::repo;
 
 pub fn get_full_name(user: &User) -> Username {
     let name = user.last_name.as_ref()
@@ -17,19 +19,36 @@ pub fn get_full_name(user: &User) -> Username {
     Username::new(name)
 }
 
+/// Appended to the replies affected by the modifiers of the active global events.
+pub async fn event_banner(events: &repo::Events, lang_code: &LanguageCode) -> String {
+    let lines = events.get_active(lang_code).await
+        .inspect_err(|e| log::error!("couldn't get the active events: {e}"))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|event| t!("events.banner", locale = lang_code, title = event.title,
+            ends_at = event.ends_at.format("%d.%m.%Y %H:%M UTC")).to_string())
+        .collect::<Vec<String>>();
+    if lines.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n<i>{}</i>", lines.join("\n"))
+    }
+}
+
 pub mod date {
     use std::borrow::Cow;
-    use chrono::{DateTime, Duration, Timelike, Utc};
+    use chrono::{DateTime, Duration, FixedOffset, Timelike, Utc};
     use rust_i18n::t;
 
-    pub fn get_time_till_next_day_string(lang_code: &str) -> Cow<str> {
+    /// Counts the time left till the local midnight of a chat with the given offset from UTC.
+    pub fn get_time_till_next_day_string(lang_code: &str, utc_offset: FixedOffset) -> Cow<str> {
         let now = if cfg!(test) {
             DateTime::parse_from_rfc3339("2023-10-21T22:10:57+00:00")
                 .expect("invalid datetime string")
                 .into()
         } else {
             Utc::now()
-        };
+        }.with_timezone(&utc_offset);
         Some(now + Duration::days(1))
             .and_then(|d| d.with_hour(0))
             .and_then(|d| d.with_minute(0))
@@ -43,16 +62,32 @@ pub mod date {
             })
             .unwrap_or(t!("titles.time_till_next_day.none", locale = lang_code))
     }
+
+    /// Whether the timestamp belongs to a previous day in the local time of a chat.
+    pub fn is_before_today(ts: DateTime<Utc>, utc_offset: FixedOffset) -> bool {
+        Utc::now().with_timezone(&utc_offset).date_naive() > ts.with_timezone(&utc_offset).date_naive()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::FixedOffset;
     use super::*;
 
     #[test]
     fn get_time_till_next_day_string() {
         let expected = "<b>1</b>h <b>49</b>m.";
-        let actual = date::get_time_till_next_day_string("en");
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let actual = date::get_time_till_next_day_string("en", utc);
+        let actual = &actual[actual.len()-expected.len()..];
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn get_time_till_next_day_string_in_tehran() {
+        let expected = "<b>22</b>h <b>19</b>m.";
+        let tehran = FixedOffset::east_opt(3 * 3600 + 30 * 60).unwrap();
+        let actual = date::get_time_till_next_day_string("en", tehran);
         let actual = &actual[actual.len()-expected.len()..];
         assert_eq!(expected, actual)
     }
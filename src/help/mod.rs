@@ -32,13 +32,24 @@ pub struct Context {
     pub bot_name: String,
     pub grow_min: String,
     pub grow_max: String,
+    pub shrink_max: String,
+    pub swell_max: String,
+    pub shrink_percentage: f32,
     pub other_bots: String,
     pub admin_channel_ru: String,
     pub admin_channel_en: String,
     pub admin_chat_ru: String,
     pub admin_chat_en: String,
     pub git_repo: String,
-    pub help_pussies_percentage: f64
+    pub help_pussies_percentage: f64,
+    pub top_damage_min: String,
+    pub top_damage_max: String,
+    pub top_lucky_percentage: String,
+    pub top_complications_percentage: String,
+    pub bottom_damage_min: String,
+    pub bottom_damage_max: String,
+    pub bottom_lucky_percentage: String,
+    pub bottom_complications_percentage: String,
 }
 
 pub fn render_help_messages(context: Context) -> Result<HelpContainer, tinytemplate::error::Error> {
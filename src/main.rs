@@ -18,8 +18,18 @@ use teloxide::update_listeners::webhooks::{axum_to_router, Options};
 use teloxide::update_listeners::UpdateListener;
 use crate::handlers::{checks, HelpCommands, LoanCommands, PrivacyCommands, PromoCommandState, StartCommands};
 use crate::handlers::{HemoroidCommands, HemoroidOfDayCommands, ImportCommands, PromoCommands};
-use crate::handlers::pvp::{BattleCommands, BattleCommandsNoArgs};
+use crate::handlers::buttfight::{BattleCommands, BattleCommandsNoArgs};
 use crate::handlers::stats::StatsCommands;
+use crate::handlers::history::HistoryCommands;
+use crate::handlers::battles::BattlesCommands;
+use crate::handlers::timezone::TimezoneCommands;
+use crate::handlers::rules::RulesCommands;
+use crate::handlers::achievements::AchievementsCommands;
+use crate::handlers::shop::ShopCommands;
+use crate::handlers::inventory::InventoryCommands;
+use crate::handlers::tournament::TournamentCommands;
+use crate::handlers::teambattle::TeamBattleCommands;
+use crate::handlers::duel::DuelCommands;
 use crate::handlers::utils::locks::LockCallbackServiceFacade;
 
 const ENV_WEBHOOK_URL: &str = "WEBHOOK_URL";
@@ -33,7 +43,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     pretty_env_logger::init();
 
-    let app_config = config::AppConfig::from_env();
+    let app_config = config::AppConfig::from_env()?;
     let database_config = config::DatabaseConfig::from_env()?;
     let db_conn = repo::establish_database_connection(&database_config).await?;
 
@@ -44,9 +54,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .branch(Update::filter_message().filter_command::<HemoroidCommands>().filter(checks::is_group_chat).endpoint(handlers::hemoroid_cmd_handler))
         .branch(Update::filter_message().filter_command::<HemoroidOfDayCommands>().filter(checks::is_group_chat).endpoint(handlers::hod_cmd_handler))
         .branch(Update::filter_message().filter_command::<BattleCommands>().filter(checks::is_group_chat).endpoint(handlers::buttfight::cmd_handler))
+        .branch(Update::filter_message().filter_command::<TeamBattleCommands>().filter(checks::is_group_chat).endpoint(handlers::teambattle::cmd_handler))
+        .branch(Update::filter_message().filter_command::<DuelCommands>().filter(checks::is_group_chat).endpoint(handlers::duel::cmd_handler))
         .branch(Update::filter_message().filter_command::<BattleCommandsNoArgs>().filter(checks::is_group_chat).endpoint(handlers::buttfight::cmd_handler_no_args))
         .branch(Update::filter_message().filter_command::<StatsCommands>().endpoint(handlers::stats::cmd_handler))
+        .branch(Update::filter_message().filter_command::<HistoryCommands>().filter(checks::is_group_chat).endpoint(handlers::history::cmd_handler))
+        .branch(Update::filter_message().filter_command::<BattlesCommands>().filter(checks::is_group_chat).endpoint(handlers::battles::cmd_handler))
+        .branch(Update::filter_message().filter_command::<AchievementsCommands>().filter(checks::is_group_chat).endpoint(handlers::achievements::cmd_handler))
+        .branch(Update::filter_message().filter_command::<ShopCommands>().filter(checks::is_group_chat).endpoint(handlers::shop::cmd_handler))
+        .branch(Update::filter_message().filter_command::<InventoryCommands>().filter(checks::is_group_chat).endpoint(handlers::inventory::cmd_handler))
+        .branch(Update::filter_message().filter_command::<TournamentCommands>().filter(checks::is_group_chat).endpoint(handlers::tournament::cmd_handler))
         .branch(Update::filter_message().filter_command::<LoanCommands>().filter(checks::is_group_chat).endpoint(handlers::loan::cmd_handler))
+        .branch(Update::filter_message().filter_command::<TimezoneCommands>().filter(checks::is_group_chat).endpoint(handlers::timezone::cmd_handler))
+        .branch(Update::filter_message().filter_command::<RulesCommands>().filter(checks::is_group_chat).endpoint(handlers::rules::cmd_handler))
         .branch(Update::filter_message().filter_command::<ImportCommands>().filter(checks::is_group_chat).endpoint(handlers::import_cmd_handler))
         .branch(Update::filter_message().filter_command::<PromoCommands>().filter(checks::is_not_group_chat).enter_dialogue::<Message, InMemStorage<PromoCommandState>, PromoCommandState>()
             .branch(dptree::case![PromoCommandState::Start].endpoint(handlers::promo_cmd_handler)))
@@ -61,7 +81,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .branch(Update::filter_chosen_inline_result().endpoint(handlers::inline_chosen_handler))
         .branch(Update::filter_callback_query().filter(handlers::page_callback_filter).endpoint(handlers::page_callback_handler))
         .branch(Update::filter_callback_query().filter(handlers::buttfight::callback_filter).endpoint(handlers::buttfight::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::teambattle::callback_filter).endpoint(handlers::teambattle::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::duel::callback_filter).endpoint(handlers::duel::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::buttfight::mercy_callback_filter).endpoint(handlers::buttfight::mercy_callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::buttfight::rematch_callback_filter).endpoint(handlers::buttfight::rematch_callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::buttfight::side_bet_callback_filter).endpoint(handlers::buttfight::side_bet_callback_handler))
         .branch(Update::filter_callback_query().filter(handlers::loan::callback_filter).endpoint(handlers::loan::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::history::callback_filter).endpoint(handlers::history::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::battles::callback_filter).endpoint(handlers::battles::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::shop::callback_filter).endpoint(handlers::shop::callback_handler))
+        .branch(Update::filter_callback_query().filter(handlers::tournament::callback_filter).endpoint(handlers::tournament::callback_handler))
         .branch(Update::filter_callback_query().endpoint(handlers::callback_handler));
 
     let bot = Bot::from_env();
@@ -80,11 +109,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let me = bot.get_me().await?;
     let repos = repo::Repositories::new(&db_conn, &app_config);
-    let perks = handlers::perks::all(&db_conn, &app_config);
-    let incrementor = handlers::utils::Incrementor::from_env(&repos.dicks, perks);
-    let help_context = config::build_context_for_help_messages(me, &incrementor, &handlers::ORIGINAL_BOT_USERNAMES)?;
+    let perks = handlers::perks::all(&db_conn, &app_config)?;
+    let incrementor = handlers::utils::Incrementor::from_env(&repos.hemoroids, &repos.events, perks)?;
+    let help_context = config::build_context_for_help_messages(me, &incrementor, &app_config.damage.default, &handlers::ORIGINAL_BOT_USERNAMES)?;
     let help_container = help::render_help_messages(help_context)?;
-    let battle_locker = LockCallbackServiceFacade::from_config(app_config.features);
+    let battle_locker = LockCallbackServiceFacade::from_config(app_config.features, &db_conn);
 
     let webhook_url: Option<Url> = match std::env::var(ENV_WEBHOOK_URL) {
         Ok(env_url) if !env_url.is_empty() => Some(env_url.parse()?),
@@ -95,6 +124,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     let metrics_router = metrics::init();
 
+    tokio::spawn(handlers::buttfight::expire_challenges(bot.clone(), repos.challenges.clone(), app_config.pvp_challenge_ttl_minutes));
+    tokio::spawn(handlers::tournament::run_tournaments(bot.clone(), repos.clone(), app_config.clone()));
+    tokio::spawn(handlers::duel::run_duel_timeouts(bot.clone(), repos.clone(), app_config.clone()));
+
     let ignore_unknown_updates = |_| Box::pin(async {});
     let deps = deps![
         repos,
@@ -70,6 +70,65 @@ pub static CMD_STATS: Lazy<BothModesCounters> = Lazy::new(|| {
         inline: Counter::new("command_stats (inline)", opts.const_label("mode", "inline")),
     }
 });
+pub static CMD_MERCY_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_mercy", Opts::new("command_mercy_usage_total", "count of mercies shown by the winners of battles"))
+});
+pub static CMD_REMATCH_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_rematch", Opts::new("command_rematch_usage_total", "count of rematches proposed after battles"))
+});
+pub static CMD_SIDE_BET_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_side_bet", Opts::new("command_side_bet_usage_total", "count of side bets placed by spectators of battles"))
+});
+pub static CMD_HISTORY_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_history", Opts::new("command_history_usage_total", "count of /history invocations"))
+});
+pub static CMD_BATTLES_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_battles", Opts::new("command_battles_usage_total", "count of /battles invocations"))
+});
+pub static CMD_TIMEZONE_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_timezone", Opts::new("command_timezone_usage_total", "count of /timezone invocations"))
+});
+pub static CMD_RULES_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_rules", Opts::new("command_rules_usage_total", "count of /rules invocations"))
+});
+pub static CMD_ACHIEVEMENTS_COUNTER: Lazy<Counter> = Lazy::new(|| {
+    Counter::new("command_achievements", Opts::new("command_achievements_usage_total", "count of /achievements invocations"))
+});
+pub static CMD_SHOP: Lazy<ComplexCommandCounters> = Lazy::new(|| {
+    let opts = Opts::new("command_shop_usage_total", "count of /shop invocations and purchases");
+    ComplexCommandCounters {
+        invoked: Counter::new("command_shop (invoked)", opts.clone().const_label("state", "invoked")),
+        finished: Counter::new("command_shop (finished)", opts.const_label("state", "finished")),
+    }
+});
+pub static CMD_TOURNAMENT: Lazy<ComplexCommandCounters> = Lazy::new(|| {
+    let opts = Opts::new("command_tournament_usage_total", "count of /tournament invocations and opened tournaments");
+    ComplexCommandCounters {
+        invoked: Counter::new("command_tournament (invoked)", opts.clone().const_label("state", "invoked")),
+        finished: Counter::new("command_tournament (finished)", opts.const_label("state", "finished")),
+    }
+});
+pub static CMD_TEAMBATTLE: Lazy<ComplexCommandCounters> = Lazy::new(|| {
+    let opts = Opts::new("command_teambattle_usage_total", "count of /teambattle invocations and fought team battles");
+    ComplexCommandCounters {
+        invoked: Counter::new("command_teambattle (invoked)", opts.clone().const_label("state", "invoked")),
+        finished: Counter::new("command_teambattle (finished)", opts.const_label("state", "finished")),
+    }
+});
+pub static CMD_DUEL: Lazy<ComplexCommandCounters> = Lazy::new(|| {
+    let opts = Opts::new("command_duel_usage_total", "count of /duel invocations and finished duels");
+    ComplexCommandCounters {
+        invoked: Counter::new("command_duel (invoked)", opts.clone().const_label("state", "invoked")),
+        finished: Counter::new("command_duel (finished)", opts.const_label("state", "finished")),
+    }
+});
+pub static CMD_USE: Lazy<ComplexCommandCounters> = Lazy::new(|| {
+    let opts = Opts::new("command_use_usage_total", "count of /use invocations and used items");
+    ComplexCommandCounters {
+        invoked: Counter::new("command_use (invoked)", opts.clone().const_label("state", "invoked")),
+        finished: Counter::new("command_use (finished)", opts.const_label("state", "finished")),
+    }
+});
 pub static CMD_IMPORT: Lazy<ComplexCommandCounters> = Lazy::new(|| {
     let opts = Opts::new("command_import_usage_total", "count of /import invocations and successes");
     ComplexCommandCounters {
@@ -105,8 +164,26 @@ pub fn init() -> axum::Router {
         .register(&CMD_DOD_COUNTER.inline)
         .register(&CMD_PVP_COUNTER.chat)
         .register(&CMD_PVP_COUNTER.inline)
+        .register(&CMD_MERCY_COUNTER)
+        .register(&CMD_REMATCH_COUNTER)
+        .register(&CMD_SIDE_BET_COUNTER)
         .register(&CMD_STATS.chat)
         .register(&CMD_STATS.inline)
+        .register(&CMD_HISTORY_COUNTER)
+        .register(&CMD_BATTLES_COUNTER)
+        .register(&CMD_TIMEZONE_COUNTER)
+        .register(&CMD_RULES_COUNTER)
+        .register(&CMD_ACHIEVEMENTS_COUNTER)
+        .register(&CMD_SHOP.invoked)
+        .register(&CMD_SHOP.finished)
+        .register(&CMD_USE.invoked)
+        .register(&CMD_USE.finished)
+        .register(&CMD_TOURNAMENT.invoked)
+        .register(&CMD_TOURNAMENT.finished)
+        .register(&CMD_TEAMBATTLE.invoked)
+        .register(&CMD_TEAMBATTLE.finished)
+        .register(&CMD_DUEL.invoked)
+        .register(&CMD_DUEL.finished)
         .register(&CMD_IMPORT.invoked)
         .register(&CMD_IMPORT.finished)
         .register(&CMD_PROMO.invoked_by_command)
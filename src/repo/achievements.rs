@@ -0,0 +1,79 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use teloxide::types::UserId;
+use crate::repo::ChatIdKind;
+use crate::repository;
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct AchievementStatus {
+    pub code: String,
+    pub unlocked_at: Option<DateTime<Utc>>,
+}
+
+repository!(Achievements, with_(chats)_(Chats),
+    /// Unlocks all the achievements whose rules are satisfied now and returns the codes of the new ones.
+    pub async fn check(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<Vec<String>> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        self.check_internal(chat_internal_id, user_id).await
+    }
+,
+    /// Used when the change isn't bound to a specific chat, like an activation of a promo code.
+    pub async fn check_in_all_chats(&self, user_id: UserId) -> anyhow::Result<Vec<String>> {
+        let chats = sqlx::query_scalar!("SELECT chat_id FROM Hemoroids WHERE uid = $1",
+                user_id.0 as i64)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the chats of {user_id}"))?;
+        let mut codes = Vec::new();
+        for chat_internal_id in chats {
+            for code in self.check_internal(chat_internal_id, user_id).await? {
+                if !codes.contains(&code) {
+                    codes.push(code)
+                }
+            }
+        }
+        Ok(codes)
+    }
+,
+    async fn check_internal(&self, chat_internal_id: i64, user_id: UserId) -> anyhow::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            "WITH metrics AS (
+                SELECT
+                    (SELECT win_streak_max FROM Battle_Stats WHERE uid = $1 AND chat_id = $2) AS win_streak_max,
+                    (SELECT count(*) FROM Hemoroid_of_Day WHERE lowest_uid = $1 AND chat_id = $2) AS hod_wins,
+                    (SELECT protrusion_level FROM Hemoroids WHERE uid = $1 AND chat_id = $2) AS protrusion_level,
+                    (SELECT streak_max FROM Hemoroids WHERE uid = $1 AND chat_id = $2) AS streak_max,
+                    (SELECT count(*) FROM Loans WHERE uid = $1 AND chat_id = $2 AND repaid_at IS NOT NULL) AS loans_repaid,
+                    (SELECT count(*) FROM Promo_Code_Activations WHERE uid = $1) AS promo_codes
+            )
+            INSERT INTO Unlocked_Achievements (uid, chat_id, code)
+                SELECT $1, $2, a.code FROM Achievements a, metrics m
+                WHERE CASE a.rule
+                    WHEN 'battle_win_streak' THEN m.win_streak_max >= a.threshold
+                    WHEN 'hod_wins' THEN m.hod_wins >= a.threshold
+                    WHEN 'level_at_most' THEN m.protrusion_level <= a.threshold
+                    WHEN 'treatment_streak' THEN m.streak_max >= a.threshold
+                    WHEN 'loans_repaid' THEN m.loans_repaid >= a.threshold
+                    WHEN 'promo_codes' THEN m.promo_codes >= a.threshold
+                END
+            ON CONFLICT (uid, chat_id, code) DO NOTHING
+            RETURNING code",
+                user_id.0 as i64, chat_internal_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't check the achievements of {user_id} in the chat with id = {chat_internal_id}"))
+    }
+,
+    /// Returns all the defined achievements; the locked ones have no unlocking time.
+    pub async fn get_all(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<Vec<AchievementStatus>> {
+        sqlx::query_as!(AchievementStatus,
+            "SELECT a.code, ua.unlocked_at FROM Achievements a
+                LEFT JOIN Unlocked_Achievements ua ON ua.code = a.code AND ua.uid = $1
+                    AND ua.chat_id = (SELECT id FROM Chats WHERE chat_id = $2::bigint OR chat_instance = $2::text)
+                ORDER BY ua.unlocked_at NULLS LAST, a.rule, a.threshold",
+                user_id.0 as i64, chat_id.value() as String)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the achievements of {user_id} in {chat_id}"))
+    }
+);
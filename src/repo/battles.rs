@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres, Transaction};
+use teloxide::types::UserId;
+use crate::config::BattleLimitsConfig;
+use crate::domain::{Tenths, Username};
+use crate::repo::ChatIdKind;
+use crate::repository;
+
+#[derive(Debug, Clone)]
+pub struct NewBattle {
+    pub initiator: UserId,
+    pub top: UserId,
+    pub bottom: UserId,
+    /// In tenths of a centimetre.
+    pub top_damage: i32,
+    pub bottom_damage: i32,
+    pub bet: u16,
+    pub winner: UserId,
+    /// The previous battle of the same pair if this one is a rematch.
+    pub rematch_of: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BattleParticipant {
+    pub uid: UserId,
+    pub name: Username,
+    pub damage: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BattleRecord {
+    pub id: i64,
+    pub initiator: UserId,
+    pub top: BattleParticipant,
+    pub bottom: BattleParticipant,
+    pub bet: u16,
+    pub winner: UserId,
+    /// The number of the rematch in its chain, 0 for an ordinary battle.
+    pub rematch: u16,
+    pub fought_at: DateTime<Utc>,
+}
+
+/// The battles fought today by a pair of users, according to the local date of the chat.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DailyBattles {
+    pub pair: u32,
+    pub first: u32,
+    pub second: u32,
+}
+
+impl DailyBattles {
+    /// The first one of the battles is the initiator's, the second one is the acceptor's.
+    pub fn exceeded(&self, limits: &BattleLimitsConfig) -> Option<DailyLimit> {
+        let reached = |limit: u16, battles: u32| limit > 0 && battles >= u32::from(limit);
+        if reached(limits.pair_daily, self.pair) {
+            Some(DailyLimit::Pair(limits.pair_daily))
+        } else if reached(limits.user_daily, self.second) {
+            Some(DailyLimit::Acceptor(limits.user_daily))
+        } else if reached(limits.user_daily, self.first) {
+            Some(DailyLimit::Initiator(limits.user_daily))
+        } else {
+            None
+        }
+    }
+}
+
+/// The daily limit a challenge has run into, along with its value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DailyLimit {
+    Pair(u16),
+    Initiator(u16),
+    Acceptor(u16),
+}
+
+/// The running score of a chain of rematches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesScore {
+    /// The number of the latest rematch in the chain.
+    pub rematch: u16,
+    wins: HashMap<UserId, u32>,
+}
+
+impl SeriesScore {
+    pub fn wins_of(&self, uid: UserId) -> u32 {
+        self.wins.get(&uid).copied().unwrap_or_default()
+    }
+}
+
+repository!(BattleLog, with_(chats)_(Chats),
+    /// Battles are usually recorded by `Hemoroids::penetrate` along with their results.
+    /// Here the whole bet is considered moved.
+    pub async fn record(&self, chat_id: &ChatIdKind, battle: &NewBattle) -> anyhow::Result<i64> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let mut tx = self.pool.begin().await?;
+        let award = Tenths::from_cm(battle.bet.into()).value();
+        let battle_id = record_battle(&mut tx, chat_internal_id, battle, award).await?;
+        tx.commit().await?;
+        Ok(battle_id)
+    }
+,
+    /// Returns the score of the chain of rematches the battle belongs to, or nothing if there were no rematches yet.
+    pub async fn get_series_score(&self, battle_id: i64) -> anyhow::Result<Option<SeriesScore>> {
+        let rows = sqlx::query!(r#"WITH series AS (SELECT COALESCE(series_id, id) AS id FROM Battles WHERE id = $1)
+                SELECT b.winner_uid, count(*) AS "wins!", max(b.rematch) AS "rematch!"
+                FROM Battles b, series s
+                WHERE b.id = s.id OR b.series_id = s.id
+                GROUP BY b.winner_uid"#,
+                battle_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the series score of the battle {battle_id}"))?;
+        let rematch = rows.iter()
+            .map(|row| row.rematch as u16)
+            .max()
+            .unwrap_or_default();
+        if rematch == 0 {
+            return Ok(None)
+        }
+        let wins = rows.into_iter()
+            .map(|row| (UserId(row.winner_uid as u64), row.wins as u32))
+            .collect();
+        Ok(Some(SeriesScore { rematch, wins }))
+    }
+,
+    pub async fn count_today(&self, chat_id: &ChatIdKind, first: UserId, second: UserId) -> anyhow::Result<DailyBattles> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        count_battles_today(&self.pool, chat_internal_id, first, second).await
+    }
+,
+    /// Returns the battles of the user in the chat, the most recent ones first.
+    pub async fn get_page(&self, chat_id: &ChatIdKind, user_id: UserId, offset: u32, limit: u16) -> anyhow::Result<Vec<BattleRecord>> {
+        let records = sqlx::query!(r#"SELECT b.id, b.initiator_uid, b.top_uid, tu.name AS top_name, b.top_damage,
+                    b.bottom_uid, bu.name AS bottom_name, b.bottom_damage, b.bet, b.winner_uid, b.rematch, b.fought_at
+                FROM Battles b
+                JOIN Chats c ON b.chat_id = c.id
+                JOIN Users tu ON b.top_uid = tu.uid
+                JOIN Users bu ON b.bottom_uid = bu.uid
+                WHERE (c.chat_id = $1::bigint OR c.chat_instance = $1::text) AND $2 IN (b.top_uid, b.bottom_uid)
+                ORDER BY b.fought_at DESC, b.id DESC
+                OFFSET $3 LIMIT $4"#,
+                chat_id.value() as String, user_id.0 as i64, offset as i64, limit as i32)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the battles of {user_id} in {chat_id} with offset = {offset} and limit = {limit}"))?
+            .into_iter()
+            .map(|row| BattleRecord {
+                id: row.id,
+                initiator: UserId(row.initiator_uid as u64),
+                top: BattleParticipant {
+                    uid: UserId(row.top_uid as u64),
+                    name: Username::new(row.top_name),
+                    damage: row.top_damage,
+                },
+                bottom: BattleParticipant {
+                    uid: UserId(row.bottom_uid as u64),
+                    name: Username::new(row.bottom_name),
+                    damage: row.bottom_damage,
+                },
+                bet: row.bet as u16,
+                winner: UserId(row.winner_uid as u64),
+                rematch: row.rematch as u16,
+                fought_at: row.fought_at,
+            })
+            .collect();
+        Ok(records)
+    }
+);
+
+/// The `award` is the part of the bet actually moved to the loser, in tenths of a centimetre.
+pub(super) async fn record_battle(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, battle: &NewBattle, award: i32) -> anyhow::Result<i64> {
+    // a rematch continues the series of the previous battle, unless the latter has gone somewhere
+    let previous = match battle.rematch_of {
+        Some(previous_id) => sqlx::query!(r#"SELECT id, COALESCE(series_id, id) AS "series_id!", rematch FROM Battles
+                WHERE id = $1 AND chat_id = $2"#,
+                previous_id, chat_internal_id)
+            .fetch_optional(&mut **tx)
+            .await
+            .context(format!("couldn't find the previous battle {previous_id} in {chat_internal_id}"))?,
+        None => None
+    };
+    let (rematch_of, series_id, rematch) = match previous {
+        Some(row) => (Some(row.id), Some(row.series_id), row.rematch + 1),
+        None => (None, None, 0)
+    };
+    sqlx::query_scalar!("INSERT INTO Battles (chat_id, initiator_uid, top_uid, bottom_uid, top_damage, bottom_damage, bet, winner_uid,
+                rematch_of, series_id, rematch, award)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id",
+            chat_internal_id, battle.initiator.0 as i64, battle.top.0 as i64, battle.bottom.0 as i64,
+            battle.top_damage, battle.bottom_damage, battle.bet as i16, battle.winner.0 as i64,
+            rematch_of, series_id, rematch, award)
+        .fetch_one(&mut **tx)
+        .await
+        .context(format!("couldn't record the battle {battle:?} with the award of {award} in {chat_internal_id}"))
+}
+
+pub(super) async fn count_battles_today<'c, E>(executor: E, chat_internal_id: i64, first: UserId, second: UserId) -> anyhow::Result<DailyBattles>
+where E: Executor<'c, Database = Postgres>,
+{
+    let counts = sqlx::query!(r#"SELECT
+                count(*) FILTER (WHERE $2 IN (top_uid, bottom_uid) AND $3 IN (top_uid, bottom_uid)) AS "pair!",
+                count(*) FILTER (WHERE $2 IN (top_uid, bottom_uid)) AS "first!",
+                count(*) FILTER (WHERE $3 IN (top_uid, bottom_uid)) AS "second!"
+            FROM Battles
+            WHERE chat_id = $1 AND chat_local_date(chat_id, fought_at) = chat_local_date(chat_id, current_timestamp)"#,
+            chat_internal_id, first.0 as i64, second.0 as i64)
+        .fetch_one(executor)
+        .await
+        .context(format!("couldn't count the battles of {first} and {second} in {chat_internal_id} today"))?;
+    Ok(DailyBattles {
+        pair: counts.pair as u32,
+        first: counts.first as u32,
+        second: counts.second as u32,
+    })
+}
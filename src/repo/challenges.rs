@@ -0,0 +1,74 @@
+use anyhow::Context;
+use teloxide::types::{ChatId, MessageId};
+use crate::domain::LanguageCode;
+use crate::repository;
+
+/// A message with an open battle challenge, sent either to a chat directly or via an inline query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChallengeMessage {
+    Chat(ChatId, MessageId),
+    Inline(String),
+}
+
+impl ChallengeMessage {
+    fn columns(&self) -> (Option<i64>, Option<i32>, Option<&str>) {
+        match self {
+            Self::Chat(chat_id, message_id) => (Some(chat_id.0), Some(message_id.0), None),
+            Self::Inline(inline_message_id) => (None, None, Some(inline_message_id.as_str())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpiredChallenge {
+    pub message: ChallengeMessage,
+    pub lang_code: LanguageCode,
+}
+
+repository!(BattleChallenges,
+    pub async fn register(&self, message: &ChallengeMessage, lang_code: &LanguageCode) -> anyhow::Result<()> {
+        let (chat_id, message_id, inline_message_id) = message.columns();
+        sqlx::query!("INSERT INTO Battle_Challenges (chat_id, message_id, inline_message_id, language) VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING",
+                chat_id, message_id, inline_message_id, lang_code.as_str())
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't register the challenge in {message:?}"))?;
+        Ok(())
+    }
+,
+    pub async fn remove(&self, message: &ChallengeMessage) -> anyhow::Result<()> {
+        let (chat_id, message_id, inline_message_id) = message.columns();
+        sqlx::query!("DELETE FROM Battle_Challenges WHERE (chat_id = $1 AND message_id = $2) OR inline_message_id = $3",
+                chat_id, message_id, inline_message_id)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't remove the challenge in {message:?}"))?;
+        Ok(())
+    }
+,
+    /// Removes the challenges older than `ttl_minutes` and returns them to clean their messages up.
+    pub async fn take_expired(&self, ttl_minutes: u32) -> anyhow::Result<Vec<ExpiredChallenge>> {
+        let challenges = sqlx::query!(
+            "DELETE FROM Battle_Challenges WHERE created_at <= current_timestamp - make_interval(mins => $1)
+                RETURNING chat_id, message_id, inline_message_id, language",
+                ttl_minutes as i32)
+            .fetch_all(&self.pool)
+            .await
+            .context("couldn't take the expired challenges")?
+            .into_iter()
+            .filter_map(|row| {
+                let message = match (row.chat_id, row.message_id, row.inline_message_id) {
+                    (Some(chat_id), Some(message_id), _) => ChallengeMessage::Chat(ChatId(chat_id), MessageId(message_id)),
+                    (_, _, Some(inline_message_id)) => ChallengeMessage::Inline(inline_message_id),
+                    _ => return None
+                };
+                Some(ExpiredChallenge {
+                    message,
+                    lang_code: LanguageCode::new(row.language),
+                })
+            })
+            .collect();
+        Ok(challenges)
+    }
+);
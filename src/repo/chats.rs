@@ -1,10 +1,22 @@
 use std::fmt::Formatter;
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
+use chrono::FixedOffset;
 use sqlx::{Postgres, Transaction};
 use teloxide::types::ChatId;
 use super::{ChatIdFull, ChatIdKind, ChatIdPartiality, ChatIdSource, ensure_only_one_row_updated};
 use crate::repository;
 
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+#[derive(sqlx::Type, Debug, Default, Copy, Clone, PartialEq, Eq, strum_macros::Display, strum_macros::EnumString, strum_macros::EnumIter)]
+#[sqlx(type_name = "battle_rules", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BattleRuleSet {
+    #[default]
+    TopBottom,
+    Classic,
+}
+
 #[derive(sqlx::FromRow, Debug, Clone)]
 pub struct Chat {
     pub internal_id: i64,
@@ -52,6 +64,62 @@ repository!(Chats, with_feature_toggles,
             .ok_or(SearchError::NotFound(chat_id.clone()))
     }
 ,
+    pub async fn get_timezone(&self, chat_id: &ChatIdKind) -> anyhow::Result<String> {
+        sqlx::query_scalar!("SELECT timezone FROM Chats WHERE chat_id = $1::bigint OR chat_instance = $1::text",
+                chat_id.value() as String)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|tz| tz.unwrap_or_else(|| DEFAULT_TIMEZONE.to_owned()))
+            .context(format!("couldn't get the timezone of the chat with id = {chat_id}"))
+    }
+,
+    /// Returns the current offset of the chat's local time from UTC.
+    pub async fn get_utc_offset(&self, chat_id: &ChatIdKind) -> anyhow::Result<FixedOffset> {
+        let seconds = sqlx::query_scalar!(
+            r#"SELECT extract(epoch FROM (current_timestamp AT TIME ZONE timezone) - (current_timestamp AT TIME ZONE 'UTC'))::integer AS "offset!"
+                FROM Chats WHERE chat_id = $1::bigint OR chat_instance = $1::text"#,
+                chat_id.value() as String)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't get the UTC offset of the chat with id = {chat_id}"))?
+            .unwrap_or_default();
+        FixedOffset::east_opt(seconds)
+            .ok_or(anyhow!("invalid UTC offset of the chat with id = {chat_id}: {seconds}"))
+    }
+,
+    /// Returns the canonical name of the timezone or `None` if PostgreSQL doesn't know such a timezone.
+    pub async fn set_timezone(&self, chat_id: &ChatIdPartiality, timezone: &str) -> anyhow::Result<Option<String>> {
+        let internal_id = self.upsert_chat(chat_id).await?;
+        sqlx::query_scalar!("UPDATE Chats SET timezone = tz.name FROM (
+                    SELECT name FROM pg_timezone_names WHERE lower(name) = lower($2) ORDER BY name LIMIT 1
+                ) AS tz
+                WHERE id = $1
+                RETURNING timezone",
+                internal_id, timezone)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't set the timezone of the chat with id = {chat_id} to {timezone}"))
+    }
+,
+    pub async fn get_battle_rules(&self, chat_id: &ChatIdKind) -> anyhow::Result<BattleRuleSet> {
+        sqlx::query_scalar!(r#"SELECT battle_rules AS "battle_rules: BattleRuleSet" FROM Chats WHERE chat_id = $1::bigint OR chat_instance = $1::text"#,
+                chat_id.value() as String)
+            .fetch_optional(&self.pool)
+            .await
+            .map(Option::unwrap_or_default)
+            .context(format!("couldn't get the battle rules of the chat with id = {chat_id}"))
+    }
+,
+    pub async fn set_battle_rules(&self, chat_id: &ChatIdPartiality, rules: BattleRuleSet) -> anyhow::Result<()> {
+        let internal_id = self.upsert_chat(chat_id).await?;
+        sqlx::query!("UPDATE Chats SET battle_rules = $2 WHERE id = $1",
+                internal_id, rules as BattleRuleSet)
+            .execute(&self.pool)
+            .await
+            .map_err(Into::into)
+            .and_then(ensure_only_one_row_updated)
+            .context(format!("couldn't set the battle rules of the chat with id = {chat_id} to {rules}"))
+    },
     pub async fn upsert_chat(&self, chat_id: &ChatIdPartiality) -> anyhow::Result<i64> {
         let (id, instance) = match chat_id {
             ChatIdPartiality::Both(full, _) if self.features.chats_merging => (Some(full.id.0), Some(full.instance.to_owned())),
@@ -115,6 +183,77 @@ repository!(Chats, with_feature_toggles,
             .rows_affected();
         log::info!("merging chats: {chats:?}, updated dicks: {updated_dicks}, deleted: {deleted_dicks}");
 
+        sqlx::query!("UPDATE Treatment_History SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the treatment history from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("INSERT INTO Unlocked_Achievements (uid, chat_id, code, unlocked_at)
+                    SELECT uid, $1, code, unlocked_at FROM Unlocked_Achievements WHERE chat_id = $2
+                    ON CONFLICT (uid, chat_id, code) DO NOTHING",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the achievements from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("INSERT INTO Wallets (uid, chat_id, coins)
+                    SELECT uid, $1, coins FROM Wallets WHERE chat_id = $2
+                    ON CONFLICT (uid, chat_id) DO UPDATE SET coins = Wallets.coins + EXCLUDED.coins",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the wallets from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("UPDATE Purchases SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the purchases from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("INSERT INTO Inventory (uid, chat_id, item, quantity)
+                    SELECT uid, $1, item, quantity FROM Inventory WHERE chat_id = $2
+                    ON CONFLICT (uid, chat_id, item) DO UPDATE SET quantity = Inventory.quantity + EXCLUDED.quantity",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the inventory from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("UPDATE Active_Effects SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the active effects from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("INSERT INTO Mercies (chat_id, winner_uid, loser_uid, battle_timestamp, amount, shown_at)
+                    SELECT $1, winner_uid, loser_uid, battle_timestamp, amount, shown_at FROM Mercies WHERE chat_id = $2
+                    ON CONFLICT DO NOTHING",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the mercies from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("UPDATE Battles SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the battle log from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("UPDATE Duels SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the duels from the old chat with id = {}", state.deleted.0))?;
+        sqlx::query!("UPDATE Side_Bets SET chat_id = $1 WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the side bets from the old chat with id = {}", state.deleted.0))?;
+        // only one tournament may be active in a chat, so the one of the old chat is cancelled in case of a clash
+        sqlx::query!("UPDATE Tournaments SET chat_id = $1, status = CASE
+                        WHEN status IN ('registration', 'running') AND EXISTS (
+                            SELECT 1 FROM Tournaments WHERE chat_id = $1 AND status IN ('registration', 'running')
+                        ) THEN 'cancelled'
+                        ELSE status
+                    END
+                    WHERE chat_id = $2",
+                state.main.internal_id, state.deleted.0)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't move the tournaments from the old chat with id = {}", state.deleted.0))?;
+
         sqlx::query!("DELETE FROM Chats WHERE id = $1 AND chat_instance = $2",
                 state.deleted.0, state.deleted.1)
             .execute(&mut **tx)
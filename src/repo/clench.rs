@@ -0,0 +1,80 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres};
+use teloxide::types::UserId;
+use crate::repo::ChatIdKind;
+use crate::repository;
+
+pub enum ClenchAttempt {
+    Shielded { expires_at: DateTime<Utc> },
+    Failed,
+    OnCooldown { available_at: DateTime<Utc> },
+}
+
+repository!(Clenches, with_(chats)_(Chats),
+    pub async fn attempt(&self, chat_id: &ChatIdKind, user_id: UserId, success: bool, shield_hours: u16, cooldown_hours: u16) -> anyhow::Result<ClenchAttempt> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let uid = user_id.0 as i64;
+        let updated = sqlx::query_scalar!(
+            "INSERT INTO Clench_Shields AS cs (uid, chat_id, shield_expires_at)
+                VALUES ($1, $2, CASE WHEN $3 THEN current_timestamp + make_interval(hours => $4) END)
+                ON CONFLICT (uid, chat_id) DO UPDATE SET
+                    attempted_at = current_timestamp,
+                    shield_expires_at = coalesce(EXCLUDED.shield_expires_at, cs.shield_expires_at)
+                WHERE cs.attempted_at <= current_timestamp - make_interval(hours => $5)
+                RETURNING shield_expires_at",
+                uid, chat_internal_id, success, shield_hours as i32, cooldown_hours as i32)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't save a clench attempt of {user_id} in {chat_id}"))?;
+
+        let attempt = match updated {
+            Some(Some(expires_at)) if success => ClenchAttempt::Shielded { expires_at },
+            Some(_) => ClenchAttempt::Failed,
+            None => {
+                let available_at = sqlx::query_scalar!(
+                    r#"SELECT attempted_at + make_interval(hours => $3) AS "available_at!" FROM Clench_Shields
+                        WHERE uid = $1 AND chat_id = $2"#,
+                        uid, chat_internal_id, cooldown_hours as i32)
+                    .fetch_one(&self.pool)
+                    .await
+                    .context(format!("couldn't fetch the clench cooldown of {user_id} in {chat_id}"))?;
+                ClenchAttempt::OnCooldown { available_at }
+            }
+        };
+        Ok(attempt)
+    }
+,
+    /// The shield is used up only by `Hemoroids::penetrate`, in the transaction of the battle.
+    pub async fn has_shield(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<bool> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM Clench_Shields
+                WHERE uid = $1 AND
+                    chat_id = (SELECT id FROM Chats WHERE chat_id = $2::bigint OR chat_instance = $2::text)
+                    AND shield_expires_at > current_timestamp) AS "exists!""#,
+                user_id.0 as i64, chat_id.value() as String)
+            .fetch_one(&self.pool)
+            .await
+            .context(format!("couldn't check the clench shield of {user_id} in {chat_id}"))
+    }
+,
+    /// Returns true if the user had an active shield, which is used up by this call.
+    pub async fn consume_shield(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<bool> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        consume_shield_internal(&self.pool, chat_internal_id, user_id).await
+    }
+);
+
+pub(super) async fn consume_shield_internal<'c, E>(executor: E, chat_internal_id: i64, user_id: UserId) -> anyhow::Result<bool>
+where E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query_scalar!(
+        "UPDATE Clench_Shields SET shield_expires_at = NULL
+            WHERE uid = $1 AND chat_id = $2 AND shield_expires_at > current_timestamp
+            RETURNING uid",
+            user_id.0 as i64, chat_internal_id)
+        .fetch_optional(executor)
+        .await
+        .map(|uid| uid.is_some())
+        .context(format!("couldn't consume the clench shield of {user_id} in {chat_internal_id}"))
+}
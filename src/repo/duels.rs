@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Context};
+use teloxide::types::{ChatId, MessageId, UserId};
+use crate::domain::{LanguageCode, Username};
+use crate::repo::ChatIdKind;
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, Eq)]
+#[sqlx(type_name = "duel_status", rename_all = "lowercase")]
+pub enum DuelStatus {
+    Open,
+    Running,
+    Finished,
+    Cancelled,
+}
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, Eq, strum_macros::Display, strum_macros::EnumString)]
+#[sqlx(type_name = "duel_action", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum DuelAction {
+    Push,
+    Clench,
+    Dodge,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuelPlayer {
+    pub uid: UserId,
+    pub name: Username,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuelRound {
+    pub round: i16,
+    /// `None` means the player didn't respond in time.
+    pub initiator_action: Option<DuelAction>,
+    pub acceptor_action: Option<DuelAction>,
+    /// `None` means a draw.
+    pub winner: Option<UserId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Duel {
+    pub id: i32,
+    pub chat_id: ChatId,
+    pub message_id: Option<MessageId>,
+    pub lang_code: LanguageCode,
+    pub initiator: DuelPlayer,
+    pub acceptor: Option<DuelPlayer>,
+    pub best_of: u16,
+    pub bet: u16,
+    pub status: DuelStatus,
+    pub round: i16,
+    pub initiator_action: Option<DuelAction>,
+    pub acceptor_action: Option<DuelAction>,
+    pub initiator_wins: u16,
+    pub acceptor_wins: u16,
+    /// In tenths of a centimetre, accumulated over the finished rounds.
+    pub initiator_damage: i32,
+    pub acceptor_damage: i32,
+    pub rounds: Vec<DuelRound>,
+}
+
+impl Duel {
+    pub fn wins_needed(&self) -> u16 {
+        self.best_of / 2 + 1
+    }
+}
+
+/// The result of a round decided by the outcome matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundOutcome {
+    pub winner: Option<UserId>,
+    pub initiator_damage: i32,
+    pub acceptor_damage: i32,
+}
+
+pub struct NewDuel {
+    pub initiator: UserId,
+    pub lang_code: LanguageCode,
+    pub best_of: u16,
+    pub bet: u16,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum DuelError {
+    Started,
+    OwnDuel,
+    NotParticipant,
+    AlreadyChosen,
+    Finished,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for DuelError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(Duels, with_(chats)_(Chats),
+    pub async fn create(&self, chat_id: ChatId, params: &NewDuel) -> anyhow::Result<Duel> {
+        let chat_internal_id = self.chats.get_internal_id(&ChatIdKind::ID(chat_id)).await?;
+        let duel_id = sqlx::query_scalar!("INSERT INTO Duels (chat_id, tg_chat_id, language, initiator_uid, best_of, bet) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+                chat_internal_id, chat_id.0, params.lang_code.as_str(), params.initiator.0 as i64, params.best_of as i16, params.bet as i16)
+            .fetch_one(&self.pool)
+            .await
+            .context(format!("couldn't create a duel of {} in {chat_id}", params.initiator))?;
+        self.get(duel_id).await?
+            .ok_or(anyhow!("the duel {duel_id} must exist right after its creation"))
+    }
+,
+    pub async fn set_message(&self, duel_id: i32, message_id: MessageId) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE Duels SET message_id = $2 WHERE id = $1",
+                duel_id, message_id.0)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't set the message of the duel {duel_id}"))?;
+        Ok(())
+    }
+,
+    pub async fn get(&self, duel_id: i32) -> anyhow::Result<Option<Duel>> {
+        let Some(row) = sqlx::query!(r#"SELECT d.id, d.tg_chat_id, d.message_id, d.language,
+                    d.initiator_uid, iu.name AS initiator_name, d.acceptor_uid, au.name AS "acceptor_name?",
+                    d.best_of, d.bet, d.status AS "status: DuelStatus", d.round,
+                    d.initiator_action AS "initiator_action: DuelAction", d.acceptor_action AS "acceptor_action: DuelAction",
+                    d.initiator_wins, d.acceptor_wins, d.initiator_damage, d.acceptor_damage
+                FROM Duels d
+                JOIN Users iu ON iu.uid = d.initiator_uid
+                LEFT JOIN Users au ON au.uid = d.acceptor_uid
+                WHERE d.id = $1"#,
+                duel_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't get the duel {duel_id}"))? else {
+            return Ok(None)
+        };
+        let rounds = sqlx::query!(r#"SELECT round, initiator_action AS "initiator_action: DuelAction",
+                    acceptor_action AS "acceptor_action: DuelAction", winner_uid
+                FROM Duel_Rounds WHERE duel_id = $1
+                ORDER BY round"#,
+                duel_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the rounds of the duel {duel_id}"))?
+            .into_iter()
+            .map(|r| DuelRound {
+                round: r.round,
+                initiator_action: r.initiator_action,
+                acceptor_action: r.acceptor_action,
+                winner: r.winner_uid.map(|uid| UserId(uid as u64)),
+            })
+            .collect();
+        let acceptor = row.acceptor_uid.zip(row.acceptor_name)
+            .map(|(uid, name)| DuelPlayer {
+                uid: UserId(uid as u64),
+                name: Username::new(name),
+            });
+        Ok(Some(Duel {
+            id: row.id,
+            chat_id: ChatId(row.tg_chat_id),
+            message_id: row.message_id.map(MessageId),
+            lang_code: LanguageCode::new(row.language),
+            initiator: DuelPlayer {
+                uid: UserId(row.initiator_uid as u64),
+                name: Username::new(row.initiator_name),
+            },
+            acceptor,
+            best_of: row.best_of as u16,
+            bet: row.bet as u16,
+            status: row.status,
+            round: row.round,
+            initiator_action: row.initiator_action,
+            acceptor_action: row.acceptor_action,
+            initiator_wins: row.initiator_wins as u16,
+            acceptor_wins: row.acceptor_wins as u16,
+            initiator_damage: row.initiator_damage,
+            acceptor_damage: row.acceptor_damage,
+            rounds,
+        }))
+    }
+,
+    /// Starts the first round.
+    pub async fn accept(&self, duel_id: i32, user_id: UserId) -> Result<Duel, DuelError> {
+        let accepted = sqlx::query!("UPDATE Duels SET acceptor_uid = $2, status = 'running', round = 1, round_started_at = current_timestamp
+                    WHERE id = $1 AND status = 'open' AND initiator_uid <> $2",
+                duel_id, user_id.0 as i64)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't accept the duel {duel_id} by {user_id}"))?
+            .rows_affected() > 0;
+        let duel = self.get(duel_id).await?
+            .ok_or(DuelError::Finished)?;
+        match (accepted, duel.status) {
+            (true, _) => Ok(duel),
+            (false, DuelStatus::Open) => Err(DuelError::OwnDuel),
+            (false, _) => Err(DuelError::Started),
+        }
+    }
+,
+    /// Saves the action of the player in the current round; every player picks only once per round.
+    pub async fn choose(&self, duel_id: i32, user_id: UserId, action: DuelAction) -> Result<Duel, DuelError> {
+        let uid = user_id.0 as i64;
+        let chosen = sqlx::query!("UPDATE Duels SET
+                        initiator_action = CASE WHEN initiator_uid = $2 THEN $3 ELSE initiator_action END,
+                        acceptor_action = CASE WHEN acceptor_uid = $2 THEN $3 ELSE acceptor_action END
+                    WHERE id = $1 AND status = 'running' AND (
+                        (initiator_uid = $2 AND initiator_action IS NULL) OR (acceptor_uid = $2 AND acceptor_action IS NULL)
+                    )",
+                duel_id, uid, action as DuelAction)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't save the action {action} of {user_id} in the duel {duel_id}"))?
+            .rows_affected() > 0;
+        let duel = self.get(duel_id).await?
+            .filter(|duel| duel.status == DuelStatus::Running)
+            .ok_or(DuelError::Finished)?;
+        let participant = duel.initiator.uid == user_id || duel.acceptor.as_ref().is_some_and(|p| p.uid == user_id);
+        match (chosen, participant) {
+            (true, _) => Ok(duel),
+            (false, false) => Err(DuelError::NotParticipant),
+            (false, true) => Err(DuelError::AlreadyChosen),
+        }
+    }
+,
+    /// Saves the outcome of the round and either starts the next one or finishes the duel.
+    /// Returns `None` if the round has already been completed by someone else.
+    pub async fn complete_round(&self, duel_id: i32, round: i16, outcome: &RoundOutcome) -> anyhow::Result<Option<Duel>> {
+        let mut tx = self.pool.begin().await?;
+        let Some(current) = sqlx::query!(r#"SELECT initiator_uid, initiator_action AS "initiator_action: DuelAction",
+                    acceptor_action AS "acceptor_action: DuelAction", initiator_wins, acceptor_wins, best_of
+                FROM Duels WHERE id = $1 AND round = $2 AND status = 'running'
+                FOR UPDATE"#,
+                duel_id, round)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't lock the round {round} of the duel {duel_id}"))? else {
+            return Ok(None)
+        };
+
+        sqlx::query!("INSERT INTO Duel_Rounds (duel_id, round, initiator_action, acceptor_action, winner_uid) VALUES ($1, $2, $3, $4, $5)",
+                duel_id, round, current.initiator_action as Option<DuelAction>, current.acceptor_action as Option<DuelAction>,
+                outcome.winner.map(|uid| uid.0 as i64))
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't save the round {round} of the duel {duel_id}"))?;
+
+        let initiator_won = outcome.winner.is_some_and(|uid| uid.0 as i64 == current.initiator_uid);
+        let acceptor_won = outcome.winner.is_some() && !initiator_won;
+        let initiator_wins = current.initiator_wins + i16::from(initiator_won);
+        let acceptor_wins = current.acceptor_wins + i16::from(acceptor_won);
+        let finished = initiator_wins.max(acceptor_wins) > current.best_of / 2;
+        let (status, next_round) = if finished { (DuelStatus::Finished, round) } else { (DuelStatus::Running, round + 1) };
+        sqlx::query!("UPDATE Duels SET initiator_action = NULL, acceptor_action = NULL,
+                        initiator_wins = $2, acceptor_wins = $3,
+                        initiator_damage = initiator_damage + $4, acceptor_damage = acceptor_damage + $5,
+                        status = $6, round = $7, round_started_at = current_timestamp
+                    WHERE id = $1",
+                duel_id, initiator_wins, acceptor_wins, outcome.initiator_damage, outcome.acceptor_damage,
+                status as DuelStatus, next_round)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't complete the round {round} of the duel {duel_id}"))?;
+        tx.commit().await?;
+
+        self.get(duel_id).await
+    }
+,
+    /// Returns `false` if the round has already been completed.
+    pub async fn cancel(&self, duel_id: i32, round: i16) -> anyhow::Result<bool> {
+        let cancelled = sqlx::query!("UPDATE Duels SET status = 'cancelled' WHERE id = $1 AND round = $2 AND status = 'running'",
+                duel_id, round)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't cancel the duel {duel_id}"))?
+            .rows_affected() > 0;
+        Ok(cancelled)
+    }
+,
+    /// Returns the running duels where at least one of the players hasn't responded for `timeout_seconds`.
+    pub async fn get_timed_out(&self, timeout_seconds: u32) -> anyhow::Result<Vec<Duel>> {
+        let ids = sqlx::query_scalar!("SELECT id FROM Duels
+                WHERE status = 'running' AND round_started_at <= current_timestamp - make_interval(secs => $1)",
+                timeout_seconds as f64)
+            .fetch_all(&self.pool)
+            .await
+            .context("couldn't get the timed out duels")?;
+        let mut duels = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(duel) = self.get(id).await? {
+                duels.push(duel);
+            }
+        }
+        Ok(duels)
+    }
+);
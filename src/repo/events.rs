@@ -0,0 +1,65 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use crate::domain::LanguageCode;
+use crate::repository;
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct ActiveEvent {
+    pub code: String,
+    pub title: String,
+    pub ends_at: DateTime<Utc>,
+}
+
+/// Multipliers of all the active events combined together.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EventModifiers {
+    pub shrink: f32,
+    pub battle_damage: f32,
+    pub hod_bonus: f32,
+}
+
+impl Default for EventModifiers {
+    fn default() -> Self {
+        Self {
+            shrink: 1.0,
+            battle_damage: 1.0,
+            hod_bonus: 1.0,
+        }
+    }
+}
+
+repository!(Events,
+    pub async fn get_active(&self, lang_code: &LanguageCode) -> anyhow::Result<Vec<ActiveEvent>> {
+        let language = lang_code.get(..2)
+            .unwrap_or("en")
+            .to_ascii_lowercase();
+        sqlx::query_as!(ActiveEvent,
+            r#"SELECT e.code, coalesce(
+                    (SELECT title FROM Event_Titles WHERE event_id = e.id AND language = $1),
+                    (SELECT title FROM Event_Titles WHERE event_id = e.id AND language = 'en'),
+                    e.code) AS "title!", e.ends_at
+                FROM Events e
+                WHERE current_timestamp BETWEEN e.starts_at AND e.ends_at
+                ORDER BY e.ends_at"#,
+                language)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the active events for {lang_code:?}"))
+    }
+,
+    pub async fn get_modifiers(&self) -> anyhow::Result<EventModifiers> {
+        let modifiers = sqlx::query!(
+            "SELECT shrink_multiplier, battle_damage_multiplier, hod_bonus_multiplier FROM Events
+                WHERE current_timestamp BETWEEN starts_at AND ends_at")
+            .fetch_all(&self.pool)
+            .await
+            .context("couldn't get the modifiers of the active events")?
+            .into_iter()
+            .fold(EventModifiers::default(), |acc, e| EventModifiers {
+                shrink: acc.shrink * e.shrink_multiplier,
+                battle_damage: acc.battle_damage * e.battle_damage_multiplier,
+                hod_bonus: acc.hod_bonus * e.hod_bonus_multiplier,
+            });
+        Ok(modifiers)
+    }
+);
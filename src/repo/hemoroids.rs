@@ -3,8 +3,14 @@ use futures::TryFutureExt;
 use rand::Rng;
 use sqlx::{Executor, Pool, Postgres, Transaction};
 use teloxide::types::UserId;
-use crate::config::FeatureToggles;
-use super::{ChatIdKind, ChatIdPartiality, Chats, UID};
+use crate::config::{BattleLimitsConfig, FeatureToggles};
+use crate::domain::Tenths;
+use super::{ChatIdKind, ChatIdPartiality, Chats, Inventory, UID};
+use super::history::{record_change, ChangeSource, LevelChange, PerkSettlement};
+use super::loans::{pay_off_loan, withhold_for_loan};
+use super::battles::{count_battles_today, record_battle, DailyLimit, NewBattle};
+use super::clench::consume_shield_internal;
+use super::side_bets::{settle_side_bets, SideBetPayout};
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct Hemoroid {
@@ -15,11 +21,77 @@ pub struct Hemoroid {
     pub position: Option<i64>,
 }
 
+/// The current streak is already reset if a day has been missed.
+#[derive(sqlx::FromRow, Debug, Default, Copy, Clone)]
+pub struct Streak {
+    pub current: i32,
+    pub max: i32,
+    pub treated_today: bool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct StreakHolder {
+    pub owner_uid: UID,
+    pub owner_name: String,
+    pub current: i32,
+    pub max: i32,
+    pub position: Option<i64>,
+}
+
 pub struct TreatmentResult {
     pub new_protrusion_level: i32,
     pub pos_in_top: Option<u64>,
 }
 
+/// The bet of a battle held in escrow: the loser's level grows by it and the winner's one shrinks by it, but not below zero.
+#[derive(Debug, Copy, Clone)]
+pub struct BattleBet {
+    pub initiator: UserId,
+    pub acceptor: UserId,
+    pub winner: UserId,
+    pub amount: Tenths,
+    /// Whether the acceptor must be able to afford the bet too (`PVP_CHECK_ACCEPTOR_LENGTH`).
+    pub check_acceptor: bool,
+}
+
+impl BattleBet {
+    fn loser(&self) -> UserId {
+        if self.winner == self.initiator { self.acceptor } else { self.initiator }
+    }
+}
+
+pub struct PenetrationResult {
+    /// The identifier of the battle in the log.
+    pub battle_id: i64,
+    pub top: TreatmentResult,
+    pub bottom: TreatmentResult,
+    /// The part of the bet moved to the loser; it's diminished for the repeated battles of the pair
+    /// and if the level of the winner has hit zero.
+    pub award: Tenths,
+    /// The battles of the pair fought earlier today, if the battle is limited.
+    pub previous_pair_battles: u32,
+    /// The part of the bet withheld from the winner to pay off their loan.
+    pub withheld: i32,
+    pub side_bets: Vec<SideBetPayout>,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PenetrationError {
+    /// The levels have changed since the check of the challenge, so the bet can't be afforded anymore.
+    NotEnoughInitiator,
+    NotEnoughAcceptor,
+    /// Other battles of the participants have reached the limit since the check of the challenge.
+    LimitReached(DailyLimit),
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for PenetrationError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
 #[derive(Clone)]
 pub struct Hemoroids {
     pool: Pool<Postgres>,
@@ -36,28 +108,30 @@ impl Hemoroids {
         }
     }
 
-    // Initialize new user with random hemoroid protrusion level between 2.5 and 5.0 cm
+    // Initialize new user with random hemoroid protrusion level between 2.5 and 5.0 cm (the level is stored in tenths)
     pub async fn initialize_new_user(&self, uid: UserId, chat_id: &ChatIdPartiality) -> anyhow::Result<TreatmentResult> {
         let uid = uid.0 as i64;
         let internal_chat_id = self.chats.upsert_chat(chat_id).await?;
-        let mut rng = rand::thread_rng();
-        let initial_level = (rng.gen_range(25..51) as f32 / 10.0) as i32;
+        let initial_level: i32 = rand::thread_rng().gen_range(25..=50);
 
+        let mut tx = self.pool.begin().await?;
         let protrusion_level = sqlx::query_scalar!(
             "INSERT INTO Hemoroids(uid, chat_id, protrusion_level, updated_at) VALUES ($1, $2, $3, current_timestamp)
                 ON CONFLICT (uid, chat_id) DO UPDATE SET protrusion_level = $3, updated_at = current_timestamp
                 RETURNING protrusion_level",
                 uid, internal_chat_id, initial_level)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
             .await
             .context(format!("couldn't initialize hemorrhoid of {uid} in {chat_id} with level of {initial_level}"))?;
+        record_change(&mut tx, internal_chat_id, uid, &initial_level.into(), ChangeSource::Treatment, None).await?;
+        tx.commit().await?;
         
         let pos_in_top = self.get_position_in_top(internal_chat_id, uid).await?;
         
         Ok(TreatmentResult { new_protrusion_level: protrusion_level, pos_in_top })
     }
 
-    pub async fn create_or_shrink(&self, uid: UserId, chat_id: &ChatIdPartiality, change: i32) -> anyhow::Result<TreatmentResult> {
+    pub async fn create_or_shrink(&self, uid: UserId, chat_id: &ChatIdPartiality, change: LevelChange) -> anyhow::Result<TreatmentResult> {
         let uid = uid.0 as i64;
         let internal_chat_id = self.chats.upsert_chat(chat_id).await?;
         
@@ -75,14 +149,19 @@ impl Hemoroids {
             return self.initialize_new_user(UserId(uid as u64), chat_id).await;
         }
         
+        let mut tx = self.pool.begin().await?;
+        let total_change = change.total();
         let new_protrusion_level = sqlx::query_scalar!(
             "INSERT INTO Hemoroids(uid, chat_id, protrusion_level, updated_at) VALUES ($1, $2, $3, current_timestamp)
                 ON CONFLICT (uid, chat_id) DO UPDATE SET protrusion_level = (Hemoroids.protrusion_level + $3), updated_at = current_timestamp
                 RETURNING protrusion_level",
-                uid, internal_chat_id, change)
-            .fetch_one(&self.pool)
+                uid, internal_chat_id, total_change)
+            .fetch_one(&mut *tx)
             .await
-            .context(format!("couldn't update the hemorrhoid of {uid} in {chat_id} with change of {change}"))?;
+            .context(format!("couldn't update the hemorrhoid of {uid} in {chat_id} with change of {total_change}"))?;
+        record_change(&mut tx, internal_chat_id, uid, &change, ChangeSource::Treatment, None).await?;
+        settle_perks(&mut tx, internal_chat_id, UserId(uid as u64), &change.settlements).await?;
+        tx.commit().await?;
         
         let pos_in_top = self.get_position_in_top(internal_chat_id, uid).await?;
         Ok(TreatmentResult { new_protrusion_level, pos_in_top })
@@ -146,41 +225,144 @@ impl Hemoroids {
             .context(format!("couldn't get the worst of {chat_id} with offset = {offset} and limit = {limit}"))
     }
 
-    pub async fn set_hod_winner(&self, chat_id: &ChatIdPartiality, user_id: UserId, improvement: u16) -> anyhow::Result<Option<TreatmentResult>> {
+    pub async fn fetch_streak(&self, uid: UserId, chat_id: &ChatIdKind) -> anyhow::Result<Option<Streak>> {
+        sqlx::query_as!(Streak,
+            r#"SELECT actual_streak(h.chat_id, h.updated_at, h.streak_current) AS "current!", h.streak_max AS max,
+                    chat_local_date(h.chat_id, h.updated_at) = chat_local_date(h.chat_id, current_timestamp) AS "treated_today!"
+                FROM Hemoroids h
+                JOIN Chats c ON h.chat_id = c.id
+                WHERE uid = $1 AND (c.chat_id = $2::bigint OR c.chat_instance = $2::text)"#,
+                uid.0 as i64, chat_id.value() as String)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't fetch the streak for {chat_id} and {uid}"))
+    }
+
+    pub async fn get_streaks_top(&self, chat_id: &ChatIdKind, offset: u32, limit: u16) -> anyhow::Result<Vec<StreakHolder>> {
+        sqlx::query_as!(StreakHolder,
+            r#"SELECT uid as owner_uid, name as owner_name, current AS "current!", max,
+                    ROW_NUMBER() OVER (ORDER BY max DESC, current DESC, name) AS position
+                FROM (
+                    SELECT uid, name, actual_streak(h.chat_id, h.updated_at, h.streak_current) AS current, h.streak_max AS max
+                    FROM Hemoroids h
+                    JOIN users using (uid)
+                    JOIN chats c ON c.id = h.chat_id
+                    WHERE (c.chat_id = $1::bigint OR c.chat_instance = $1::text) AND h.streak_max > 0
+                ) AS _
+                ORDER BY position
+                OFFSET $2 LIMIT $3"#,
+                chat_id.value() as String, offset as i64, limit as i32)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the streaks top of {chat_id} with offset = {offset} and limit = {limit}"))
+    }
+
+    pub async fn set_hod_winner(&self, chat_id: &ChatIdPartiality, user_id: UserId, improvement: LevelChange) -> anyhow::Result<Option<TreatmentResult>> {
         let internal_chat_id = self.chats.upsert_chat(chat_id).await?;
 
         let mut tx = self.pool.begin().await?;
         let uid = user_id.0 as i64;
-        // Note: improvement is negative since we want to reduce protrusion level
-        let new_protrusion_level = match Self::shrink_no_attempts_check_internal(&mut *tx, internal_chat_id, uid, -(improvement as i32)).await? {
+        // Note: the change is negative since we want to reduce protrusion level
+        let change = -improvement;
+        let new_protrusion_level = match Self::shrink_no_attempts_check_internal(&mut *tx, internal_chat_id, uid, change.total()).await? {
             Some(level) => level,
             None => return Ok(None)
         };
         Self::insert_to_hod_table(&mut tx, internal_chat_id, uid).await?;
+        record_change(&mut tx, internal_chat_id, uid, &change, ChangeSource::HemoroidOfDay, None).await?;
+        settle_perks(&mut tx, internal_chat_id, user_id, &change.settlements).await?;
         tx.commit().await?;
 
         let pos_in_top = self.get_position_in_top(internal_chat_id, uid).await?;
         Ok(Some(TreatmentResult { new_protrusion_level, pos_in_top }))
     }
 
-    pub async fn check_hemoroid(&self, chat_id: &ChatIdKind, user_id: UserId, level: u16) -> anyhow::Result<bool> {
+    pub async fn check_hemoroid(&self, chat_id: &ChatIdKind, user_id: UserId, max_level: Tenths) -> anyhow::Result<bool> {
         sqlx::query_scalar!(r#"SELECT protrusion_level <= $3 AS "enough!" FROM Hemoroids h
                 JOIN Chats c ON h.chat_id = c.id
                 WHERE (c.chat_id = $1::bigint OR c.chat_instance = $1::text)
                     AND uid = $2"#,
-                chat_id.value() as String, user_id.0 as i64, level as i32)
+                chat_id.value() as String, user_id.0 as i64, max_level.value())
             .fetch_optional(&self.pool)
             .map_ok(|opt| opt.unwrap_or(false))
             .await
-            .context(format!("couldn't check the hemorrhoid {chat_id}, {user_id} to have at most {level} cm"))
+            .context(format!("couldn't check the hemorrhoid {chat_id}, {user_id} to have at most {max_level:?}"))
     }
 
-    pub async fn penetrate(&self, chat_id: &ChatIdPartiality, top: UserId, bottom: UserId, top_damage: i32, bottom_damage: i32) -> anyhow::Result<(TreatmentResult, TreatmentResult)> {
+    /// Applies the damages, moves the bet from the loser to the winner and records the battle in the log in the same transaction.
+    /// The bets of the spectators on the challenge, if it has a key, are settled in it too, and so are used up
+    /// the shields of the participants who have got their damage softened by them.
+    /// The daily `limits` are checked and the reward is diminished only if they're given.
+    pub async fn penetrate(&self, chat_id: &ChatIdPartiality, battle: &NewBattle, bet: BattleBet, shielded: &[UserId],
+                           challenge: Option<&str>, limits: Option<&BattleLimitsConfig>) -> Result<PenetrationResult, PenetrationError> {
         let internal_chat_id = self.chats.upsert_chat(chat_id).await?;
+        let (top, bottom) = (battle.top, battle.bottom);
+        let (top_damage, bottom_damage) = (battle.top_damage, battle.bottom_damage);
 
         let mut tx = self.pool.begin().await?;
-        let level_top = Self::damage_for_one_user(&mut tx, internal_chat_id, top.0, top_damage).await?;
-        let level_bottom = Self::damage_for_one_user(&mut tx, internal_chat_id, bottom.0, bottom_damage).await?;
+        let levels = sqlx::query!("SELECT uid, protrusion_level FROM Hemoroids WHERE chat_id = $1 AND uid IN ($2, $3) ORDER BY uid FOR UPDATE",
+                internal_chat_id, top.0 as i64, bottom.0 as i64)
+            .fetch_all(&mut *tx)
+            .await
+            .context(format!("couldn't lock the hemorrhoids of {top} and {bottom} in {chat_id}"))?;
+        let affords_bet = |user_id: UserId| levels.iter()
+            .any(|h| h.uid == user_id.0 as i64 && h.protrusion_level <= bet.amount.value());
+        if !affords_bet(bet.initiator) {
+            return Err(PenetrationError::NotEnoughInitiator)
+        }
+        if bet.check_acceptor && !affords_bet(bet.acceptor) {
+            return Err(PenetrationError::NotEnoughAcceptor)
+        }
+
+        // every other battle of the participants in the chat waits for the locks above, so it's counted here once committed
+        let previous_pair_battles = match limits {
+            Some(limits) => {
+                let today = count_battles_today(&mut *tx, internal_chat_id, bet.initiator, bet.acceptor).await?;
+                if let Some(limit) = today.exceeded(limits) {
+                    return Err(PenetrationError::LimitReached(limit))
+                }
+                today.pair
+            }
+            None => 0
+        };
+        let reward_ratio = limits.map_or(1.0, |limits| limits.reward_ratio(previous_pair_battles));
+
+        for &user_id in shielded {
+            if !consume_shield_internal(&mut *tx, internal_chat_id, user_id).await? {
+                return Err(PenetrationError::Other(anyhow!("the shield of {user_id} in {chat_id} has been used up by another battle")))
+            }
+        }
+
+        // the level of the winner can't drop below zero, so only what is really removed from it moves to the loser
+        let winner_damage = if bet.winner == top { top_damage } else { bottom_damage };
+        let winner_level = levels.iter()
+            .find(|h| h.uid == bet.winner.0 as i64)
+            .map(|h| h.protrusion_level)
+            .unwrap_or_default();
+        let loser = bet.loser();
+        let award = ((bet.amount.value() as f32 * reward_ratio).round() as i32)
+            .min((winner_level + winner_damage).max(0));
+        let withheld = withhold_for_loan(&mut tx, internal_chat_id, bet.winner, award).await?;
+        let transfer = |user_id: UserId| match user_id {
+            uid if uid == bet.winner => withheld - award,
+            uid if uid == loser => award,
+            _ => 0
+        };
+        let level_top = Self::damage_for_one_user(&mut tx, internal_chat_id, top.0, top_damage + transfer(top)).await?;
+        let level_bottom = Self::damage_for_one_user(&mut tx, internal_chat_id, bottom.0, bottom_damage + transfer(bottom)).await?;
+        record_change(&mut tx, internal_chat_id, top.0 as i64, &top_damage.into(), ChangeSource::Battle, Some(bottom.to_string())).await?;
+        record_change(&mut tx, internal_chat_id, bottom.0 as i64, &bottom_damage.into(), ChangeSource::Battle, Some(top.to_string())).await?;
+        for (user_id, opponent) in [(bet.winner, loser), (loser, bet.winner)] {
+            let change = transfer(user_id);
+            if change != 0 {
+                record_change(&mut tx, internal_chat_id, user_id.0 as i64, &change.into(), ChangeSource::Battle, Some(opponent.to_string())).await?;
+            }
+        }
+        let side_bets = match challenge {
+            Some(challenge) => settle_side_bets(&mut tx, internal_chat_id, challenge, &bet).await?,
+            None => Vec::new()
+        };
+        let battle_id = record_battle(&mut tx, internal_chat_id, battle, award).await?;
         tx.commit().await?;
 
         let pos_top = self.get_position_in_top(internal_chat_id, top.0 as i64).await?;
@@ -194,7 +376,21 @@ impl Hemoroids {
             new_protrusion_level: level_bottom,
             pos_in_top: pos_bottom,
         };
-        Ok((top_result, bottom_result))
+        Ok(PenetrationResult { battle_id, top: top_result, bottom: bottom_result, award: award.into(), previous_pair_battles, withheld, side_bets })
+    }
+
+    /// Applies the damages of all the members of a team battle at once.
+    pub async fn penetrate_teams(&self, chat_id: &ChatIdPartiality, damages: &[(UserId, i32)]) -> anyhow::Result<Vec<i32>> {
+        let internal_chat_id = self.chats.upsert_chat(chat_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+        let mut levels = Vec::with_capacity(damages.len());
+        for (uid, damage) in damages {
+            levels.push(Self::damage_for_one_user(&mut tx, internal_chat_id, uid.0, *damage).await?);
+            record_change(&mut tx, internal_chat_id, uid.0 as i64, &(*damage).into(), ChangeSource::Battle, None).await?;
+        }
+        tx.commit().await?;
+        Ok(levels)
     }
 
     async fn damage_for_one_user(tx: &mut Transaction<'_, Postgres>, chat_id_internal: i64, user_id: u64, damage: i32) -> anyhow::Result<i32> {
@@ -257,3 +453,16 @@ impl Hemoroids {
         Ok(())
     }
 }
+
+/// Carries out the side effects of the perks which have contributed to the change applied in the transaction.
+async fn settle_perks(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, user_id: UserId, settlements: &[PerkSettlement]) -> anyhow::Result<()> {
+    for settlement in settlements {
+        match settlement {
+            PerkSettlement::LoanPayout(payout) => pay_off_loan(tx, chat_internal_id, user_id, *payout).await?,
+            PerkSettlement::ConsumeEffects(target) => {
+                Inventory::consume_effects_internal(&mut **tx, chat_internal_id, user_id.0 as i64, *target).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::ops::Neg;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use teloxide::types::UserId;
+use crate::repo::{ChatIdKind, EffectTarget};
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, strum_macros::Display)]
+#[sqlx(type_name = "change_source", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChangeSource {
+    Treatment,
+    Battle,
+    HemoroidOfDay,
+    Promo,
+    Loan,
+    Perk,
+    Shop,
+    Item,
+    Mercy,
+    Tournament,
+    SideBet,
+}
+
+/// A change of the protrusion level along with the parts contributed by perks,
+/// which are written to the ledger as separate entries.
+#[derive(Clone, Debug, Default)]
+pub struct LevelChange {
+    pub base: i32,
+    pub by_perks: HashMap<String, i32>,
+    /// Carried out in the same transaction as the change, only if it has been applied.
+    pub settlements: Vec<PerkSettlement>,
+}
+
+/// A side effect of a perk, which mustn't happen unless the change it has contributed to is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PerkSettlement {
+    /// Pays the active loan off by the amount.
+    LoanPayout(u16),
+    /// Takes one charge of every active effect of the target.
+    ConsumeEffects(EffectTarget),
+}
+
+impl LevelChange {
+    pub fn total(&self) -> i32 {
+        self.base + self.by_perks.values().sum::<i32>()
+    }
+}
+
+impl From<i32> for LevelChange {
+    fn from(base: i32) -> Self {
+        Self { base, by_perks: HashMap::default(), settlements: Vec::default() }
+    }
+}
+
+impl Neg for LevelChange {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            base: -self.base,
+            by_perks: self.by_perks.into_iter()
+                .map(|(perk, change)| (perk, -change))
+                .collect(),
+            settlements: self.settlements,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct HistoryRecord {
+    pub delta: i32,
+    pub source: ChangeSource,
+    pub reference_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+repository!(TreatmentHistory,
+    pub async fn get_page(&self, chat_id: &ChatIdKind, user_id: UserId, offset: u32, limit: u16) -> anyhow::Result<Vec<HistoryRecord>> {
+        sqlx::query_as!(HistoryRecord,
+            r#"SELECT delta, source AS "source: ChangeSource", reference_id, h.created_at FROM Treatment_History h
+                JOIN Chats c ON h.chat_id = c.id
+                WHERE (c.chat_id = $1::bigint OR c.chat_instance = $1::text) AND uid = $2
+                ORDER BY h.created_at DESC, h.id DESC
+                OFFSET $3 LIMIT $4"#,
+                chat_id.value() as String, user_id.0 as i64, offset as i64, limit as i32)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the history of {user_id} in {chat_id} with offset = {offset} and limit = {limit}"))
+    }
+);
+
+/// Must be called in the same transaction as the change of the protrusion level itself.
+pub(super) async fn record_change(tx: &mut Transaction<'_, Postgres>, chat_id_internal: i64, uid: i64,
+                                  change: &LevelChange, source: ChangeSource, reference_id: Option<String>) -> anyhow::Result<()> {
+    if change.base != 0 {
+        insert_record(tx, chat_id_internal, uid, change.base, source, reference_id).await?;
+    }
+    for (perk, delta) in change.by_perks.iter().filter(|(_, delta)| **delta != 0) {
+        insert_record(tx, chat_id_internal, uid, *delta, ChangeSource::Perk, Some(perk.clone())).await?;
+    }
+    Ok(())
+}
+
+async fn insert_record(tx: &mut Transaction<'_, Postgres>, chat_id_internal: i64, uid: i64,
+                       delta: i32, source: ChangeSource, reference_id: Option<String>) -> anyhow::Result<()> {
+    sqlx::query!("INSERT INTO Treatment_History (uid, chat_id, delta, source, reference_id) VALUES ($1, $2, $3, $4, $5)",
+            uid, chat_id_internal, delta, source as ChangeSource, reference_id)
+        .execute(&mut **tx)
+        .await
+        .context(format!("couldn't write a {source} change of {delta} to the history of {uid} in {chat_id_internal}"))?;
+    Ok(())
+}
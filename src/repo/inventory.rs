@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres, Transaction};
+use teloxide::types::UserId;
+use crate::config::{Consumable, ShopItem, ShopItemEffect};
+use crate::repo::ChatIdKind;
+use crate::repo::history::{record_change, ChangeSource};
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, strum_macros::Display)]
+#[sqlx(type_name = "effect_target", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EffectTarget {
+    Treatment,
+    Battle,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct InventoryItem {
+    pub item: String,
+    pub quantity: i32,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct ActiveEffect {
+    pub item: String,
+    pub target: EffectTarget,
+    pub value: i32,
+    pub charges_left: i32,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow, Debug, PartialEq)]
+pub struct ConsumedEffect {
+    pub item: String,
+    pub value: i32,
+}
+
+pub struct UseResult {
+    pub quantity_left: i32,
+    /// Present only if the item has changed the protrusion level.
+    pub new_protrusion_level: Option<i32>,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum UseError {
+    NoItem,
+    NoHemoroid,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for UseError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(Inventory, with_(chats)_(Chats),
+    pub async fn get_items(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<Vec<InventoryItem>> {
+        sqlx::query_as!(InventoryItem,
+            "SELECT item, quantity FROM Inventory
+                WHERE uid = $1 AND chat_id = (SELECT id FROM Chats WHERE chat_id = $2::bigint OR chat_instance = $2::text)
+                    AND quantity > 0
+                ORDER BY item",
+                user_id.0 as i64, chat_id.value() as String)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the inventory of {user_id} in {chat_id}"))
+    }
+,
+    pub async fn get_active_effects(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<Vec<ActiveEffect>> {
+        sqlx::query_as!(ActiveEffect,
+            r#"SELECT item, target AS "target: EffectTarget", value, charges_left, expires_at FROM Active_Effects
+                WHERE uid = $1 AND chat_id = (SELECT id FROM Chats WHERE chat_id = $2::bigint OR chat_instance = $2::text)
+                    AND charges_left > 0 AND expires_at > current_timestamp
+                ORDER BY expires_at"#,
+                user_id.0 as i64, chat_id.value() as String)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the active effects of {user_id} in {chat_id}"))
+    }
+,
+    /// Must be called in the same transaction as the payment for the items.
+    pub(super) async fn add(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, item: &str, quantity: i32) -> anyhow::Result<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO Inventory (uid, chat_id, item, quantity) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (uid, chat_id, item) DO UPDATE SET quantity = Inventory.quantity + EXCLUDED.quantity
+                RETURNING quantity",
+                uid, chat_internal_id, item, quantity)
+            .fetch_one(&mut **tx)
+            .await
+            .context(format!("couldn't add {quantity} of the {item} item to the inventory of {uid} in the chat with id = {chat_internal_id}"))
+    }
+,
+    /// Takes one item from the inventory and either applies its effect immediately or activates it.
+    pub async fn use_item(&self, chat_id: &ChatIdKind, user_id: UserId, item: &ShopItem) -> Result<UseResult, UseError> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let uid = user_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+
+        let quantity_left = sqlx::query_scalar!(
+            "UPDATE Inventory SET quantity = quantity - 1 WHERE uid = $1 AND chat_id = $2 AND item = $3 AND quantity > 0
+                RETURNING quantity",
+                uid, chat_internal_id, item.code)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't take the {} item from the inventory of {user_id} in {chat_id}", item.code))?
+            .ok_or(UseError::NoItem)?;
+
+        let new_protrusion_level = match item.effect {
+            ShopItemEffect::Shrink(value) => {
+                let level = Self::update_hemoroid(&mut tx, chat_internal_id, uid, -i32::from(value), 0).await?;
+                record_change(&mut tx, chat_internal_id, uid, &(-i32::from(value)).into(), ChangeSource::Item, Some(item.code.clone())).await?;
+                Some(level)
+            }
+            ShopItemEffect::Attempts(count) => {
+                Self::update_hemoroid(&mut tx, chat_internal_id, uid, 0, count.into()).await?;
+                None
+            }
+            ShopItemEffect::Shield(hours) => {
+                Self::add_shield(&mut tx, chat_internal_id, uid, hours).await?;
+                None
+            }
+            ShopItemEffect::Boost(consumable) => {
+                Self::activate(&mut tx, chat_internal_id, uid, &item.code, EffectTarget::Treatment, consumable).await?;
+                None
+            }
+            ShopItemEffect::Cushion(consumable) => {
+                Self::activate(&mut tx, chat_internal_id, uid, &item.code, EffectTarget::Battle, consumable).await?;
+                None
+            }
+        };
+        tx.commit().await?;
+
+        Ok(UseResult { quantity_left, new_protrusion_level })
+    }
+,
+    /// Takes one charge of every active effect of the target and returns their values.
+    pub async fn consume_effects(&self, chat_id: &ChatIdKind, user_id: UserId, target: EffectTarget) -> anyhow::Result<Vec<ConsumedEffect>> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        Self::consume_effects_internal(&self.pool, chat_internal_id, user_id.0 as i64, target).await
+    }
+,
+    /// The treatment effects must be consumed in the same transaction as the treatment they've contributed to.
+    pub(super) async fn consume_effects_internal<'c, E>(executor: E, chat_internal_id: i64, uid: i64, target: EffectTarget) -> anyhow::Result<Vec<ConsumedEffect>>
+    where E: Executor<'c, Database = Postgres>,
+    {
+        sqlx::query_as!(ConsumedEffect,
+            r#"WITH consumed AS (
+                UPDATE Active_Effects SET charges_left = charges_left - 1
+                WHERE uid = $1 AND chat_id = $2
+                    AND target = $3 AND charges_left > 0 AND expires_at > current_timestamp
+                RETURNING item, value
+            )
+            SELECT item AS "item!", sum(value)::integer AS "value!" FROM consumed GROUP BY item ORDER BY item"#,
+                uid, chat_internal_id, target as EffectTarget)
+            .fetch_all(executor)
+            .await
+            .context(format!("couldn't consume the {target} effects of {uid} in the chat with id = {chat_internal_id}"))
+    }
+,
+    async fn update_hemoroid(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, change: i32, attempts: i32) -> Result<i32, UseError> {
+        // a bonus attempt lets the update pass through the trigger without being counted as a treatment
+        sqlx::query_scalar!(
+            "UPDATE Hemoroids SET protrusion_level = protrusion_level + $3, bonus_attempts = bonus_attempts + 1 + $4
+                WHERE uid = $1 AND chat_id = $2
+                RETURNING protrusion_level",
+                uid, chat_internal_id, change, attempts)
+            .fetch_optional(&mut **tx)
+            .await
+            .context(format!("couldn't change the hemorrhoid of {uid} in the chat with id = {chat_internal_id} by {change} with {attempts} attempts"))?
+            .ok_or(UseError::NoHemoroid)
+    }
+,
+    async fn add_shield(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, hours: u16) -> anyhow::Result<()> {
+        // a shield from the inventory mustn't put /clench on cooldown
+        sqlx::query!(
+            "INSERT INTO Clench_Shields AS cs (uid, chat_id, attempted_at, shield_expires_at)
+                VALUES ($1, $2, '-infinity', current_timestamp + make_interval(hours => $3))
+                ON CONFLICT (uid, chat_id) DO UPDATE SET
+                    shield_expires_at = greatest(cs.shield_expires_at, current_timestamp) + make_interval(hours => $3)",
+                uid, chat_internal_id, hours as i32)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't add a shield for {hours} hours to {uid} in the chat with id = {chat_internal_id}"))?;
+        Ok(())
+    }
+,
+    async fn activate(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, item: &str,
+                      target: EffectTarget, consumable: Consumable) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO Active_Effects (uid, chat_id, item, target, value, charges_left, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6, current_timestamp + make_interval(hours => $7))",
+                uid, chat_internal_id, item, target as EffectTarget, consumable.value as i32, consumable.charges as i32, consumable.hours as i32)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't activate the {item} item of {uid} in the chat with id = {chat_internal_id}"))?;
+        Ok(())
+    }
+);
@@ -3,7 +3,8 @@ use sqlx::{Postgres, Transaction};
 use teloxide::types::UserId;
 
 use crate::config;
-use crate::repo::{ChatIdKind, Chats, Dicks, ensure_only_one_row_updated};
+use crate::repo::{ChatIdKind, Chats, Hemoroids, ensure_only_one_row_updated};
+use crate::repo::history::{record_change, ChangeSource};
 
 #[derive(Debug)]
 pub struct Loan {
@@ -63,11 +64,15 @@ impl Loans {
         let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
         let mut tx = self.pool.begin().await?;
 
-        match get_active_loan(&mut tx, user_id, chat_internal_id).await? {
-            Some(LoanEntity { id, .. }) => refinance_loan(&mut tx, id, value, self.payout_ratio).await?,
+        let loan_id = match get_active_loan(&mut tx, user_id, chat_internal_id).await? {
+            Some(LoanEntity { id, .. }) => {
+                refinance_loan(&mut tx, id, value, self.payout_ratio).await?;
+                id
+            },
             None => create_loan(&mut tx, chat_internal_id, uid, value, self.payout_ratio).await?
         };
-        Dicks::grow_no_attempts_check_internal(&mut *tx, chat_internal_id, uid, value.into()).await?;
+        Hemoroids::shrink_no_attempts_check_internal(&mut *tx, chat_internal_id, uid, value.into()).await?;
+        record_change(&mut tx, chat_internal_id, uid, &i32::from(value).into(), ChangeSource::Loan, Some(loan_id.to_string())).await?;
 
         tx.commit().await?;
         Ok(())
@@ -87,6 +92,34 @@ impl Loans {
     }
 }
 
+/// Pays off the active loan of the user, if any, with a part of the award and returns the withheld amount.
+pub(super) async fn withhold_for_loan(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, user_id: UserId, award: i32) -> anyhow::Result<i32> {
+    let Some(loan) = get_active_loan(tx, user_id, chat_internal_id).await? else {
+        return Ok(0)
+    };
+    let payout = ((loan.payout_ratio * award as f32).round() as i32).clamp(0, loan.debt);
+    if payout > 0 {
+        sqlx::query!("UPDATE Loans SET debt = debt - $2 WHERE id = $1",
+                loan.id, payout)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't pay {payout} for the loan with id = {}", loan.id))?;
+    }
+    Ok(payout)
+}
+
+/// Pays off the active loan of the user, if any, by the payout of the perk. The debt may have been reduced since
+/// the payout was calculated, so it never drops below zero.
+pub(super) async fn pay_off_loan(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, user_id: UserId, payout: u16) -> anyhow::Result<()> {
+    sqlx::query!("UPDATE Loans SET debt = greatest(debt - $3, 0)
+                    WHERE uid = $1 AND chat_id = $2 AND repaid_at IS NULL",
+                user_id.0 as i64, chat_internal_id, payout as i32)
+        .execute(&mut **tx)
+        .await
+        .context(format!("couldn't pay {payout} for the loan of {user_id} in the chat with id = {chat_internal_id}"))?;
+    Ok(())
+}
+
 async fn get_active_loan(tx: &mut Transaction<'_, Postgres>, uid: UserId, chat_internal_id: i64) -> anyhow::Result<Option<LoanEntity>> {
     let maybe_loan = sqlx::query_as!(LoanEntity,
             "SELECT id, debt, payout_ratio FROM loans
@@ -99,13 +132,12 @@ async fn get_active_loan(tx: &mut Transaction<'_, Postgres>, uid: UserId, chat_i
     Ok(maybe_loan)
 }
 
-async fn create_loan(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, value: u16, payout_ratio: f32) -> anyhow::Result<()> {
-    sqlx::query!("INSERT INTO Loans (chat_id, uid, debt, payout_ratio) VALUES ($1, $2, $3, $4)",
+async fn create_loan(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, uid: i64, value: u16, payout_ratio: f32) -> anyhow::Result<i32> {
+    sqlx::query_scalar!("INSERT INTO Loans (chat_id, uid, debt, payout_ratio) VALUES ($1, $2, $3, $4) RETURNING id",
                 chat_internal_id, uid, value as i32, payout_ratio)
-        .execute(&mut **tx)
+        .fetch_one(&mut **tx)
         .await
-        .map(ensure_only_one_row_updated)
-        .context(format!("couldn't create a loan for {chat_internal_id} and {uid} with value of {value}"))?
+        .context(format!("couldn't create a loan for {chat_internal_id} and {uid} with value of {value}"))
 }
 
 async fn refinance_loan(tx: &mut Transaction<'_, Postgres>, id: i32, value: u16, payout_ratio: f32) -> anyhow::Result<()> {
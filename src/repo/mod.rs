@@ -7,6 +7,18 @@ mod loans;
 mod pvpstats;
 mod stats;
 mod announcements;
+mod clench;
+mod history;
+mod achievements;
+mod shop;
+mod inventory;
+mod events;
+mod challenges;
+mod tournaments;
+mod teambattles;
+mod battles;
+mod duels;
+mod side_bets;
 
 #[cfg(test)]
 pub(crate) mod test;
@@ -24,6 +36,18 @@ pub use loans::*;
 pub use pvpstats::*;
 pub use stats::*;
 pub use announcements::*;
+pub use clench::*;
+pub use history::*;
+pub use achievements::*;
+pub use shop::*;
+pub use inventory::*;
+pub use events::*;
+pub use challenges::*;
+pub use tournaments::*;
+pub use teambattles::*;
+pub use battles::*;
+pub use duels::*;
+pub use side_bets::*;
 use crate::config;
 use crate::config::DatabaseConfig;
 
@@ -38,6 +62,18 @@ pub struct Repositories {
     pub announcements: Announcements,
     pub pvp_stats: BattleStatsRepo,
     pub personal_stats: PersonalStatsRepo,
+    pub clenches: Clenches,
+    pub history: TreatmentHistory,
+    pub achievements: Achievements,
+    pub shop: Shop,
+    pub inventory: Inventory,
+    pub events: Events,
+    pub challenges: BattleChallenges,
+    pub tournaments: Tournaments,
+    pub team_battles: TeamBattles,
+    pub battles: BattleLog,
+    pub duels: Duels,
+    pub side_bets: SideBets,
 }
 
 impl Repositories {
@@ -52,6 +88,18 @@ impl Repositories {
             announcements: Announcements::new(db_conn.clone(), config.announcements.clone()),
             pvp_stats: BattleStatsRepo::new(db_conn.clone(), config.features),
             personal_stats: PersonalStatsRepo::new(db_conn.clone()),
+            clenches: Clenches::new(db_conn.clone(), config.features),
+            history: TreatmentHistory::new(db_conn.clone()),
+            achievements: Achievements::new(db_conn.clone(), config.features),
+            shop: Shop::new(db_conn.clone(), config.features),
+            inventory: Inventory::new(db_conn.clone(), config.features),
+            events: Events::new(db_conn.clone()),
+            challenges: BattleChallenges::new(db_conn.clone()),
+            tournaments: Tournaments::new(db_conn.clone(), config.features),
+            team_battles: TeamBattles::new(db_conn.clone(), config.features),
+            battles: BattleLog::new(db_conn.clone(), config.features),
+            duels: Duels::new(db_conn.clone(), config.features),
+            side_bets: SideBets::new(db_conn.clone(), config.features),
         }
     }
 }
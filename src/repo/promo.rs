@@ -3,6 +3,7 @@ use anyhow::{anyhow, Context};
 use sqlx::{FromRow, Postgres};
 use teloxide::types::UserId;
 use crate::repository;
+use crate::repo::history::{record_change, ChangeSource};
 
 const PROMOCODE_ACTIVATIONS_PK: &str = "promo_code_activations_pkey";
 
@@ -55,7 +56,7 @@ repository!(Promo,
         let PromoCodeInfo { found_code, bonus_length } = Self::find_code_length_and_decr_capacity(&mut tx, code)
             .await?
             .ok_or(ActivationError::NoActivationsLeft)?;
-        let chats_affected = Self::grow_dicks(&mut tx, user_id, bonus_length).await?;
+        let chats_affected = Self::grow_hemoroids(&mut tx, user_id, bonus_length, &found_code).await?;
         if chats_affected < 1 {
             return Err(ActivationError::NoDicks)
         }
@@ -92,14 +93,17 @@ repository!(Promo,
             .context(format!("couldn't find a promo code length of {code}"))
     }
 ,
-    async fn grow_dicks(tx: &mut sqlx::Transaction<'_, Postgres>, user_id: UserId, bonus: i32) -> anyhow::Result<u64> {
-        let rows_affected = sqlx::query!("UPDATE Dicks SET bonus_attempts = (bonus_attempts + 1), length = (length + $2) WHERE uid = $1",
-                user_id.0 as i64, bonus)
-            .execute(&mut **tx)
+    async fn grow_hemoroids(tx: &mut sqlx::Transaction<'_, Postgres>, user_id: UserId, bonus: i32, code: &str) -> anyhow::Result<u64> {
+        let uid = user_id.0 as i64;
+        let affected_chats = sqlx::query_scalar!("UPDATE Hemoroids SET bonus_attempts = (bonus_attempts + 1), protrusion_level = (protrusion_level + $2) WHERE uid = $1 RETURNING chat_id",
+                uid, bonus)
+            .fetch_all(&mut **tx)
             .await
-            .context(format!("couldn't grow dicks of {user_id} by {bonus}"))?
-            .rows_affected();
-        Ok(rows_affected)
+            .context(format!("couldn't change hemorrhoids of {user_id} by {bonus}"))?;
+        for chat_id in affected_chats.iter() {
+            record_change(tx, *chat_id, uid, &bonus.into(), ChangeSource::Promo, Some(code.to_owned())).await?;
+        }
+        Ok(affected_chats.len() as u64)
     }
 ,
     async fn add_activation(tx: &mut sqlx::Transaction<'_, Postgres>, uid: UserId, code: &str, affected_chats: u64) -> anyhow::Result<()> {
@@ -1,12 +1,14 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use num_traits::{Num, ToPrimitive};
 use sqlx::{FromRow, Postgres, Transaction};
 use teloxide::types::UserId;
 
-use crate::repo::ChatIdKind;
+use crate::domain::{elo_delta, Tenths, INITIAL_RATING};
+use crate::repo::{ChatIdKind, UID};
+use crate::repo::history::{record_change, ChangeSource};
 use crate::repository;
 
-#[derive(Default, FromRow)]
+#[derive(FromRow)]
 struct UserStatsEntity {
     battles_total: i32,
     battles_won: i32,
@@ -14,12 +16,34 @@ struct UserStatsEntity {
     win_streak_current: i16,
     acquired_length: i32,
     lost_length: i32,
+    mercies_shown: i32,
+    team_battles_total: i32,
+    team_battles_won: i32,
+    rating: i32,
+}
+
+impl Default for UserStatsEntity {
+    fn default() -> Self {
+        Self {
+            battles_total: 0,
+            battles_won: 0,
+            win_streak_max: 0,
+            win_streak_current: 0,
+            acquired_length: 0,
+            lost_length: 0,
+            mercies_shown: 0,
+            team_battles_total: 0,
+            team_battles_won: 0,
+            rating: INITIAL_RATING,
+        }
+    }
 }
 
 #[derive(FromRow)]
 struct UserBattlesStatsEntity {
     battles_total: i32,
     battles_won: i32,
+    rating: i32,
 }
 
 pub trait WinRateAware {
@@ -37,6 +61,10 @@ pub struct UserStats {
     pub win_streak_current: u16,
     pub acquired_length: u32,
     pub lost_length: u32,
+    pub mercies_shown: u32,
+    pub team_battles_total: u32,
+    pub team_battles_won: u32,
+    pub rating: i32,
 }
 
 impl WinRateAware for UserStats {
@@ -54,6 +82,10 @@ impl From<UserStatsEntity> for UserStats {
             win_streak_current: value.win_streak_current.to_u16().expect("win_streak_current, fetched from the database, must not be negative"),
             acquired_length: value.acquired_length.to_u32().expect("acquired_length, fetched from the database, must not be negative"),
             lost_length: value.lost_length.to_u32().expect("lost_length, fetched from the database, must not be negative"),
+            mercies_shown: value.mercies_shown.to_u32().expect("mercies_shown, fetched from the database, must not be negative"),
+            team_battles_total: value.team_battles_total.to_u32().expect("team_battles_total, fetched from the database, must not be negative"),
+            team_battles_won: value.team_battles_won.to_u32().expect("team_battles_won, fetched from the database, must not be negative"),
+            rating: value.rating,
         }
     }
 }
@@ -63,6 +95,7 @@ type WinnerStats = UserStats;
 pub struct LoserStats {
     pub win_rate_percentage: f64,
     pub prev_win_streak: u16,
+    pub rating: i32,
 }
 
 impl WinRateAware for LoserStats {
@@ -75,7 +108,8 @@ impl LoserStats {
     fn new(user_battles_stats: UserBattlesStatsEntity, prev_win_streak: i16) -> Self {
         Self {
             win_rate_percentage: win_rate_percentage(user_battles_stats.battles_won, user_battles_stats.battles_total),
-            prev_win_streak: prev_win_streak.to_u16().expect("prev_win_streak, fetched from the database, must not be negative")
+            prev_win_streak: prev_win_streak.to_u16().expect("prev_win_streak, fetched from the database, must not be negative"),
+            rating: user_battles_stats.rating,
         }
     }
 }
@@ -83,20 +117,92 @@ impl LoserStats {
 pub struct BattleStats {
     pub winner: WinnerStats,
     pub loser: LoserStats,
+    /// How many rating points the winner took from the loser.
+    pub rating_change: i32,
+}
+
+#[derive(FromRow)]
+pub struct RatingHolder {
+    pub owner_uid: UID,
+    pub owner_name: String,
+    pub rating: i32,
+    pub battles_total: i32,
+    pub position: Option<i64>,
+}
+
+/// The battle the winner wants to show mercy for.
+pub struct MercyRequest {
+    pub battle_id: i64,
+    pub winner: UserId,
+    pub battle_timestamp: i64,
+}
+
+pub struct MercyResult {
+    pub loser: UserId,
+    /// How much has been returned to the loser, in tenths of a centimetre.
+    pub amount: i32,
+    pub loser_level: i32,
+    pub mercies_shown: i32,
+}
+
+/// The winner may return the award and the advantage to the loser, but not more than the loser has swelled.
+pub fn mercy_amount(award: i32, winner_damage: i32, loser_damage: i32) -> i32 {
+    award.max(0) + (loser_damage - winner_damage).clamp(0, loser_damage.max(0))
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum MercyError {
+    /// The battle isn't found or the loser has nothing to get back.
+    NoAdvantage,
+    AlreadyShown,
+    NoHemoroid,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for MercyError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
 }
 
 repository!(BattleStatsRepo, with_(chats)_(Chats),
-    pub async fn send_battle_result(&self, chat_id_kind: &ChatIdKind, winner_id: UserId, loser_id: UserId, bet: u16) -> anyhow::Result<BattleStats> {
+    pub async fn send_battle_result(&self, chat_id_kind: &ChatIdKind, winner_id: UserId, loser_id: UserId, bet: Tenths) -> anyhow::Result<BattleStats> {
+        let chat_id = self.chats.get_internal_id(chat_id_kind).await?;
+        let mut tx = self.pool.begin().await?;
+        let (winner_rating, loser_rating) = (
+            get_rating_for_update(&mut tx, chat_id, winner_id).await?,
+            get_rating_for_update(&mut tx, chat_id, loser_id).await?,
+        );
+        let rating_change = elo_delta(winner_rating, loser_rating);
+        let winner = update_winner(&mut tx, chat_id, winner_id, bet.value(), winner_rating + rating_change).await?;
+        let loser = update_loser(&mut tx, chat_id, loser_id, bet.value(), loser_rating - rating_change).await?;
+        tx.commit().await?;
+        Ok(BattleStats { winner, loser, rating_change })
+    }
+,
+    /// Team battles are counted separately and don't affect the win streaks of solo battles.
+    pub async fn send_team_battle_result(&self, chat_id_kind: &ChatIdKind, winners: &[UserId], losers: &[UserId]) -> anyhow::Result<()> {
         let chat_id = self.chats.get_internal_id(chat_id_kind).await?;
         let mut tx = self.pool.begin().await?;
-        let winner = update_winner(&mut tx, chat_id, winner_id, bet.into()).await?;
-        let loser = update_loser(&mut tx, chat_id, loser_id, bet.into()).await?;
+        let members = winners.iter().map(|uid| (uid, 1))
+            .chain(losers.iter().map(|uid| (uid, 0)));
+        for (uid, won) in members {
+            sqlx::query!("INSERT INTO Battle_Stats(uid, chat_id, team_battles_total, team_battles_won) VALUES ($1, $2, 1, $3) \
+                        ON CONFLICT (uid, chat_id) DO UPDATE SET \
+                            team_battles_total = Battle_Stats.team_battles_total + 1, \
+                            team_battles_won = Battle_Stats.team_battles_won + $3",
+                    uid.0 as i64, chat_id, won)
+                .execute(&mut *tx)
+                .await
+                .context(format!("couldn't update the team battle stats of {uid} in {chat_id_kind}"))?;
+        }
         tx.commit().await?;
-        Ok(BattleStats { winner, loser })
+        Ok(())
     }
 ,
     pub async fn get_stats(&self, chat_id_kind: &ChatIdKind, user_id: UserId) -> anyhow::Result<UserStats> {
-        sqlx::query_as!(UserStatsEntity, "SELECT battles_total, battles_won, win_streak_max, win_streak_current, acquired_length, lost_length FROM Battle_Stats \
+        sqlx::query_as!(UserStatsEntity, "SELECT battles_total, battles_won, win_streak_max, win_streak_current, acquired_length, lost_length, mercies_shown, team_battles_total, team_battles_won, rating FROM Battle_Stats \
                 WHERE chat_id = (SELECT id FROM Chats WHERE chat_id = $1::bigint OR chat_instance = $1::text) AND uid = $2",
             chat_id_kind.value() as String, user_id.0 as i64)
         .fetch_optional(&self.pool)
@@ -105,37 +211,143 @@ repository!(BattleStatsRepo, with_(chats)_(Chats),
         .map(UserStats::from)
         .context(format!("couldn't get the stats for {chat_id_kind} and {user_id}"))
     }
+,
+    /// Only those who have fought at least one solo battle are ranked.
+    pub async fn get_rating_top(&self, chat_id: &ChatIdKind, offset: u32, limit: u16) -> anyhow::Result<Vec<RatingHolder>> {
+        sqlx::query_as!(RatingHolder,
+            r#"SELECT uid AS owner_uid, name AS owner_name, rating, battles_total,
+                    ROW_NUMBER() OVER (ORDER BY rating DESC, battles_won DESC, name) AS position
+                FROM Battle_Stats s
+                JOIN Users USING (uid)
+                JOIN Chats c ON c.id = s.chat_id
+                WHERE (c.chat_id = $1::bigint OR c.chat_instance = $1::text) AND battles_total > 0
+                ORDER BY position
+                OFFSET $2 LIMIT $3"#,
+                chat_id.value() as String, offset as i64, limit as i32)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the rating top of {chat_id} with offset = {offset} and limit = {limit}"))
+    }
+,
+    /// Moves the award and the advantage of the battle from the winner back to the loser
+    /// and reverts the award in the statistics of both participants.
+    pub async fn show_mercy(&self, chat_id_kind: &ChatIdKind, req: &MercyRequest) -> Result<MercyResult, MercyError> {
+        let chat_id = self.chats.get_internal_id(chat_id_kind).await?;
+        let winner = req.winner.0 as i64;
+        let mut tx = self.pool.begin().await?;
+
+        let battle = sqlx::query!("SELECT top_uid, bottom_uid, top_damage, bottom_damage, award FROM Battles \
+                    WHERE id = $1 AND chat_id = $2 AND winner_uid = $3",
+                req.battle_id, chat_id, winner)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't find the battle {} won by {winner} in {chat_id_kind}", req.battle_id))?
+            .ok_or(MercyError::NoAdvantage)?;
+        let (loser, winner_damage, loser_damage) = if battle.top_uid == winner {
+            (battle.bottom_uid, battle.top_damage, battle.bottom_damage)
+        } else {
+            (battle.top_uid, battle.bottom_damage, battle.top_damage)
+        };
+        let amount = mercy_amount(battle.award, winner_damage, loser_damage);
+        if amount <= 0 {
+            return Err(MercyError::NoAdvantage)
+        }
+
+        let inserted = sqlx::query!("INSERT INTO Mercies (chat_id, winner_uid, loser_uid, battle_timestamp, amount, battle_id) VALUES ($1, $2, $3, $4, $5, $6) \
+                    ON CONFLICT DO NOTHING",
+                chat_id, winner, loser, req.battle_timestamp, amount, req.battle_id)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't save the mercy of {winner} to {loser} in {chat_id_kind}"))?
+            .rows_affected();
+        if inserted == 0 {
+            return Err(MercyError::AlreadyShown)
+        }
+
+        // in the same order as the battles lock them
+        sqlx::query!("SELECT uid FROM Hemoroids WHERE chat_id = $1 AND uid IN ($2, $3) ORDER BY uid FOR UPDATE",
+                chat_id, winner, loser)
+            .fetch_all(&mut *tx)
+            .await
+            .context(format!("couldn't lock the hemorrhoids of {winner} and {loser} in {chat_id_kind}"))?;
+        // a bonus attempt lets the update pass through the trigger without being counted as a treatment
+        let loser_level = sqlx::query_scalar!("UPDATE Hemoroids SET protrusion_level = protrusion_level - $3, bonus_attempts = bonus_attempts + 1 \
+                    WHERE chat_id = $1 AND uid = $2 \
+                    RETURNING protrusion_level",
+                chat_id, loser, amount)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't return {amount} to {loser} in {chat_id_kind}"))?
+            .ok_or(MercyError::NoHemoroid)?;
+        sqlx::query!("UPDATE Hemoroids SET protrusion_level = protrusion_level + $3, bonus_attempts = bonus_attempts + 1 \
+                    WHERE chat_id = $1 AND uid = $2",
+                chat_id, winner, amount)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't take {amount} back from {winner} in {chat_id_kind}"))?;
+        record_change(&mut tx, chat_id, loser, &(-amount).into(), ChangeSource::Mercy, Some(winner.to_string())).await?;
+        record_change(&mut tx, chat_id, winner, &amount.into(), ChangeSource::Mercy, Some(loser.to_string())).await?;
+
+        let award = battle.award;
+        let mercies_shown = sqlx::query_scalar!("UPDATE Battle_Stats SET mercies_shown = mercies_shown + 1, acquired_length = greatest(acquired_length - $3, 0) \
+                    WHERE chat_id = $1 AND uid = $2 \
+                    RETURNING mercies_shown",
+                chat_id, winner, award)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't update the stats of the merciful winner: {chat_id}, {winner}, {award}"))?
+            .unwrap_or_default();
+        sqlx::query!("UPDATE Battle_Stats SET lost_length = greatest(lost_length - $3, 0) WHERE chat_id = $1 AND uid = $2",
+                chat_id, loser, award)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't update the stats of the pardoned loser: {chat_id}, {loser}, {award}"))?;
+        tx.commit().await?;
+
+        Ok(MercyResult { loser: UserId(loser as u64), amount, loser_level, mercies_shown })
+    }
 );
 
-async fn update_winner(tx: &mut Transaction<'_, Postgres>, chat_id: i64, uid: UserId, bet: i32) -> anyhow::Result<WinnerStats> {
-    sqlx::query_as!(UserStatsEntity, "INSERT INTO Battle_Stats(uid, chat_id, battles_total, battles_won, win_streak_current, acquired_length) VALUES ($1, $2, 1, 1, 1, $3) \
+async fn get_rating_for_update(tx: &mut Transaction<'_, Postgres>, chat_id: i64, uid: UserId) -> anyhow::Result<i32> {
+    sqlx::query_scalar!("SELECT rating FROM Battle_Stats WHERE chat_id = $1 AND uid = $2 FOR UPDATE",
+            chat_id, uid.0 as i64)
+        .fetch_optional(&mut **tx)
+        .await
+        .map(|rating| rating.unwrap_or(INITIAL_RATING))
+        .context(format!("couldn't fetch the rating: {chat_id}, {uid}"))
+}
+
+async fn update_winner(tx: &mut Transaction<'_, Postgres>, chat_id: i64, uid: UserId, bet: i32, rating: i32) -> anyhow::Result<WinnerStats> {
+    sqlx::query_as!(UserStatsEntity, "INSERT INTO Battle_Stats(uid, chat_id, battles_total, battles_won, win_streak_current, acquired_length, rating) VALUES ($1, $2, 1, 1, 1, $3, $4) \
                 ON CONFLICT (uid, chat_id) DO UPDATE SET \
                     battles_total = Battle_Stats.battles_total + 1, \
                     battles_won = Battle_Stats.battles_won + 1, \
                     win_streak_current = Battle_Stats.win_streak_current + 1, \
-                    acquired_length = Battle_Stats.acquired_length + $3 \
-                RETURNING battles_total, battles_won, win_streak_max, win_streak_current, acquired_length, lost_length",
-            uid.0 as i64, chat_id, bet)
+                    acquired_length = Battle_Stats.acquired_length + $3, \
+                    rating = $4 \
+                RETURNING battles_total, battles_won, win_streak_max, win_streak_current, acquired_length, lost_length, mercies_shown, team_battles_total, team_battles_won, rating",
+            uid.0 as i64, chat_id, bet, rating)
         .fetch_one(&mut **tx)
         .await
         .map(WinnerStats::from)
         .context(format!("couldn't update the stats of the winner: {chat_id}, {uid}, {bet}"))
 }
 
-async fn update_loser(tx: &mut Transaction<'_, Postgres>, chat_id: i64, uid: UserId, bet: i32) -> anyhow::Result<LoserStats> {
+async fn update_loser(tx: &mut Transaction<'_, Postgres>, chat_id: i64, uid: UserId, bet: i32, rating: i32) -> anyhow::Result<LoserStats> {
     let uid = uid.0 as i64;
     let prev_win_streak = sqlx::query_scalar!("SELECT win_streak_current FROM Battle_Stats WHERE chat_id = $1 AND uid = $2", chat_id, uid)
         .fetch_optional(&mut **tx)
         .await
         .context(format!("couldn't fetch the win streak of the loser: {chat_id}, {uid}"))?
         .unwrap_or(0);
-    let win_rate = sqlx::query_as!(UserBattlesStatsEntity, "INSERT INTO Battle_Stats(uid, chat_id, battles_total, battles_won, win_streak_current, lost_length) VALUES ($1, $2, 1, 0, 0, $3) \
+    let win_rate = sqlx::query_as!(UserBattlesStatsEntity, "INSERT INTO Battle_Stats(uid, chat_id, battles_total, battles_won, win_streak_current, lost_length, rating) VALUES ($1, $2, 1, 0, 0, $3, $4) \
                 ON CONFLICT (uid, chat_id) DO UPDATE SET \
                     battles_total = Battle_Stats.battles_total + 1, \
                     win_streak_current = 0, \
-                    lost_length = Battle_Stats.lost_length + $3 \
-                RETURNING battles_total, battles_won",
-            uid, chat_id, bet)
+                    lost_length = Battle_Stats.lost_length + $3, \
+                    rating = $4 \
+                RETURNING battles_total, battles_won, rating",
+            uid, chat_id, bet, rating)
         .fetch_one(&mut **tx)
         .await
         .context(format!("couldn't update the stats of the loser: {chat_id}, {uid}, {bet}"))?;
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Context};
+use teloxide::types::UserId;
+use crate::config::{ShopCurrency, ShopItem};
+use crate::repo::{ChatIdKind, Inventory};
+use crate::repo::history::{record_change, ChangeSource};
+use crate::repository;
+
+pub struct PurchaseResult {
+    pub new_protrusion_level: i32,
+    pub coins_left: i32,
+    /// The number of such items in the inventory after the purchase.
+    pub quantity: i32,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PurchaseError {
+    NotEnoughCoins,
+    NoHemoroid,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for PurchaseError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(Shop, with_(chats)_(Chats),
+    pub async fn get_coins(&self, chat_id: &ChatIdKind, user_id: UserId) -> anyhow::Result<i32> {
+        sqlx::query_scalar!(
+            "SELECT coins FROM Wallets
+                WHERE uid = $1 AND chat_id = (SELECT id FROM Chats WHERE chat_id = $2::bigint OR chat_instance = $2::text)",
+                user_id.0 as i64, chat_id.value() as String)
+            .fetch_optional(&self.pool)
+            .await
+            .map(Option::unwrap_or_default)
+            .context(format!("couldn't get the coins of {user_id} in {chat_id}"))
+    }
+,
+    pub async fn award_coins(&self, chat_id: &ChatIdKind, user_id: UserId, coins: u16) -> anyhow::Result<i32> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        sqlx::query_scalar!(
+            "INSERT INTO Wallets (uid, chat_id, coins) VALUES ($1, $2, $3)
+                ON CONFLICT (uid, chat_id) DO UPDATE SET coins = Wallets.coins + EXCLUDED.coins
+                RETURNING coins",
+                user_id.0 as i64, chat_internal_id, coins as i32)
+            .fetch_one(&self.pool)
+            .await
+            .context(format!("couldn't award {coins} coins to {user_id} in {chat_id}"))
+    }
+,
+    /// Charges the price and puts the item into the inventory in one transaction.
+    pub async fn buy(&self, chat_id: &ChatIdKind, user_id: UserId, item: &ShopItem) -> Result<PurchaseResult, PurchaseError> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let uid = user_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+
+        let (price_coins, price_level) = match item.currency {
+            ShopCurrency::Coins => (item.price as i32, 0),
+            ShopCurrency::Cm => (0, item.price as i32),
+        };
+        let coins_left = sqlx::query_scalar!(
+            "UPDATE Wallets SET coins = coins - $3 WHERE uid = $1 AND chat_id = $2 AND coins >= $3 RETURNING coins",
+                uid, chat_internal_id, price_coins)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't charge {price_coins} coins from {user_id} in {chat_id}"))?;
+        let coins_left = match coins_left {
+            Some(coins) => coins,
+            None if price_coins == 0 => 0,
+            None => return Err(PurchaseError::NotEnoughCoins)
+        };
+
+        // a bonus attempt lets the update pass through the trigger without being counted as a treatment
+        let new_protrusion_level = sqlx::query_scalar!(
+            "UPDATE Hemoroids SET protrusion_level = protrusion_level + $3, bonus_attempts = bonus_attempts + 1
+                WHERE uid = $1 AND chat_id = $2
+                RETURNING protrusion_level",
+                uid, chat_internal_id, price_level)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't charge {price_level} tenths of a centimetre from {user_id} in {chat_id}"))?
+            .ok_or(PurchaseError::NoHemoroid)?;
+
+        record_change(&mut tx, chat_internal_id, uid, &price_level.into(), ChangeSource::Shop, Some(item.code.clone())).await?;
+        let quantity = Inventory::add(&mut tx, chat_internal_id, uid, &item.code, 1).await?;
+        sqlx::query!("INSERT INTO Purchases (uid, chat_id, item, price_coins, price_level) VALUES ($1, $2, $3, $4, $5)",
+                uid, chat_internal_id, item.code, price_coins, price_level)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't save the purchase of the {} item by {user_id} in {chat_id}", item.code))?;
+        tx.commit().await?;
+
+        Ok(PurchaseResult { new_protrusion_level, coins_left, quantity })
+    }
+);
@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Context};
+use sqlx::{Postgres, Transaction};
+use teloxide::types::UserId;
+use crate::domain::{Tenths, Username};
+use crate::repo::{BattleBet, ChatIdKind};
+use crate::repo::history::{record_change, ChangeSource};
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, Eq, strum_macros::Display, strum_macros::EnumString)]
+#[sqlx(type_name = "side_bet_side", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum BetSide {
+    Initiator,
+    Acceptor,
+}
+
+/// The total amounts bet on both sides, in tenths of a centimetre.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SideBetPools {
+    pub initiator: i64,
+    pub acceptor: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SideBetPayout {
+    pub uid: UserId,
+    pub name: Username,
+    /// In tenths of a centimetre: the winnings of the spectator, or the negative amount of the lost bet.
+    pub payout: i32,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum SideBetError {
+    AlreadyPlaced,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for SideBetError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(SideBets, with_(chats)_(Chats),
+    /// The balance of the spectator must be checked in advance, nothing is held until the battle is fought.
+    pub async fn place(&self, chat_id: &ChatIdKind, challenge: &str, user_id: UserId, side: BetSide, amount: Tenths) -> Result<SideBetPools, SideBetError> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let placed = sqlx::query!("INSERT INTO Side_Bets (chat_id, challenge, uid, side, amount) VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT DO NOTHING",
+                chat_internal_id, challenge, user_id.0 as i64, side as BetSide, amount.value())
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't place a side bet of {user_id} on the {side} of the challenge {challenge} in {chat_id}"))?
+            .rows_affected() > 0;
+        if !placed {
+            return Err(SideBetError::AlreadyPlaced)
+        }
+        let pools = sqlx::query!(r#"SELECT
+                    coalesce(sum(amount) FILTER (WHERE side = 'initiator'), 0) AS "initiator!",
+                    coalesce(sum(amount) FILTER (WHERE side = 'acceptor'), 0) AS "acceptor!"
+                FROM Side_Bets WHERE chat_id = $1 AND challenge = $2"#,
+                chat_internal_id, challenge)
+            .fetch_one(&self.pool)
+            .await
+            .context(format!("couldn't get the side bet pools of the challenge {challenge} in {chat_id}"))?;
+        Ok(SideBetPools { initiator: pools.initiator, acceptor: pools.acceptor })
+    }
+);
+
+/// Settles the bets of the spectators pari-mutuel: the pool of the losing side is split among the winners
+/// in proportion to their bets. The bets of the participants themselves are returned.
+pub(super) async fn settle_side_bets(tx: &mut Transaction<'_, Postgres>, chat_internal_id: i64, challenge: &str,
+                                     bet: &BattleBet) -> anyhow::Result<Vec<SideBetPayout>> {
+    let bets = sqlx::query!(r#"SELECT s.uid, u.name, s.side AS "side: BetSide", s.amount FROM Side_Bets s
+                JOIN Users u ON u.uid = s.uid
+                WHERE s.chat_id = $1 AND s.challenge = $2 AND s.payout IS NULL
+                FOR UPDATE OF s"#,
+            chat_internal_id, challenge)
+        .fetch_all(&mut **tx)
+        .await
+        .context(format!("couldn't lock the side bets of the challenge {challenge}"))?;
+    let winning_side = if bet.winner == bet.initiator { BetSide::Initiator } else { BetSide::Acceptor };
+    let stakes = bets.iter()
+        .map(|row| {
+            let uid = UserId(row.uid as u64);
+            let participant = uid == bet.initiator || uid == bet.acceptor;
+            (row.side, if participant { 0 } else { row.amount })
+        })
+        .collect::<Vec<_>>();
+    let payouts = pari_mutuel(&stakes, winning_side);
+
+    let mut results = Vec::with_capacity(bets.len());
+    for (row, payout) in bets.into_iter().zip(payouts) {
+        sqlx::query!("UPDATE Side_Bets SET payout = $4 WHERE chat_id = $1 AND challenge = $2 AND uid = $3",
+                chat_internal_id, challenge, row.uid, payout)
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't settle the side bet of {} on the challenge {challenge}", row.uid))?;
+        if payout != 0 {
+            // the spectator may have left the game since the bet was placed;
+            // a bonus attempt lets the update pass through the trigger if the spectator has been treated today
+            let updated = sqlx::query!("UPDATE Hemoroids SET protrusion_level = protrusion_level - $3, bonus_attempts = bonus_attempts + 1
+                        WHERE chat_id = $1 AND uid = $2",
+                    chat_internal_id, row.uid, payout)
+                .execute(&mut **tx)
+                .await
+                .context(format!("couldn't pay {payout} out to {} for the side bet", row.uid))?
+                .rows_affected() > 0;
+            if updated {
+                record_change(tx, chat_internal_id, row.uid, &(-payout).into(), ChangeSource::SideBet, Some(bet.winner.to_string())).await?;
+            }
+        }
+        results.push(SideBetPayout {
+            uid: UserId(row.uid as u64),
+            name: Username::new(row.name),
+            payout,
+        });
+    }
+    Ok(results)
+}
+
+/// Returns the payouts in the order of the stakes. If nobody has bet on the winner, the losers keep their bets.
+fn pari_mutuel(stakes: &[(BetSide, i32)], winning_side: BetSide) -> Vec<i32> {
+    let pool_of = |side: BetSide| stakes.iter()
+        .filter(|(s, _)| *s == side)
+        .map(|(_, amount)| i64::from(*amount))
+        .sum::<i64>();
+    let won = pool_of(winning_side);
+    let lost = stakes.iter().map(|(_, amount)| i64::from(*amount)).sum::<i64>() - won;
+    stakes.iter()
+        .map(|(side, amount)| match (*side == winning_side, won) {
+            (_, 0) => 0,
+            (true, won) => (lost * i64::from(*amount) / won) as i32,
+            (false, _) => -amount,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pari_mutuel, BetSide::*};
+
+    #[test]
+    fn test_pari_mutuel() {
+        let stakes = [(Initiator, 10), (Initiator, 30), (Acceptor, 20), (Acceptor, 0)];
+        assert_eq!(pari_mutuel(&stakes, Initiator), vec![5, 15, -20, 0]);
+        assert_eq!(pari_mutuel(&stakes, Acceptor), vec![-10, -30, 40, 0]);
+
+        // the remainder is lost
+        assert_eq!(pari_mutuel(&[(Initiator, 10), (Initiator, 20), (Acceptor, 10)], Initiator), vec![3, 6, -10]);
+
+        // nobody to pay
+        assert_eq!(pari_mutuel(&[(Acceptor, 10)], Initiator), vec![0]);
+        assert_eq!(pari_mutuel(&[(Initiator, 10)], Initiator), vec![0]);
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{anyhow, Context};
+use teloxide::types::UserId;
+use crate::domain::Username;
+use crate::repo::ChatIdKind;
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq, Eq, strum_macros::Display, strum_macros::EnumString)]
+#[sqlx(type_name = "battle_team", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Team {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone)]
+pub struct TeamMember {
+    pub uid: UserId,
+    pub name: Username,
+    pub team: Team,
+}
+
+#[derive(Debug, Clone)]
+pub struct TeamLobby {
+    pub id: i32,
+    pub initiator: UserId,
+    pub team_size: u16,
+    pub bet: u16,
+    pub members: Vec<TeamMember>,
+    /// Both teams are full and the battle must be fought.
+    pub started: bool,
+}
+
+impl TeamLobby {
+    pub fn team(&self, team: Team) -> impl Iterator<Item = &TeamMember> {
+        self.members.iter().filter(move |m| m.team == team)
+    }
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TeamJoinError {
+    Started,
+    AlreadyJoined,
+    TeamFull,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for TeamJoinError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(TeamBattles, with_(chats)_(Chats),
+    /// Creates a lobby where the initiator is the first member of the team A.
+    pub async fn create(&self, chat_id: &ChatIdKind, initiator: UserId, team_size: u16, bet: u16) -> anyhow::Result<TeamLobby> {
+        let chat_internal_id = self.chats.get_internal_id(chat_id).await?;
+        let uid = initiator.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let battle_id = sqlx::query_scalar!("INSERT INTO Team_Battles (chat_id, initiator_uid, team_size, bet) VALUES ($1, $2, $3, $4) RETURNING id",
+                chat_internal_id, uid, team_size as i16, bet as i16)
+            .fetch_one(&mut *tx)
+            .await
+            .context(format!("couldn't create a team battle of {initiator} in {chat_id}"))?;
+        sqlx::query!("INSERT INTO Team_Battle_Members (battle_id, uid, team) VALUES ($1, $2, $3)",
+                battle_id, uid, Team::A as Team)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't add the initiator {initiator} to the team battle {battle_id}"))?;
+        tx.commit().await?;
+
+        self.get_lobby(battle_id).await?
+            .ok_or(anyhow!("the team battle {battle_id} must exist right after its creation"))
+    }
+,
+    pub async fn get_lobby(&self, battle_id: i32) -> anyhow::Result<Option<TeamLobby>> {
+        let Some(battle) = sqlx::query!("SELECT initiator_uid, team_size, bet, started_at IS NOT NULL AS \"started!\" FROM Team_Battles WHERE id = $1",
+                battle_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't get the team battle {battle_id}"))? else {
+            return Ok(None)
+        };
+        let members = sqlx::query!(r#"SELECT m.uid, u.name, m.team AS "team: Team" FROM Team_Battle_Members m
+                JOIN Users u USING (uid)
+                WHERE m.battle_id = $1
+                ORDER BY m.joined_at"#,
+                battle_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the members of the team battle {battle_id}"))?
+            .into_iter()
+            .map(|row| TeamMember {
+                uid: UserId(row.uid as u64),
+                name: Username::new(row.name),
+                team: row.team,
+            })
+            .collect();
+        Ok(Some(TeamLobby {
+            id: battle_id,
+            initiator: UserId(battle.initiator_uid as u64),
+            team_size: battle.team_size as u16,
+            bet: battle.bet as u16,
+            members,
+            started: battle.started,
+        }))
+    }
+,
+    /// Adds a member to the team and starts the battle if it was the last free place.
+    pub async fn join(&self, battle_id: i32, user_id: UserId, team: Team) -> Result<TeamLobby, TeamJoinError> {
+        let uid = user_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let battle = sqlx::query!("SELECT team_size, started_at IS NOT NULL AS \"started!\" FROM Team_Battles WHERE id = $1 FOR UPDATE",
+                battle_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't lock the team battle {battle_id}"))?
+            .filter(|battle| !battle.started)
+            .ok_or(TeamJoinError::Started)?;
+        let members = sqlx::query!(r#"SELECT uid, team AS "team: Team" FROM Team_Battle_Members WHERE battle_id = $1"#,
+                battle_id)
+            .fetch_all(&mut *tx)
+            .await
+            .context(format!("couldn't get the members of the team battle {battle_id}"))?;
+        if members.iter().any(|m| m.uid == uid) {
+            return Err(TeamJoinError::AlreadyJoined)
+        }
+        if members.iter().filter(|m| m.team == team).count() >= battle.team_size as usize {
+            return Err(TeamJoinError::TeamFull)
+        }
+
+        sqlx::query!("INSERT INTO Team_Battle_Members (battle_id, uid, team) VALUES ($1, $2, $3)",
+                battle_id, uid, team as Team)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't add {user_id} to the team {team} of the battle {battle_id}"))?;
+        if members.len() + 1 == 2 * battle.team_size as usize {
+            sqlx::query!("UPDATE Team_Battles SET started_at = current_timestamp WHERE id = $1",
+                    battle_id)
+                .execute(&mut *tx)
+                .await
+                .context(format!("couldn't start the team battle {battle_id}"))?;
+        }
+        tx.commit().await?;
+
+        let lobby = self.get_lobby(battle_id).await?
+            .ok_or(anyhow!("the team battle {battle_id} disappeared"))?;
+        Ok(lobby)
+    }
+);
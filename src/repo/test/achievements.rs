@@ -0,0 +1,45 @@
+use sqlx::{Pool, Postgres};
+use crate::repo;
+use crate::repo::ChatIdPartiality;
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let achievements = repo::Achievements::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+    let unlocked = achievements.check(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't check the achievements");
+    assert!(unlocked.is_empty());
+
+    cure(&db).await;
+    let unlocked = achievements.check(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't check the achievements");
+    assert_eq!(unlocked, vec!["zero_level".to_owned()]);
+
+    // the same achievement cannot be unlocked twice
+    let unlocked = achievements.check(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't check the achievements");
+    assert!(unlocked.is_empty());
+
+    let all = achievements.get_all(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the achievements");
+    assert_eq!(all.len(), 7);
+    assert_eq!(all[0].code, "zero_level");
+    assert!(all[0].unlocked_at.is_some());
+    assert!(all[1..].iter().all(|a| a.unlocked_at.is_none()));
+}
+
+async fn cure(db: &Pool<Postgres>) {
+    // a bonus attempt lets the update pass through the trigger
+    sqlx::query!("UPDATE Hemoroids SET protrusion_level = 0, bonus_attempts = bonus_attempts + 1")
+        .execute(db)
+        .await.expect("couldn't cure the hemorrhoid");
+}
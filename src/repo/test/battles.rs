@@ -0,0 +1,103 @@
+use teloxide::types::UserId;
+use crate::repo;
+use crate::repo::{ChatIdPartiality, DailyBattles, NewBattle};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let battles = repo::BattleLog::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let opponent = UserId(UID as u64 + 1);
+    users.create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create an opponent");
+    for uid in [USER_ID, opponent] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+
+    let empty = battles.get_page(&CHAT_ID_KIND, USER_ID, 0, 10)
+        .await.expect("couldn't fetch the empty battle log");
+    assert!(empty.is_empty());
+
+    let first = NewBattle {
+        initiator: USER_ID,
+        top: USER_ID,
+        bottom: opponent,
+        top_damage: 15,
+        bottom_damage: -5,
+        bet: 3,
+        winner: opponent,
+        rematch_of: None,
+    };
+    let second = NewBattle {
+        initiator: opponent,
+        top: opponent,
+        bottom: USER_ID,
+        top_damage: 20,
+        bottom_damage: 10,
+        bet: 1,
+        winner: USER_ID,
+        rematch_of: None,
+    };
+    for battle in [&first, &second] {
+        battles.record(&CHAT_ID_KIND, battle)
+            .await.expect("couldn't record a battle");
+    }
+
+    let records = battles.get_page(&CHAT_ID_KIND, USER_ID, 0, 10)
+        .await.expect("couldn't fetch the battle log");
+    assert_eq!(records.len(), 2);
+    let latest = &records[0];
+    assert_eq!(latest.initiator, opponent);
+    assert_eq!(latest.top.uid, opponent);
+    assert_eq!(&*latest.top.name, "Opponent");
+    assert_eq!(latest.top.damage, 20);
+    assert_eq!(latest.bottom.uid, USER_ID);
+    assert_eq!(latest.bottom.damage, 10);
+    assert_eq!(latest.bet, 1);
+    assert_eq!(latest.winner, USER_ID);
+    assert_eq!(records[1].bottom.damage, -5);
+
+    let page = battles.get_page(&CHAT_ID_KIND, opponent, 1, 10)
+        .await.expect("couldn't fetch the second page");
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].winner, opponent);
+
+    let first_id = battles.record(&CHAT_ID_KIND, &first)
+        .await.expect("couldn't record the first battle of the series");
+    let no_series = battles.get_series_score(first_id)
+        .await.expect("couldn't get the score of a single battle");
+    assert_eq!(no_series, None);
+
+    let rematch = NewBattle { rematch_of: Some(first_id), ..second.clone() };
+    let rematch_id = battles.record(&CHAT_ID_KIND, &rematch)
+        .await.expect("couldn't record the rematch");
+    let double = NewBattle { rematch_of: Some(rematch_id), bet: 2, ..second.clone() };
+    let double_id = battles.record(&CHAT_ID_KIND, &double)
+        .await.expect("couldn't record the second rematch");
+    let score = battles.get_series_score(double_id)
+        .await.expect("couldn't get the series score")
+        .expect("the series must have a score");
+    assert_eq!(score.rematch, 2);
+    assert_eq!(score.wins_of(USER_ID), 2);
+    assert_eq!(score.wins_of(opponent), 1);
+    assert_eq!(battles.get_series_score(first_id).await.expect("couldn't get the series score by the first battle"), Some(score));
+
+    let latest = battles.get_page(&CHAT_ID_KIND, USER_ID, 0, 1)
+        .await.expect("couldn't fetch the latest battle");
+    assert_eq!(latest[0].id, double_id);
+    assert_eq!(latest[0].rematch, 2);
+
+    let today = battles.count_today(&CHAT_ID_KIND, USER_ID, opponent)
+        .await.expect("couldn't count the battles of today");
+    assert_eq!(today, DailyBattles { pair: 5, first: 5, second: 5 });
+    let stranger = UserId(UID as u64 + 2);
+    let today = battles.count_today(&CHAT_ID_KIND, stranger, opponent)
+        .await.expect("couldn't count the battles of a stranger");
+    assert_eq!(today, DailyBattles { pair: 0, first: 0, second: 5 });
+}
@@ -0,0 +1,41 @@
+use teloxide::types::{ChatId, MessageId};
+use crate::domain::LanguageCode;
+use crate::repo;
+use crate::repo::ChallengeMessage;
+use crate::repo::test::start_postgres;
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    let challenges = repo::BattleChallenges::new(db.clone());
+    let lang_code = LanguageCode::new("fa".to_owned());
+    let accepted = ChallengeMessage::Chat(ChatId(-100), MessageId(1));
+    let stale = ChallengeMessage::Chat(ChatId(-100), MessageId(2));
+    let inline = ChallengeMessage::Inline("inline-id".to_owned());
+
+    for message in [&accepted, &stale, &inline] {
+        challenges.register(message, &lang_code)
+            .await.expect("couldn't register a challenge");
+    }
+    challenges.remove(&accepted)
+        .await.expect("couldn't remove the accepted challenge");
+
+    let expired = challenges.take_expired(60)
+        .await.expect("couldn't take the expired challenges");
+    assert!(expired.is_empty());
+
+    sqlx::query!("UPDATE Battle_Challenges SET created_at = current_timestamp - interval '2 hours'")
+        .execute(&db)
+        .await.expect("couldn't move the challenges back");
+    let expired = challenges.take_expired(60)
+        .await.expect("couldn't take the expired challenges");
+    assert_eq!(expired.len(), 2);
+    assert!(expired.iter().any(|c| c.message == stale));
+    assert!(expired.iter().any(|c| c.message == inline));
+    assert_eq!(expired[0].lang_code.as_str(), "fa");
+
+    // every message is cleaned up only once
+    let expired = challenges.take_expired(60)
+        .await.expect("couldn't take the expired challenges");
+    assert!(expired.is_empty());
+}
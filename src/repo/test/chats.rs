@@ -95,3 +95,46 @@ async fn check_chat(chats: &repo::Chats, chat_id: ChatId, inst: String) {
     assert_eq!(chat.as_ref().unwrap().chat_id.unwrap(), chat_id.0);
     assert_eq!(chat.unwrap().chat_instance.unwrap(), inst);
 }
+
+#[tokio::test]
+async fn timezone() {
+    let (_container, db) = start_postgres().await;
+    let chats = repo::Chats::new(db.clone(), Default::default());
+    let chat_id = ChatIdPartiality::Specific(ChatId(CHAT_ID).into());
+
+    let tz = chats.get_timezone(&chat_id.kind())
+        .await.expect("couldn't get the default timezone");
+    assert_eq!(tz, repo::DEFAULT_TIMEZONE);
+
+    let res = chats.set_timezone(&chat_id, "Mars/Olympus_Mons")
+        .await.expect("couldn't try to set an unknown timezone");
+    assert!(res.is_none());
+
+    let res = chats.set_timezone(&chat_id, "asia/tehran")
+        .await.expect("couldn't set the timezone");
+    assert_eq!(res.as_deref(), Some("Asia/Tehran"));
+
+    let tz = chats.get_timezone(&chat_id.kind())
+        .await.expect("couldn't get the timezone");
+    assert_eq!(tz, "Asia/Tehran");
+    let offset = chats.get_utc_offset(&chat_id.kind())
+        .await.expect("couldn't get the UTC offset");
+    assert_eq!(offset.local_minus_utc(), 3 * 3600 + 30 * 60);
+}
+
+#[tokio::test]
+async fn battle_rules() {
+    let (_container, db) = start_postgres().await;
+    let chats = repo::Chats::new(db.clone(), Default::default());
+    let chat_id = ChatIdPartiality::Specific(ChatId(CHAT_ID).into());
+
+    let rules = chats.get_battle_rules(&chat_id.kind())
+        .await.expect("couldn't get the default battle rules");
+    assert_eq!(rules, repo::BattleRuleSet::TopBottom);
+
+    chats.set_battle_rules(&chat_id, repo::BattleRuleSet::Classic)
+        .await.expect("couldn't set the battle rules");
+    let rules = chats.get_battle_rules(&chat_id.kind())
+        .await.expect("couldn't get the battle rules");
+    assert_eq!(rules, repo::BattleRuleSet::Classic);
+}
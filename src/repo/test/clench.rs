@@ -0,0 +1,58 @@
+use teloxide::prelude::UserId;
+use crate::repo;
+use crate::repo::ClenchAttempt;
+use crate::repo::test::dicks::{create_dick, create_user};
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    create_dick(&db).await; // to create a chat
+
+    let clenches = repo::Clenches::new(db.clone(), Default::default());
+    let user_id = UserId(UID as u64);
+
+    let no_shield = clenches.consume_shield(&CHAT_ID_KIND, user_id)
+        .await.expect("couldn't consume a non-existent shield");
+    assert!(!no_shield);
+
+    let attempt = clenches.attempt(&CHAT_ID_KIND, user_id, true, 1, 1)
+        .await.expect("couldn't clench");
+    assert!(matches!(attempt, ClenchAttempt::Shielded { .. }));
+
+    let attempt = clenches.attempt(&CHAT_ID_KIND, user_id, true, 1, 1)
+        .await.expect("couldn't clench for the second time");
+    assert!(matches!(attempt, ClenchAttempt::OnCooldown { .. }));
+
+    // without a cooldown, a failed attempt must not take away the active shield
+    let attempt = clenches.attempt(&CHAT_ID_KIND, user_id, false, 1, 0)
+        .await.expect("couldn't clench without a cooldown");
+    assert!(matches!(attempt, ClenchAttempt::Failed));
+
+    // checking the shield doesn't use it up
+    for _ in 0..2 {
+        let shielded = clenches.has_shield(&CHAT_ID_KIND, user_id)
+            .await.expect("couldn't check the shield");
+        assert!(shielded);
+    }
+
+    let shielded = clenches.consume_shield(&CHAT_ID_KIND, user_id)
+        .await.expect("couldn't consume the shield");
+    assert!(shielded);
+
+    let shielded = clenches.consume_shield(&CHAT_ID_KIND, user_id)
+        .await.expect("couldn't consume the shield for the second time");
+    assert!(!shielded);
+    let shielded = clenches.has_shield(&CHAT_ID_KIND, user_id)
+        .await.expect("couldn't check the consumed shield");
+    assert!(!shielded);
+
+    // an expired shield must not be consumed
+    let attempt = clenches.attempt(&CHAT_ID_KIND, user_id, true, 0, 0)
+        .await.expect("couldn't clench with an instant expiration");
+    assert!(matches!(attempt, ClenchAttempt::Shielded { .. }));
+    let shielded = clenches.consume_shield(&CHAT_ID_KIND, user_id)
+        .await.expect("couldn't consume the expired shield");
+    assert!(!shielded);
+}
@@ -0,0 +1,96 @@
+use teloxide::types::{ChatId, MessageId, UserId};
+use crate::domain::LanguageCode;
+use crate::repo;
+use crate::repo::{ChatIdPartiality, DuelAction, DuelError, DuelStatus, NewDuel, RoundOutcome};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let duels = repo::Duels::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let opponent = UserId(UID as u64 + 1);
+    let stranger = UserId(UID as u64 + 2);
+    users.create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create an opponent");
+    users.create_or_update(stranger, "Stranger")
+        .await.expect("couldn't create a stranger");
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+
+    let params = NewDuel {
+        initiator: USER_ID,
+        lang_code: LanguageCode::new("en".to_owned()),
+        best_of: 3,
+        bet: 5,
+    };
+    let duel = duels.create(ChatId(CHAT_ID), &params)
+        .await.expect("couldn't create a duel");
+    assert_eq!(duel.status, DuelStatus::Open);
+    assert_eq!(duel.wins_needed(), 2);
+    assert!(duel.acceptor.is_none());
+    duels.set_message(duel.id, MessageId(100))
+        .await.expect("couldn't set the message");
+
+    let result = duels.accept(duel.id, USER_ID).await;
+    assert!(matches!(result, Err(DuelError::OwnDuel)));
+    let result = duels.choose(duel.id, USER_ID, DuelAction::Push).await;
+    assert!(matches!(result, Err(DuelError::Finished)));
+    let duel = duels.accept(duel.id, opponent)
+        .await.expect("couldn't accept the duel");
+    assert_eq!(duel.status, DuelStatus::Running);
+    assert_eq!(duel.round, 1);
+    assert_eq!(duel.message_id, Some(MessageId(100)));
+    assert_eq!(duel.acceptor.as_ref().map(|p| p.uid), Some(opponent));
+    let result = duels.accept(duel.id, stranger).await;
+    assert!(matches!(result, Err(DuelError::Started)));
+
+    let result = duels.choose(duel.id, stranger, DuelAction::Push).await;
+    assert!(matches!(result, Err(DuelError::NotParticipant)));
+    let duel = duels.choose(duel.id, USER_ID, DuelAction::Push)
+        .await.expect("couldn't choose an action");
+    assert_eq!(duel.initiator_action, Some(DuelAction::Push));
+    assert_eq!(duel.acceptor_action, None);
+    let result = duels.choose(duel.id, USER_ID, DuelAction::Dodge).await;
+    assert!(matches!(result, Err(DuelError::AlreadyChosen)));
+    let duel = duels.choose(duel.id, opponent, DuelAction::Clench)
+        .await.expect("couldn't choose an action");
+    assert_eq!(duel.acceptor_action, Some(DuelAction::Clench));
+
+    let win = RoundOutcome { winner: Some(USER_ID), initiator_damage: 0, acceptor_damage: 10 };
+    let duel = duels.complete_round(duel.id, 1, &win)
+        .await.expect("couldn't complete the round")
+        .expect("the round must be completed");
+    assert_eq!(duel.round, 2);
+    assert_eq!((duel.initiator_wins, duel.acceptor_wins), (1, 0));
+    assert_eq!((duel.initiator_action, duel.acceptor_action), (None, None));
+    assert_eq!(duel.rounds.len(), 1);
+    assert_eq!(duel.rounds[0].initiator_action, Some(DuelAction::Push));
+    assert_eq!(duel.rounds[0].winner, Some(USER_ID));
+    let repeated = duels.complete_round(duel.id, 1, &win)
+        .await.expect("couldn't complete the round again");
+    assert!(repeated.is_none());
+
+    // the acceptor didn't respond in time
+    duels.choose(duel.id, USER_ID, DuelAction::Dodge)
+        .await.expect("couldn't choose an action");
+    let timed_out = duels.get_timed_out(0)
+        .await.expect("couldn't get the timed out duels");
+    assert_eq!(timed_out.len(), 1);
+    let duel = duels.complete_round(duel.id, 2, &win)
+        .await.expect("couldn't complete the round")
+        .expect("the round must be completed");
+    assert_eq!(duel.status, DuelStatus::Finished);
+    assert_eq!((duel.initiator_wins, duel.acceptor_wins), (2, 0));
+    assert_eq!((duel.initiator_damage, duel.acceptor_damage), (0, 20));
+    assert_eq!(duel.rounds[1].acceptor_action, None);
+    assert!(!duels.cancel(duel.id, duel.round).await.expect("couldn't cancel the duel"));
+
+    let timed_out = duels.get_timed_out(0)
+        .await.expect("couldn't get the timed out duels");
+    assert!(timed_out.is_empty());
+}
@@ -0,0 +1,47 @@
+use crate::domain::LanguageCode;
+use crate::repo;
+use crate::repo::EventModifiers;
+use crate::repo::test::start_postgres;
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    let events = repo::Events::new(db.clone());
+    let en = LanguageCode::new("en".to_owned());
+    let fa = LanguageCode::new("fa-IR".to_owned());
+
+    let modifiers = events.get_modifiers()
+        .await.expect("couldn't get the modifiers");
+    assert_eq!(modifiers, EventModifiers::default());
+    let active = events.get_active(&en)
+        .await.expect("couldn't get the active events");
+    assert!(active.is_empty());
+
+    sqlx::query!("INSERT INTO Events (code, starts_at, ends_at, shrink_multiplier, battle_damage_multiplier) VALUES
+            ('double_shrink', current_timestamp - interval '1 day', current_timestamp + interval '1 day', 2.0, 1.0),
+            ('soft_battles', current_timestamp - interval '1 day', current_timestamp + interval '2 days', 1.0, 0.5),
+            ('finished', current_timestamp - interval '2 days', current_timestamp - interval '1 day', 10.0, 10.0)")
+        .execute(&db)
+        .await.expect("couldn't create the events");
+    sqlx::query!("INSERT INTO Event_Titles (event_id, language, title)
+            SELECT id, 'en', 'Double Shrink Week' FROM Events WHERE code = 'double_shrink'
+            UNION ALL
+            SELECT id, 'fa', 'هفته‌ی کوچک‌شدن دوبرابر' FROM Events WHERE code = 'double_shrink'")
+        .execute(&db)
+        .await.expect("couldn't create the titles");
+
+    let modifiers = events.get_modifiers()
+        .await.expect("couldn't get the modifiers");
+    assert_eq!(modifiers, EventModifiers { shrink: 2.0, battle_damage: 0.5, hod_bonus: 1.0 });
+
+    let active = events.get_active(&fa)
+        .await.expect("couldn't get the active events");
+    assert_eq!(active.len(), 2);
+    assert_eq!(active[0].title, "هفته‌ی کوچک‌شدن دوبرابر");
+    // the code is used if there are no titles at all
+    assert_eq!(active[1].title, "soft_battles");
+
+    let active = events.get_active(&en)
+        .await.expect("couldn't get the active events");
+    assert_eq!(active[0].title, "Double Shrink Week");
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use teloxide::prelude::UserId;
+use crate::repo;
+use crate::repo::{ChangeSource, ChatIdPartiality, LevelChange};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let history = repo::TreatmentHistory::new(db.clone());
+    let user_id = UserId(UID as u64);
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+
+    let empty = history.get_page(&CHAT_ID_KIND, user_id, 0, 10)
+        .await.expect("couldn't fetch the empty history");
+    assert!(empty.is_empty());
+
+    let initial = hemoroids.create_or_shrink(user_id, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+    assert!((25..=50).contains(&initial.new_protrusion_level), "the initial level must be between 2.5 and 5.0 cm");
+
+    let improvement = LevelChange {
+        base: 2,
+        by_perks: HashMap::from([("test-perk".to_owned(), 1)]),
+        ..Default::default()
+    };
+    let hod = hemoroids.set_hod_winner(&chat_id, user_id, improvement)
+        .await.expect("couldn't elect a winner")
+        .expect("the winner must have a hemorrhoid");
+    assert_eq!(hod.new_protrusion_level, initial.new_protrusion_level - 3);
+
+    let records = history.get_page(&CHAT_ID_KIND, user_id, 0, 10)
+        .await.expect("couldn't fetch the history");
+    assert_eq!(records.len(), 3);
+    assert_eq!(records.iter().map(|r| r.delta).sum::<i32>(), hod.new_protrusion_level);
+    assert!(records.iter().any(|r| r.source == ChangeSource::HemoroidOfDay && r.delta == -2));
+    assert!(records.iter().any(|r| r.source == ChangeSource::Perk && r.delta == -1
+        && r.reference_id.as_deref() == Some("test-perk")));
+    assert_eq!(records.last().expect("the initial record must be present").source, ChangeSource::Treatment);
+
+    let second_page = history.get_page(&CHAT_ID_KIND, user_id, 2, 10)
+        .await.expect("couldn't fetch the second page of the history");
+    assert_eq!(second_page.len(), 1);
+}
@@ -0,0 +1,100 @@
+use sqlx::{Pool, Postgres};
+use crate::config::{Consumable, ShopCurrency, ShopItem, ShopItemEffect};
+use crate::repo;
+use crate::repo::{ChatIdPartiality, ConsumedEffect, EffectTarget, LevelChange, PerkSettlement};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let shop = repo::Shop::new(db.clone(), Default::default());
+    let inventory = repo::Inventory::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let cream = ShopItem {
+        code: "cream".to_owned(),
+        currency: ShopCurrency::Coins,
+        price: 1,
+        effect: ShopItemEffect::Boost(Consumable { value: 5, charges: 2, hours: 72 }),
+    };
+    let cushion = ShopItem {
+        code: "cushion".to_owned(),
+        currency: ShopCurrency::Coins,
+        price: 1,
+        effect: ShopItemEffect::Cushion(Consumable { value: 10, charges: 1, hours: 48 }),
+    };
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+    shop.award_coins(&CHAT_ID_KIND, USER_ID, 2)
+        .await.expect("couldn't award coins");
+    for item in [&cream, &cushion] {
+        shop.buy(&CHAT_ID_KIND, USER_ID, item)
+            .await.expect("couldn't buy an item");
+        inventory.use_item(&CHAT_ID_KIND, USER_ID, item)
+            .await.expect("couldn't use an item");
+    }
+
+    let effects = inventory.get_active_effects(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the active effects");
+    assert_eq!(effects.len(), 2);
+
+    // the hemorrhoid has been already treated today, so the cream must be kept for tomorrow
+    let treatment = LevelChange {
+        settlements: vec![PerkSettlement::ConsumeEffects(EffectTarget::Treatment)],
+        ..(-5).into()
+    };
+    assert!(hemoroids.create_or_shrink(USER_ID, &chat_id, treatment.clone()).await.is_err());
+    let effects = inventory.get_active_effects(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the active effects");
+    let cream_charges = effects.iter()
+        .find(|effect| effect.item == "cream")
+        .map(|effect| effect.charges_left);
+    assert_eq!(cream_charges, Some(2));
+
+    let consumed = inventory.consume_effects(&CHAT_ID_KIND, USER_ID, EffectTarget::Battle)
+        .await.expect("couldn't consume the battle effects");
+    assert_eq!(consumed, vec![ConsumedEffect { item: "cushion".to_owned(), value: 10 }]);
+    let consumed = inventory.consume_effects(&CHAT_ID_KIND, USER_ID, EffectTarget::Battle)
+        .await.expect("couldn't consume the battle effects");
+    assert!(consumed.is_empty());
+
+    move_last_treatment_back(&db).await;
+    hemoroids.create_or_shrink(USER_ID, &chat_id, treatment)
+        .await.expect("couldn't treat the hemorrhoid");
+
+    let effects = inventory.get_active_effects(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the active effects");
+    assert_eq!(effects.len(), 1);
+    assert_eq!(effects[0].charges_left, 1);
+
+    // the Hemorrhoid of the Day takes the last charge of the cream of the winner
+    let improvement = LevelChange {
+        settlements: vec![PerkSettlement::ConsumeEffects(EffectTarget::Treatment)],
+        ..5.into()
+    };
+    hemoroids.set_hod_winner(&chat_id, USER_ID, improvement)
+        .await.expect("couldn't elect the winner")
+        .expect("the winner must have a hemorrhoid");
+    let effects = inventory.get_active_effects(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the active effects");
+    assert!(effects.is_empty());
+
+    // expired effects are ignored even if they have charges left
+    sqlx::query!("UPDATE Active_Effects SET charges_left = 1, expires_at = current_timestamp - interval '1 hour'")
+        .execute(&db)
+        .await.expect("couldn't expire the effects");
+    let consumed = inventory.consume_effects(&CHAT_ID_KIND, USER_ID, EffectTarget::Treatment)
+        .await.expect("couldn't consume the treatment effects");
+    assert!(consumed.is_empty());
+}
+
+async fn move_last_treatment_back(db: &Pool<Postgres>) {
+    // a bonus attempt lets the update pass through the trigger without affecting the streak
+    sqlx::query!("UPDATE Hemoroids SET updated_at = updated_at - interval '1 day', bonus_attempts = bonus_attempts + 1")
+        .execute(db)
+        .await.expect("couldn't move the last treatment back");
+}
@@ -0,0 +1,74 @@
+use teloxide::types::UserId;
+use crate::domain::Tenths;
+use crate::repo;
+use crate::repo::{BattleBet, ChatIdPartiality, MercyError, MercyRequest};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::penetrate::new_battle;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let loser = UserId(UID as u64 + 1);
+    repo::Users::new(db.clone()).create_or_update(loser, "Loser")
+        .await.expect("couldn't create the loser");
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let pvp_stats = repo::BattleStatsRepo::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+
+    for uid in [USER_ID, loser] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+    sqlx::query!("UPDATE Hemoroids SET protrusion_level = 20, bonus_attempts = bonus_attempts + 1")
+        .execute(&db)
+        .await.expect("couldn't reset the levels");
+    let bet = BattleBet {
+        initiator: USER_ID,
+        acceptor: loser,
+        winner: USER_ID,
+        amount: Tenths::from_cm(3),
+        check_acceptor: false,
+    };
+    let battle = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, loser, 2, 12), bet, &[], None, None)
+        .await.expect("couldn't penetrate");
+    assert_eq!(battle.award, Tenths::from(22));
+    pvp_stats.send_battle_result(&CHAT_ID_KIND, USER_ID, loser, battle.award)
+        .await.expect("couldn't send the result of the battle");
+
+    // only the winner of the battle may show mercy
+    let result = pvp_stats.show_mercy(&CHAT_ID_KIND, &MercyRequest { battle_id: battle.battle_id, winner: loser, battle_timestamp: 42 }).await;
+    assert!(matches!(result, Err(MercyError::NoAdvantage)));
+
+    let request = MercyRequest {
+        battle_id: battle.battle_id,
+        winner: USER_ID,
+        battle_timestamp: 42,
+    };
+    let result = pvp_stats.show_mercy(&CHAT_ID_KIND, &request)
+        .await.expect("couldn't show mercy");
+    // both the award and the extra swelling of the loser are returned
+    assert_eq!(result.loser, loser);
+    assert_eq!(result.amount, 22 + 12 - 2);
+    assert_eq!(result.loser_level, battle.bottom.new_protrusion_level - 32);
+    assert_eq!(result.mercies_shown, 1);
+    let winner_level = hemoroids.fetch_hemoroid(USER_ID, &CHAT_ID_KIND)
+        .await.expect("couldn't fetch the hemorrhoid of the winner")
+        .expect("the hemorrhoid of the winner must exist")
+        .protrusion_level;
+    assert_eq!(winner_level, battle.top.new_protrusion_level + 32);
+
+    // mercy is shown only once per battle
+    let result = pvp_stats.show_mercy(&CHAT_ID_KIND, &MercyRequest { battle_timestamp: 43, ..request }).await;
+    assert!(matches!(result, Err(MercyError::AlreadyShown)));
+
+    let winner_stats = pvp_stats.get_stats(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the stats of the winner");
+    assert_eq!(winner_stats.mercies_shown, 1);
+    assert_eq!(winner_stats.acquired_length, 0);
+    let loser_stats = pvp_stats.get_stats(&CHAT_ID_KIND, loser)
+        .await.expect("couldn't get the stats of the loser");
+    assert_eq!(loser_stats.lost_length, 0);
+}
@@ -7,6 +7,21 @@ mod loans;
 mod pvpstats;
 mod stats;
 mod announcements;
+mod clench;
+mod history;
+mod streaks;
+mod achievements;
+mod shop;
+mod inventory;
+mod events;
+mod mercy;
+mod challenges;
+mod tournaments;
+mod teambattles;
+mod battles;
+mod penetrate;
+mod duels;
+mod side_bets;
 
 use std::str::FromStr;
 use reqwest::Url;
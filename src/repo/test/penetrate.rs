@@ -0,0 +1,192 @@
+use teloxide::types::UserId;
+use crate::{config, repo};
+use crate::domain::Tenths;
+use crate::config::BattleLimitsConfig;
+use crate::repo::{BattleBet, ChatIdPartiality, DailyLimit, NewBattle, PenetrationError};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_bet_transfer() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let battles = repo::BattleLog::new(db.clone(), Default::default());
+    let loans = repo::Loans::new(db.clone(), &config::AppConfig {
+        loan_payout_ratio: 0.5,
+        ..Default::default()
+    });
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let opponent = UserId(UID as u64 + 1);
+    users.create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create an opponent");
+    for uid in [USER_ID, opponent] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+    loans.borrow(USER_ID, &CHAT_ID_KIND, 10)
+        .await.expect("couldn't borrow");
+    sqlx::query!("UPDATE Hemoroids SET protrusion_level = 20, bonus_attempts = bonus_attempts + 1")
+        .execute(&db)
+        .await.expect("couldn't reset the levels");
+
+    let bet = BattleBet {
+        initiator: USER_ID,
+        acceptor: opponent,
+        winner: USER_ID,
+        amount: Tenths::from_cm(3),
+        check_acceptor: false,
+    };
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 5, 12), bet, &[], None, None)
+        .await.expect("couldn't penetrate");
+    // a half of the bet is withheld, but not more than the debt
+    assert_eq!(result.withheld, 10);
+    // the winner has only 25 to lose, so only that much is moved
+    assert_eq!(result.award, Tenths::from(25));
+    assert_eq!(result.top.new_protrusion_level, 20 + 5 - 25 + 10);
+    assert_eq!(result.bottom.new_protrusion_level, 20 + 12 + 25);
+    let logged = battles.get_page(&CHAT_ID_KIND, USER_ID, 0, 10)
+        .await.expect("couldn't fetch the battle log");
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].id, result.battle_id);
+    assert_eq!(logged[0].bottom.damage, 12);
+    let loan = loans.get_active_loan(USER_ID, &CHAT_ID_KIND)
+        .await.expect("couldn't fetch the loan");
+    assert!(loan.is_none(), "the loan must be repaid");
+
+    let clenches = repo::Clenches::new(db.clone(), Default::default());
+    clenches.attempt(&CHAT_ID_KIND, opponent, true, 1, 0)
+        .await.expect("couldn't clench");
+
+    // the levels have changed since the challenge was checked
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 5, 12), BattleBet { initiator: opponent, acceptor: USER_ID, ..bet }, &[opponent], None, None).await;
+    assert!(matches!(result, Err(PenetrationError::NotEnoughInitiator)));
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 5, 12), BattleBet { check_acceptor: true, ..bet }, &[opponent], None, None).await;
+    assert!(matches!(result, Err(PenetrationError::NotEnoughAcceptor)));
+
+    let level = hemoroids.fetch_hemoroid(opponent, &CHAT_ID_KIND)
+        .await.expect("couldn't fetch the hemorrhoid")
+        .expect("the hemorrhoid must exist")
+        .protrusion_level;
+    assert_eq!(level, 57, "failed battles must not change the levels");
+    let logged = battles.get_page(&CHAT_ID_KIND, USER_ID, 0, 10)
+        .await.expect("couldn't fetch the battle log");
+    assert_eq!(logged.len(), 1, "failed battles must not be logged");
+    let shielded = clenches.has_shield(&CHAT_ID_KIND, opponent)
+        .await.expect("couldn't check the shield");
+    assert!(shielded, "failed battles must not use up the shields");
+
+    // the reward of a repeated battle moves only a part of the bet
+    let limits = BattleLimitsConfig { pair_reward_decay: 0.25, ..Default::default() };
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 0, 0), bet, &[opponent], None, Some(&limits))
+        .await.expect("couldn't penetrate with a diminished reward");
+    assert_eq!(result.previous_pair_battles, 1);
+    assert_eq!(result.award, Tenths::from(8));
+    assert_eq!(result.top.new_protrusion_level, 10 - 8);
+    assert_eq!(result.bottom.new_protrusion_level, 57 + 8);
+    let shielded = clenches.has_shield(&CHAT_ID_KIND, opponent)
+        .await.expect("couldn't check the used shield");
+    assert!(!shielded);
+
+    // the shield has been used up already
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 0, 0), bet, &[opponent], None, None).await;
+    assert!(matches!(result, Err(PenetrationError::Other(_))));
+}
+
+#[tokio::test]
+async fn test_winner_level_floor() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let opponent = UserId(UID as u64 + 1);
+    users.create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create an opponent");
+    for uid in [USER_ID, opponent] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+    sqlx::query!("UPDATE Hemoroids SET protrusion_level = $1, bonus_attempts = bonus_attempts + 1 WHERE uid = $2",
+            30, UID)
+        .execute(&db)
+        .await.expect("couldn't reset the level");
+
+    let bet = BattleBet {
+        initiator: USER_ID,
+        acceptor: opponent,
+        winner: USER_ID,
+        amount: Tenths::from_cm(3),
+        check_acceptor: false,
+    };
+    // the winner without a loan keeps the swelling of the battle
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 4, 12), bet, &[], None, None)
+        .await.expect("couldn't penetrate");
+    assert_eq!(result.award, Tenths::from(30));
+    assert_eq!(result.withheld, 0);
+    assert_eq!(result.top.new_protrusion_level, 30 + 4 - 30);
+    assert_eq!(result.bottom.new_protrusion_level, 12 + 30);
+
+    // but the level doesn't drop below zero, and the loser gets only what has been removed
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 2, 0), bet, &[], None, None)
+        .await.expect("couldn't penetrate for the second time");
+    assert_eq!(result.award, Tenths::from(6));
+    assert_eq!(result.top.new_protrusion_level, 0);
+    assert_eq!(result.bottom.new_protrusion_level, 42 + 6);
+
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 0, 0), bet, &[], None, None)
+        .await.expect("couldn't penetrate at zero");
+    assert_eq!(result.award, Tenths::from(0));
+    assert_eq!(result.top.new_protrusion_level, 0);
+    assert_eq!(result.bottom.new_protrusion_level, 48);
+}
+
+#[tokio::test]
+async fn test_daily_limits() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let opponent = UserId(UID as u64 + 1);
+    users.create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create an opponent");
+    for uid in [USER_ID, opponent] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+
+    let bet = BattleBet {
+        initiator: USER_ID,
+        acceptor: opponent,
+        winner: USER_ID,
+        amount: Tenths::from_cm(3),
+        check_acceptor: false,
+    };
+    let limits = BattleLimitsConfig { pair_daily: 1, user_daily: 5, pair_reward_decay: 1.0 };
+    let battle = new_battle(USER_ID, opponent, 0, 0);
+    // the concurrent challenges of the same pair must be counted one after another
+    let (first, second) = tokio::join!(
+        hemoroids.penetrate(&chat_id, &battle, bet, &[], None, Some(&limits)),
+        hemoroids.penetrate(&chat_id, &battle, BattleBet { initiator: opponent, acceptor: USER_ID, ..bet }, &[], None, Some(&limits)),
+    );
+    let results = [first, second];
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    assert!(results.iter().any(|result| matches!(result, Err(PenetrationError::LimitReached(DailyLimit::Pair(1))))));
+
+    // battles without limits aren't counted against them
+    hemoroids.penetrate(&chat_id, &battle, bet, &[], None, None)
+        .await.expect("couldn't penetrate without limits");
+}
+
+/// The top initiates the battle and wins it.
+pub fn new_battle(top: UserId, bottom: UserId, top_damage: i32, bottom_damage: i32) -> NewBattle {
+    NewBattle {
+        initiator: top,
+        top, bottom, top_damage, bottom_damage,
+        bet: 3,
+        winner: top,
+        rematch_of: None,
+    }
+}
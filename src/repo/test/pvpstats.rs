@@ -1,4 +1,5 @@
 use teloxide::prelude::{ChatId, UserId};
+use crate::domain::{Tenths, INITIAL_RATING};
 use crate::repo;
 use crate::repo::{ChatIdKind, ChatIdPartiality, WinRateAware};
 use crate::repo::test::dicks::{create_dick, create_user, create_user_and_dick_2};
@@ -10,7 +11,7 @@ async fn test_all() {
     let pvp_stats = repo::BattleStatsRepo::new(db.clone(), Default::default());
 
     let chat_id = ChatIdKind::ID(ChatId(CHAT_ID));
-    let bet = 42;
+    let bet = Tenths::from_cm(42);
 
     // create user and dick #1
     create_user(&db).await;
@@ -28,6 +29,7 @@ async fn test_all() {
     assert_eq!(stats.win_streak_current, 0);
     assert_eq!(stats.win_streak_max, 0);
     assert_eq!(stats.win_rate_percentage(), 0.00);
+    assert_eq!(stats.rating, INITIAL_RATING);
     
     // send the first battle to check insertions
     let stats = pvp_stats.send_battle_result(&chat_id, uid_1, uid_2, bet).await
@@ -36,12 +38,15 @@ async fn test_all() {
     assert_eq!(stats.winner.battles_won, 1);
     assert_eq!(stats.winner.win_streak_current, 1);
     assert_eq!(stats.winner.win_streak_max, 1);
-    assert_eq!(stats.winner.acquired_length, bet as u32);
+    assert_eq!(stats.winner.acquired_length, bet.value() as u32);
     assert_eq!(stats.winner.lost_length, 0);
     assert_eq!(stats.winner.win_rate_percentage(), 100.0);
     assert_eq!(stats.winner.win_rate_formatted(), "100.00%");
     assert_eq!(stats.loser.win_rate_percentage, 0.00);
     assert_eq!(stats.loser.prev_win_streak, 0);
+    assert_eq!(stats.rating_change, 16);
+    assert_eq!(stats.winner.rating, INITIAL_RATING + 16);
+    assert_eq!(stats.loser.rating, INITIAL_RATING - 16);
 
     // send the second battle to check updates
     let stats = pvp_stats.send_battle_result(&chat_id, uid_2, uid_1, bet).await
@@ -50,12 +55,16 @@ async fn test_all() {
     assert_eq!(stats.winner.battles_won, 1);
     assert_eq!(stats.winner.win_streak_current, 1);
     assert_eq!(stats.winner.win_streak_max, 1);
-    assert_eq!(stats.winner.acquired_length, bet as u32);
-    assert_eq!(stats.winner.lost_length, bet as u32);
+    assert_eq!(stats.winner.acquired_length, bet.value() as u32);
+    assert_eq!(stats.winner.lost_length, bet.value() as u32);
     assert_eq!(stats.winner.win_rate_percentage(), 50.0);
     assert_eq!(stats.winner.win_rate_formatted(), "50.00%");
     assert_eq!(stats.loser.win_rate_percentage, 50.0);
     assert_eq!(stats.loser.prev_win_streak, 1);
+    // the underdog takes more points
+    assert_eq!(stats.rating_change, 17);
+    assert_eq!(stats.winner.rating, INITIAL_RATING + 1);
+    assert_eq!(stats.loser.rating, INITIAL_RATING - 1);
 
     // send the third battle to test the getter again and check percentage rounding
     pvp_stats.send_battle_result(&chat_id, uid_2, uid_1, bet).await
@@ -65,6 +74,15 @@ async fn test_all() {
     assert_eq!(stats.battles_total, 3);
     assert_eq!(stats.battles_won, 1);
     assert_eq!(stats.win_rate_formatted(), "33.33%");
-    assert_eq!(stats.acquired_length, bet as u32);
-    assert_eq!(stats.lost_length, bet as u32 * 2);
+    assert_eq!(stats.acquired_length, bet.value() as u32);
+    assert_eq!(stats.lost_length, bet.value() as u32 * 2);
+    assert_eq!(stats.rating, INITIAL_RATING - 17);
+
+    let top = pvp_stats.get_rating_top(&chat_id, 0, 10).await
+        .expect("couldn't fetch the rating top");
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0].owner_name, "User-2");
+    assert_eq!(top[0].rating, INITIAL_RATING + 17);
+    assert_eq!(top[0].position, Some(1));
+    assert_eq!(top[1].rating, INITIAL_RATING - 17);
 }
@@ -0,0 +1,77 @@
+use crate::config::{ShopCurrency, ShopItem, ShopItemEffect};
+use crate::repo;
+use crate::repo::{ChatIdPartiality, PurchaseError, UseError};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let shop = repo::Shop::new(db.clone(), Default::default());
+    let inventory = repo::Inventory::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let ointment = ShopItem {
+        code: "ointment".to_owned(),
+        currency: ShopCurrency::Coins,
+        price: 5,
+        effect: ShopItemEffect::Shrink(15),
+    };
+    let suppository = ShopItem {
+        code: "suppository".to_owned(),
+        currency: ShopCurrency::Cm,
+        price: 10,
+        effect: ShopItemEffect::Attempts(1),
+    };
+
+    let initial_level = hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid")
+        .new_protrusion_level;
+
+    let res = shop.buy(&CHAT_ID_KIND, USER_ID, &ointment).await;
+    assert!(matches!(res, Err(PurchaseError::NotEnoughCoins)));
+
+    let coins = shop.award_coins(&CHAT_ID_KIND, USER_ID, 3)
+        .await.expect("couldn't award coins");
+    assert_eq!(coins, 3);
+    let coins = shop.award_coins(&CHAT_ID_KIND, USER_ID, 3)
+        .await.expect("couldn't award coins");
+    assert_eq!(coins, 6);
+
+    let res = shop.buy(&CHAT_ID_KIND, USER_ID, &ointment)
+        .await.expect("couldn't buy an ointment");
+    assert_eq!(res.coins_left, 1);
+    assert_eq!(res.quantity, 1);
+    assert_eq!(res.new_protrusion_level, initial_level);
+
+    let res = inventory.use_item(&CHAT_ID_KIND, USER_ID, &ointment)
+        .await.expect("couldn't use the ointment");
+    assert_eq!(res.quantity_left, 0);
+    assert_eq!(res.new_protrusion_level, Some(initial_level - 15));
+
+    let res = inventory.use_item(&CHAT_ID_KIND, USER_ID, &ointment).await;
+    assert!(matches!(res, Err(UseError::NoItem)));
+
+    let res = shop.buy(&CHAT_ID_KIND, USER_ID, &suppository)
+        .await.expect("couldn't buy a suppository");
+    assert_eq!(res.coins_left, 0);
+    assert_eq!(res.new_protrusion_level, initial_level - 5);
+
+    let items = inventory.get_items(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the inventory");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].item, "suppository");
+
+    let res = inventory.use_item(&CHAT_ID_KIND, USER_ID, &suppository)
+        .await.expect("couldn't use the suppository");
+    assert_eq!(res.new_protrusion_level, None);
+
+    // the bought attempt allows to get treatment once more today
+    hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into())
+        .await.expect("couldn't use the bought attempt");
+    let coins = shop.get_coins(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the coins");
+    assert_eq!(coins, 1);
+}
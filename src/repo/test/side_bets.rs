@@ -0,0 +1,86 @@
+use teloxide::types::UserId;
+use crate::repo;
+use crate::domain::Tenths;
+use crate::repo::{BattleBet, BetSide, ChatIdPartiality, SideBetError};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::penetrate::new_battle;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID, USER_ID};
+
+const CHALLENGE: &str = "1:42";
+
+#[tokio::test]
+async fn test_settlement() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let side_bets = repo::SideBets::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let [opponent, first, second, third] = [1, 2, 3, 4].map(|n| UserId(UID as u64 + n));
+    for (n, uid) in [opponent, first, second, third].into_iter().enumerate() {
+        users.create_or_update(uid, &format!("User {n}"))
+            .await.expect("couldn't create a user");
+    }
+    for uid in [USER_ID, opponent, first, second, third] {
+        hemoroids.create_or_shrink(uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+    sqlx::query!("UPDATE Hemoroids SET protrusion_level = 20, bonus_attempts = bonus_attempts + 1")
+        .execute(&db)
+        .await.expect("couldn't reset the levels");
+    // the spectators have been treated today and have no bonus attempts left, so the payouts mustn't be blocked by the trigger
+    let treated_today = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM Hemoroids
+            WHERE uid = ANY($1) AND bonus_attempts = 0
+                AND chat_local_date(chat_id, updated_at) = chat_local_date(chat_id, current_timestamp)"#,
+            &[first, second, third].map(|uid| uid.0 as i64)[..])
+        .fetch_one(&db)
+        .await.expect("couldn't check the spectators");
+    assert_eq!(treated_today, 3);
+
+    let bets = [
+        (first, BetSide::Initiator, 10),
+        (second, BetSide::Initiator, 30),
+        (third, BetSide::Acceptor, 20),
+        // the acceptor is allowed to bet before accepting, but gets the bet back
+        (opponent, BetSide::Acceptor, 10),
+    ];
+    for (uid, side, amount) in bets {
+        side_bets.place(&CHAT_ID_KIND, CHALLENGE, uid, side, Tenths::from(amount))
+            .await.expect("couldn't place a side bet");
+    }
+    let repeated = side_bets.place(&CHAT_ID_KIND, CHALLENGE, first, BetSide::Acceptor, Tenths::from(10)).await;
+    assert!(matches!(repeated, Err(SideBetError::AlreadyPlaced)));
+    let pools = side_bets.place(&CHAT_ID_KIND, "1:43", first, BetSide::Acceptor, Tenths::from(10))
+        .await.expect("couldn't place a side bet on another challenge");
+    assert_eq!(pools.initiator, 0);
+    assert_eq!(pools.acceptor, 10);
+
+    let bet = BattleBet {
+        initiator: USER_ID,
+        acceptor: opponent,
+        winner: USER_ID,
+        amount: Tenths::from_cm(3),
+        check_acceptor: false,
+    };
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 0, 0), bet, &[], Some(CHALLENGE), None)
+        .await.expect("couldn't penetrate");
+    let payouts = result.side_bets.iter()
+        .map(|side_bet| (side_bet.uid, side_bet.payout))
+        .collect::<Vec<_>>();
+    for expected in [(first, 5), (second, 15), (third, -20), (opponent, 0)] {
+        assert!(payouts.contains(&expected), "{expected:?} is not in {payouts:?}");
+    }
+    for (uid, level) in [(first, 15), (second, 5), (third, 40), (opponent, 20 + 20)] {
+        let actual = hemoroids.fetch_hemoroid(uid, &CHAT_ID_KIND)
+            .await.expect("couldn't fetch the hemorrhoid")
+            .expect("the hemorrhoid must exist")
+            .protrusion_level;
+        assert_eq!(actual, level, "the level of {uid}");
+    }
+
+    // the bets are settled only once
+    let result = hemoroids.penetrate(&chat_id, &new_battle(USER_ID, opponent, 0, 0), bet, &[], Some(CHALLENGE), None)
+        .await.expect("couldn't penetrate again");
+    assert!(result.side_bets.is_empty());
+}
@@ -0,0 +1,101 @@
+use sqlx::{Pool, Postgres};
+use crate::repo;
+use crate::repo::ChatIdPartiality;
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+
+    let streak = hemoroids.fetch_streak(USER_ID, &CHAT_ID_KIND)
+        .await.expect("couldn't fetch a streak");
+    assert!(streak.is_none());
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+    check_streak(&hemoroids, 1, 1, true).await;
+
+    move_last_treatment_back(&db, 1).await;
+    check_streak(&hemoroids, 1, 1, false).await;
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into())
+        .await.expect("couldn't shrink the hemorrhoid");
+    check_streak(&hemoroids, 2, 2, true).await;
+
+    // a missed day resets the current streak but not the best one
+    move_last_treatment_back(&db, 2).await;
+    check_streak(&hemoroids, 0, 2, false).await;
+
+    let top = hemoroids.get_streaks_top(&CHAT_ID_KIND, 0, 10)
+        .await.expect("couldn't fetch the top of streaks");
+    assert_eq!(top.len(), 1);
+    assert_eq!(top[0].max, 2);
+    assert_eq!(top[0].current, 0);
+    assert_eq!(top[0].position, Some(1));
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into())
+        .await.expect("couldn't shrink the hemorrhoid");
+    check_streak(&hemoroids, 1, 2, true).await;
+}
+
+#[tokio::test]
+async fn test_bonus_attempts() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create a hemorrhoid");
+    move_last_treatment_back(&db, 1).await;
+
+    // an item with an additional attempt is used before the daily treatment
+    sqlx::query!("UPDATE Hemoroids SET bonus_attempts = bonus_attempts + 1 + 1")
+        .execute(&db)
+        .await.expect("couldn't add a bonus attempt");
+    check_bonus_attempts(&db, 1).await;
+
+    // the daily attempt is spent first and continues the streak
+    hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into())
+        .await.expect("couldn't shrink the hemorrhoid");
+    check_streak(&hemoroids, 2, 2, true).await;
+    check_bonus_attempts(&db, 1).await;
+
+    hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into())
+        .await.expect("couldn't shrink the hemorrhoid with the bonus attempt");
+    check_streak(&hemoroids, 2, 2, true).await;
+    check_bonus_attempts(&db, 0).await;
+
+    let res = hemoroids.create_or_shrink(USER_ID, &chat_id, (-1).into()).await;
+    assert!(res.is_err());
+}
+
+async fn check_streak(hemoroids: &repo::Hemoroids, current: i32, max: i32, treated_today: bool) {
+    let streak = hemoroids.fetch_streak(USER_ID, &CHAT_ID_KIND)
+        .await.expect("couldn't fetch a streak")
+        .expect("the streak must exist");
+    assert_eq!(streak.current, current);
+    assert_eq!(streak.max, max);
+    assert_eq!(streak.treated_today, treated_today);
+}
+
+async fn move_last_treatment_back(db: &Pool<Postgres>, days: i32) {
+    // a bonus attempt lets the update pass through the trigger without affecting the streak
+    sqlx::query!("UPDATE Hemoroids SET updated_at = updated_at - make_interval(days => $1), bonus_attempts = bonus_attempts + 1",
+            days)
+        .execute(db)
+        .await.expect("couldn't move the last treatment back");
+}
+
+async fn check_bonus_attempts(db: &Pool<Postgres>, expected: i32) {
+    let bonus_attempts = sqlx::query_scalar!("SELECT bonus_attempts FROM Hemoroids")
+        .fetch_one(db)
+        .await.expect("couldn't fetch the bonus attempts");
+    assert_eq!(bonus_attempts, expected);
+}
@@ -0,0 +1,68 @@
+use teloxide::types::UserId;
+use crate::repo;
+use crate::repo::{ChatIdPartiality, Team, TeamJoinError};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let users = repo::Users::new(db.clone());
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let team_battles = repo::TeamBattles::new(db.clone(), Default::default());
+    let pvp_stats = repo::BattleStatsRepo::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    let others = (1..=3).map(|n| UserId(UID as u64 + n)).collect::<Vec<UserId>>();
+    for (n, uid) in others.iter().enumerate() {
+        users.create_or_update(*uid, &format!("Member {n}"))
+            .await.expect("couldn't create a member");
+    }
+    for uid in std::iter::once(&USER_ID).chain(&others) {
+        hemoroids.create_or_shrink(*uid, &chat_id, 0.into())
+            .await.expect("couldn't create a hemorrhoid");
+    }
+
+    let lobby = team_battles.create(&CHAT_ID_KIND, USER_ID, 2, 5)
+        .await.expect("couldn't create a team battle");
+    assert_eq!(lobby.team(Team::A).count(), 1);
+    assert!(!lobby.started);
+
+    let result = team_battles.join(lobby.id, USER_ID, Team::B).await;
+    assert!(matches!(result, Err(TeamJoinError::AlreadyJoined)));
+    team_battles.join(lobby.id, others[0], Team::A)
+        .await.expect("couldn't join the team A");
+    let result = team_battles.join(lobby.id, others[1], Team::A).await;
+    assert!(matches!(result, Err(TeamJoinError::TeamFull)));
+    let lobby = team_battles.join(lobby.id, others[1], Team::B)
+        .await.expect("couldn't join the team B");
+    assert!(!lobby.started);
+    let lobby = team_battles.join(lobby.id, others[2], Team::B)
+        .await.expect("couldn't join the team B");
+    assert!(lobby.started);
+    assert_eq!(lobby.members.len(), 4);
+
+    // the battle is fought only once
+    let result = team_battles.join(lobby.id, UserId(UID as u64 + 10), Team::B).await;
+    assert!(matches!(result, Err(TeamJoinError::Started)));
+
+    let damages = lobby.members.iter()
+        .map(|m| (m.uid, if m.team == Team::A { 3 } else { -2 }))
+        .collect::<Vec<(UserId, i32)>>();
+    let levels = hemoroids.penetrate_teams(&chat_id, &damages)
+        .await.expect("couldn't apply the damages");
+    assert_eq!(levels.len(), 4);
+
+    let (winners, losers): (Vec<_>, Vec<_>) = lobby.members.iter().partition(|m| m.team == Team::B);
+    let uids = |members: Vec<&repo::TeamMember>| members.into_iter().map(|m| m.uid).collect::<Vec<UserId>>();
+    pvp_stats.send_team_battle_result(&CHAT_ID_KIND, &uids(winners), &uids(losers))
+        .await.expect("couldn't send the team battle result");
+    let stats = pvp_stats.get_stats(&CHAT_ID_KIND, USER_ID)
+        .await.expect("couldn't get the stats");
+    assert_eq!(stats.team_battles_total, 1);
+    assert_eq!(stats.team_battles_won, 0);
+    assert_eq!(stats.battles_total, 0);
+    let stats = pvp_stats.get_stats(&CHAT_ID_KIND, others[2])
+        .await.expect("couldn't get the stats");
+    assert_eq!(stats.team_battles_won, 1);
+}
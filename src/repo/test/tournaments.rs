@@ -0,0 +1,82 @@
+use teloxide::types::{ChatId, MessageId, UserId};
+use crate::domain::LanguageCode;
+use crate::repo;
+use crate::repo::{ChatIdPartiality, CreateTournamentError, JoinTournamentError, NewTournament, TournamentMatch, TournamentStatus};
+use crate::repo::test::dicks::create_user;
+use crate::repo::test::{start_postgres, CHAT_ID, CHAT_ID_KIND, UID, USER_ID};
+
+#[tokio::test]
+async fn test_all() {
+    let (_container, db) = start_postgres().await;
+    create_user(&db).await;
+    let opponent = UserId(UID as u64 + 1);
+    repo::Users::new(db.clone()).create_or_update(opponent, "Opponent")
+        .await.expect("couldn't create the opponent");
+
+    let hemoroids = repo::Hemoroids::new(db.clone(), Default::default());
+    let tournaments = repo::Tournaments::new(db.clone(), Default::default());
+    let chat_id: ChatIdPartiality = CHAT_ID_KIND.into();
+    hemoroids.create_or_shrink(USER_ID, &chat_id, 0.into())
+        .await.expect("couldn't create the hemorrhoid of the user");
+    let level = hemoroids.create_or_shrink(opponent, &chat_id, 0.into())
+        .await.expect("couldn't create the hemorrhoid of the opponent")
+        .new_protrusion_level;
+
+    let params = NewTournament {
+        lang_code: LanguageCode::new("en".to_owned()),
+        bet: 5,
+        prize: 30,
+        registration_minutes: 10,
+    };
+    let tournament = tournaments.create(&chat_id, ChatId(CHAT_ID), &params)
+        .await.expect("couldn't create a tournament");
+    assert_eq!(tournament.status, TournamentStatus::Registration);
+    let result = tournaments.create(&chat_id, ChatId(CHAT_ID), &params).await;
+    assert!(matches!(result, Err(CreateTournamentError::AlreadyExists)));
+    tournaments.set_message(tournament.id, MessageId(1))
+        .await.expect("couldn't set the message");
+
+    for uid in [USER_ID, opponent] {
+        tournaments.join(tournament.id, uid)
+            .await.expect("couldn't join the tournament");
+    }
+    let result = tournaments.join(tournament.id, opponent).await;
+    assert!(matches!(result, Err(JoinTournamentError::AlreadyJoined)));
+    let due = tournaments.get_due()
+        .await.expect("couldn't get the due tournaments");
+    assert!(due.is_empty());
+
+    sqlx::query!("UPDATE Tournaments SET registration_ends_at = current_timestamp")
+        .execute(&db)
+        .await.expect("couldn't close the registration");
+    let result = tournaments.join(tournament.id, opponent).await;
+    assert!(matches!(result, Err(JoinTournamentError::Closed)));
+    let due = tournaments.get_due()
+        .await.expect("couldn't get the due tournaments");
+    assert_eq!(due.len(), 1);
+
+    let players = tournaments.get_players(tournament.id)
+        .await.expect("couldn't get the players");
+    assert_eq!(players.len(), 2);
+
+    let final_match = TournamentMatch { round: 1, position: 0, player1: USER_ID, player2: Some(opponent), winner: None };
+    tournaments.start_round(tournament.id, 1, &[final_match])
+        .await.expect("couldn't start the first round");
+    tournaments.set_winner(tournament.id, 1, 0, opponent)
+        .await.expect("couldn't set the winner");
+    let matches = tournaments.get_matches(tournament.id)
+        .await.expect("couldn't get the matches");
+    assert_eq!(matches[0].winner, Some(opponent));
+
+    let new_level = tournaments.finish(tournament.id, opponent)
+        .await.expect("couldn't finish the tournament");
+    assert_eq!(new_level, Some(level - 30));
+    let tournament = tournaments.get(tournament.id)
+        .await.expect("couldn't get the tournament")
+        .expect("the tournament must exist");
+    assert_eq!(tournament.status, TournamentStatus::Finished);
+
+    // another tournament may be opened after the previous one is finished
+    tournaments.create(&chat_id, ChatId(CHAT_ID), &params)
+        .await.expect("couldn't create the next tournament");
+}
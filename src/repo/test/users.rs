@@ -34,6 +34,26 @@ async fn create_or_update() {
     check_member_with_name(&members, NEW_NAME);
 }
 
+#[tokio::test]
+async fn find_by_username() {
+    let (_container, db) = start_postgres().await;
+    let users = repo::Users::new(db.clone());
+    let uid = UserId(UID as u64);
+
+    users.create_or_update(uid, NAME).await
+        .expect("creation failed");
+    let u = users.find_by_username("@Tester").await
+        .expect("couldn't find a user without a username");
+    assert!(u.is_none());
+
+    users.update_username(uid, Some("tester")).await
+        .expect("couldn't update the username");
+    let u = users.find_by_username("@Tester").await
+        .expect("couldn't find a user by the username")
+        .expect("the user must be found");
+    check_user_with_name(&u, NAME);
+}
+
 #[tokio::test]
 async fn get_chat_members() {
     let (_container, db) = start_postgres().await;
@@ -0,0 +1,305 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use teloxide::types::{ChatId, MessageId, UserId};
+use crate::domain::{LanguageCode, Username};
+use crate::repo::ChatIdPartiality;
+use crate::repo::history::{record_change, ChangeSource};
+use crate::repository;
+
+#[derive(sqlx::Type, Debug, Copy, Clone, PartialEq)]
+#[sqlx(type_name = "tournament_status", rename_all = "lowercase")]
+pub enum TournamentStatus {
+    Registration,
+    Running,
+    Finished,
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    pub id: i32,
+    pub chat_id: ChatId,
+    pub message_id: Option<MessageId>,
+    pub lang_code: LanguageCode,
+    pub bet: u16,
+    /// In tenths of a centimetre.
+    pub prize: i32,
+    pub status: TournamentStatus,
+    pub round: i16,
+    pub registration_ends_at: DateTime<Utc>,
+}
+
+struct TournamentEntity {
+    id: i32,
+    tg_chat_id: i64,
+    message_id: Option<i32>,
+    language: String,
+    bet: i16,
+    prize: i32,
+    status: TournamentStatus,
+    round: i16,
+    registration_ends_at: DateTime<Utc>,
+}
+
+impl From<TournamentEntity> for Tournament {
+    fn from(value: TournamentEntity) -> Self {
+        Self {
+            id: value.id,
+            chat_id: ChatId(value.tg_chat_id),
+            message_id: value.message_id.map(MessageId),
+            lang_code: LanguageCode::new(value.language),
+            bet: value.bet as u16,
+            prize: value.prize,
+            status: value.status,
+            round: value.round,
+            registration_ends_at: value.registration_ends_at,
+        }
+    }
+}
+
+pub struct NewTournament {
+    pub lang_code: LanguageCode,
+    pub bet: u16,
+    pub prize: i32,
+    pub registration_minutes: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentPlayer {
+    pub uid: UserId,
+    pub name: Username,
+    pub protrusion_level: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentMatch {
+    pub round: i16,
+    pub position: i16,
+    pub player1: UserId,
+    /// `None` means a bye.
+    pub player2: Option<UserId>,
+    pub winner: Option<UserId>,
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum CreateTournamentError {
+    AlreadyExists,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for CreateTournamentError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+#[derive(Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum JoinTournamentError {
+    Closed,
+    AlreadyJoined,
+    Other(anyhow::Error)
+}
+
+impl <T: Into<anyhow::Error>> From<T> for JoinTournamentError {
+    fn from(value: T) -> Self {
+        Self::Other(anyhow!(value))
+    }
+}
+
+repository!(Tournaments, with_(chats)_(Chats),
+    /// Only one tournament may be either open for registration or running in a chat.
+    pub async fn create(&self, chat_id: &ChatIdPartiality, tg_chat_id: ChatId, params: &NewTournament) -> Result<Tournament, CreateTournamentError> {
+        let chat_internal_id = self.chats.upsert_chat(chat_id).await?;
+        sqlx::query_as!(TournamentEntity,
+            r#"INSERT INTO Tournaments (chat_id, tg_chat_id, language, bet, prize, registration_ends_at)
+                VALUES ($1, $2, $3, $4, $5, current_timestamp + make_interval(mins => $6))
+                ON CONFLICT DO NOTHING
+                RETURNING id, tg_chat_id, message_id, language, bet, prize, status AS "status: TournamentStatus", round, registration_ends_at"#,
+                chat_internal_id, tg_chat_id.0, params.lang_code.as_str(), params.bet as i16, params.prize, params.registration_minutes as i32)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't create a tournament in {chat_id}"))?
+            .map(Tournament::from)
+            .ok_or(CreateTournamentError::AlreadyExists)
+    }
+,
+    pub async fn set_message(&self, tournament_id: i32, message_id: MessageId) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE Tournaments SET message_id = $2 WHERE id = $1",
+                tournament_id, message_id.0)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't set the message of the tournament {tournament_id}"))?;
+        Ok(())
+    }
+,
+    pub async fn get(&self, tournament_id: i32) -> anyhow::Result<Option<Tournament>> {
+        sqlx::query_as!(TournamentEntity,
+            r#"SELECT id, tg_chat_id, message_id, language, bet, prize, status AS "status: TournamentStatus", round, registration_ends_at
+                FROM Tournaments WHERE id = $1"#,
+                tournament_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|t| t.map(Tournament::from))
+            .context(format!("couldn't get the tournament {tournament_id}"))
+    }
+,
+    /// Returns the tournaments whose registration has been closed but who haven't been started yet, and the running ones.
+    pub async fn get_due(&self) -> anyhow::Result<Vec<Tournament>> {
+        sqlx::query_as!(TournamentEntity,
+            r#"SELECT id, tg_chat_id, message_id, language, bet, prize, status AS "status: TournamentStatus", round, registration_ends_at
+                FROM Tournaments
+                WHERE message_id IS NOT NULL
+                    AND (status = 'running' OR status = 'registration' AND registration_ends_at <= current_timestamp)
+                ORDER BY id"#)
+            .fetch_all(&self.pool)
+            .await
+            .map(|ts| ts.into_iter().map(Tournament::from).collect())
+            .context("couldn't get the due tournaments")
+    }
+,
+    pub async fn join(&self, tournament_id: i32, user_id: UserId) -> Result<(), JoinTournamentError> {
+        let uid = user_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let open = sqlx::query_scalar!(
+            r#"SELECT status = 'registration' AND registration_ends_at > current_timestamp AS "open!" FROM Tournaments WHERE id = $1 FOR UPDATE"#,
+                tournament_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't check the registration of the tournament {tournament_id}"))?
+            .unwrap_or(false);
+        if !open {
+            return Err(JoinTournamentError::Closed)
+        }
+        let inserted = sqlx::query!("INSERT INTO Tournament_Players (tournament_id, uid) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                tournament_id, uid)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't add {user_id} to the tournament {tournament_id}"))?
+            .rows_affected();
+        if inserted == 0 {
+            return Err(JoinTournamentError::AlreadyJoined)
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+,
+    /// The players are ordered by their seeds: the less the protrusion level, the higher the seed.
+    pub async fn get_players(&self, tournament_id: i32) -> anyhow::Result<Vec<TournamentPlayer>> {
+        let players = sqlx::query!(
+            r#"SELECT p.uid, u.name, coalesce(h.protrusion_level, 0) AS "protrusion_level!" FROM Tournament_Players p
+                JOIN Tournaments t ON t.id = p.tournament_id
+                JOIN Users u ON u.uid = p.uid
+                LEFT JOIN Hemoroids h ON h.uid = p.uid AND h.chat_id = t.chat_id
+                WHERE p.tournament_id = $1
+                ORDER BY 3, p.joined_at"#,
+                tournament_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the players of the tournament {tournament_id}"))?
+            .into_iter()
+            .map(|row| TournamentPlayer {
+                uid: UserId(row.uid as u64),
+                name: Username::new(row.name),
+                protrusion_level: row.protrusion_level,
+            })
+            .collect();
+        Ok(players)
+    }
+,
+    pub async fn get_matches(&self, tournament_id: i32) -> anyhow::Result<Vec<TournamentMatch>> {
+        let matches = sqlx::query!(
+            "SELECT round, position, player1_uid, player2_uid, winner_uid FROM Tournament_Matches
+                WHERE tournament_id = $1
+                ORDER BY round, position",
+                tournament_id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("couldn't get the matches of the tournament {tournament_id}"))?
+            .into_iter()
+            .map(|row| TournamentMatch {
+                round: row.round,
+                position: row.position,
+                player1: UserId(row.player1_uid as u64),
+                player2: row.player2_uid.map(|uid| UserId(uid as u64)),
+                winner: row.winner_uid.map(|uid| UserId(uid as u64)),
+            })
+            .collect();
+        Ok(matches)
+    }
+,
+    pub async fn cancel(&self, tournament_id: i32) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE Tournaments SET status = 'cancelled' WHERE id = $1",
+                tournament_id)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't cancel the tournament {tournament_id}"))?;
+        Ok(())
+    }
+,
+    /// Saves the matches of the next round and makes it the current one.
+    pub async fn start_round(&self, tournament_id: i32, round: i16, matches: &[TournamentMatch]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("UPDATE Tournaments SET status = 'running', round = $2 WHERE id = $1",
+                tournament_id, round)
+            .execute(&mut *tx)
+            .await
+            .context(format!("couldn't start the round {round} of the tournament {tournament_id}"))?;
+        for m in matches {
+            Self::insert_match(&mut tx, tournament_id, m).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+,
+    pub async fn set_winner(&self, tournament_id: i32, round: i16, position: i16, winner: UserId) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE Tournament_Matches SET winner_uid = $4 WHERE tournament_id = $1 AND round = $2 AND position = $3",
+                tournament_id, round, position, winner.0 as i64)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't set the winner of the match {round}:{position} of the tournament {tournament_id}"))?;
+        Ok(())
+    }
+,
+    /// Finishes the tournament and awards the prize to the winner.
+    /// Returns the new protrusion level of the winner if they still have a hemorrhoid in the chat.
+    pub async fn finish(&self, tournament_id: i32, winner: UserId) -> anyhow::Result<Option<i32>> {
+        let uid = winner.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let Some(t) = sqlx::query!("UPDATE Tournaments SET status = 'finished', winner_uid = $2 WHERE id = $1 AND status = 'running'
+                RETURNING chat_id, prize",
+                tournament_id, uid)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't finish the tournament {tournament_id}"))? else {
+            return Err(anyhow!("the tournament {tournament_id} is not running"))
+        };
+
+        // a bonus attempt lets the update pass through the trigger without being counted as a treatment
+        let level = sqlx::query_scalar!("UPDATE Hemoroids SET protrusion_level = protrusion_level - $3, bonus_attempts = bonus_attempts + 1
+                WHERE chat_id = $1 AND uid = $2
+                RETURNING protrusion_level",
+                t.chat_id, uid, t.prize)
+            .fetch_optional(&mut *tx)
+            .await
+            .context(format!("couldn't award the prize of the tournament {tournament_id} to {winner}"))?;
+        if level.is_some() {
+            record_change(&mut tx, t.chat_id, uid, &(-t.prize).into(), ChangeSource::Tournament, Some(tournament_id.to_string())).await?;
+        }
+        tx.commit().await?;
+        Ok(level)
+    }
+,
+    async fn insert_match(tx: &mut Transaction<'_, Postgres>, tournament_id: i32, m: &TournamentMatch) -> anyhow::Result<()> {
+        sqlx::query!("INSERT INTO Tournament_Matches (tournament_id, round, position, player1_uid, player2_uid, winner_uid)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                tournament_id, m.round, m.position, m.player1.0 as i64,
+                m.player2.map(|uid| uid.0 as i64), m.winner.map(|uid| uid.0 as i64))
+            .execute(&mut **tx)
+            .await
+            .context(format!("couldn't save the match {m:?} of the tournament {tournament_id}"))?;
+        Ok(())
+    }
+);
@@ -102,6 +102,25 @@ repository!(Users,
             .await
             .context(format!("couldn't get a random active user of the chat with id = {chat_id}"))
     }
+,
+    /// Usernames are kept only to resolve @mentions, so they are updated separately from names.
+    pub async fn update_username(&self, user_id: UserId, username: Option<&str>) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE Users SET username = $2 WHERE uid = $1 AND username IS DISTINCT FROM $2",
+                user_id.0 as i64, username)
+            .execute(&self.pool)
+            .await
+            .context(format!("couldn't update the username of the user with id = {user_id}"))?;
+        Ok(())
+    }
+,
+    pub async fn find_by_username(&self, username: &str) -> anyhow::Result<Option<User>> {
+        let username = username.trim_start_matches('@');
+        sqlx::query_as!(User, "SELECT uid, name, created_at FROM Users WHERE lower(username) = lower($1)",
+                username)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("couldn't find a user by the username '{username}'"))
+    }
 ,
     pub async fn get(&self, user_id: UserId) -> anyhow::Result<Option<User>> {
         sqlx::query_as!(User, "SELECT uid, name, created_at FROM Users WHERE uid = $1",
@@ -113,7 +132,7 @@ repository!(Users,
 ,
     #[cfg(test)]
     pub async fn get_all(&self) -> anyhow::Result<Vec<User>> {
-        sqlx::query_as!(User, "SELECT * FROM Users")
+        sqlx::query_as!(User, "SELECT uid, name, created_at FROM Users")
             .fetch_all(&self.pool)
             .await
             .map_err(Into::into)